@@ -1,7 +1,10 @@
-use crate::layer::Layer;
 #[cfg(not(target_arch = "wasm32"))]
 use crate::window_dimensions::WindowDimensions;
-use crate::{Painter, painter::PainterConfig};
+use crate::{
+	Painter,
+	error::PainterError,
+	painter::{PainterConfig, SurfaceFormat, is_transparent_alpha_mode},
+};
 #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
 use notify::Watcher;
 #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
@@ -49,8 +52,18 @@ pub trait CanvasApp<UserEvent = (), DevState = ()> {
 	where
 		Self: Sized,
 	{
+		Self::try_create().unwrap_or_else(|err| panic!("{}", err))
+	}
+
+	/// Like `create`, but returns an error if the event loop can not be created,
+	/// e.g. because the host application already created one.
+	fn try_create() -> Result<CanvasAppStarter<UserEvent, Self, DevState>, PainterError>
+	where
+		Self: Sized,
+	{
+		// Ignore the error if the host application already initialized a logger
 		#[cfg(not(target_arch = "wasm32"))]
-		env_logger::init();
+		let _ = env_logger::try_init();
 
 		#[cfg(target_arch = "wasm32")]
 		{
@@ -58,9 +71,7 @@ pub trait CanvasApp<UserEvent = (), DevState = ()> {
 			let _ = console_log::init(); // Ignore error if already initialized
 		}
 
-		let event_loop = EventLoop::<CustomEvent<UserEvent>>::with_user_event()
-			.build()
-			.unwrap();
+		let event_loop = EventLoop::<CustomEvent<UserEvent>>::with_user_event().build()?;
 
 		let event_loop_proxy = event_loop.create_proxy();
 
@@ -74,9 +85,14 @@ pub trait CanvasApp<UserEvent = (), DevState = ()> {
 			now: Instant::now(),
 			config: AppConfig::default(),
 			last_cursor: None,
+			#[cfg(not(target_arch = "wasm32"))]
+			is_recording: false,
+			#[cfg(not(target_arch = "wasm32"))]
+			recorded_frames: 0,
+			init_error: None,
 		};
 
-		CanvasAppStarter { runner, event_loop }
+		Ok(CanvasAppStarter { runner, event_loop })
 	}
 }
 
@@ -89,8 +105,11 @@ enum WindowState<UserEvent, App: CanvasApp<UserEvent, DevState>, DevState> {
 
 pub enum CustomEvent<UserEvent> {
 	StateInitializationEvent(Painter),
+	InitializationError(PainterError),
 	UserEvent(UserEvent),
 	ReloadShaders(String),
+	#[cfg(not(target_arch = "wasm32"))]
+	SetRecording(bool),
 }
 
 pub struct CanvasAppRunner<UserEvent, App, DevState = ()>
@@ -107,6 +126,13 @@ where
 	now: Instant,
 	config: AppConfig,
 	last_cursor: Option<(f64, f64)>,
+	#[cfg(not(target_arch = "wasm32"))]
+	is_recording: bool,
+	/// Number of the next recorded frame, continued when recording is restarted.
+	#[cfg(not(target_arch = "wasm32"))]
+	recorded_frames: u32,
+	/// Error that stopped the initialization, returned by `CanvasAppStarter::try_start`.
+	init_error: Option<PainterError>,
 }
 
 impl<UserEvent, App, DevState> CanvasAppRunner<UserEvent, App, DevState>
@@ -125,6 +151,13 @@ where
 			painter.request_next_frame();
 		}
 	}
+
+	/// Stops the event loop after a failed initialization, see `CanvasAppStarter::try_start`.
+	fn exit_with_error(&mut self, event_loop: &ActiveEventLoop, err: PainterError) {
+		log::error!("{}", err);
+		self.init_error = Some(err);
+		event_loop.exit();
+	}
 }
 
 pub struct CanvasHandle<UserEvent>
@@ -142,6 +175,25 @@ impl<UserEvent> CanvasHandle<UserEvent> {
 		self.event_loop_proxy
 			.send_event(CustomEvent::UserEvent(event))
 	}
+
+	/// Starts recording frames, see [`RecordingConfig`].
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn start_recording(&self) -> Result<(), winit::event_loop::EventLoopClosed<()>> {
+		self.set_recording(true)
+	}
+
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn stop_recording(&self) -> Result<(), winit::event_loop::EventLoopClosed<()>> {
+		self.set_recording(false)
+	}
+
+	/// The returned error does not carry the event back, unlike `send_event`.
+	#[cfg(not(target_arch = "wasm32"))]
+	fn set_recording(&self, recording: bool) -> Result<(), winit::event_loop::EventLoopClosed<()>> {
+		self.event_loop_proxy
+			.send_event(CustomEvent::SetRecording(recording))
+			.map_err(|_| winit::event_loop::EventLoopClosed(()))
+	}
 }
 
 #[derive(Debug)]
@@ -150,10 +202,29 @@ pub struct AppConfig {
 	pub use_vsync: bool,
 	pub remember_window_dimensions: bool,
 	pub features: Option<wgpu::Features>,
+	/// Preferred surface format, e.g. sRGB, linear or HDR. See `SurfaceFormat`.
+	pub surface_format: SurfaceFormat,
+	/// Alpha mode of the surface. `PreMultiplied` or `PostMultiplied` make the window transparent:
+	/// where the shown layer is transparent, the content behind the window shows through.
+	/// With `PreMultiplied`, the shown layer has to contain premultiplied colors,
+	/// it is written to the window without blending.
+	/// Defaults to `Auto`, an opaque window.
+	pub alpha_mode: wgpu::CompositeAlphaMode,
 	#[cfg(target_arch = "wasm32")]
 	pub canvas: Option<web_sys::HtmlCanvasElement>,
 	pub dev_state_key: &'static str,
 	pub reload_dev_state: bool,
+	/// Enables recording the shown layer to a PNG sequence.
+	#[cfg(not(target_arch = "wasm32"))]
+	pub recording: Option<RecordingConfig>,
+	/// Persists compiled pipelines between runs, next to the dev state, to speed up startup.
+	/// Stored under the `dev_state_key`, or the executable name if it is empty.
+	/// Only supported on Vulkan, ignored on other backends. Defaults to `false`.
+	///
+	/// The cache file is written to the user config directory on every exit,
+	/// so it is opt-in, e.g. for apps with many or large shaders.
+	#[cfg(not(target_arch = "wasm32"))]
+	pub use_pipeline_cache: bool,
 }
 
 impl Default for AppConfig {
@@ -163,10 +234,51 @@ impl Default for AppConfig {
 			use_vsync: true,
 			remember_window_dimensions: false,
 			features: None,
+			surface_format: SurfaceFormat::Auto,
+			alpha_mode: wgpu::CompositeAlphaMode::Auto,
 			#[cfg(target_arch = "wasm32")]
 			canvas: None,
 			dev_state_key: "",
 			reload_dev_state: false,
+			#[cfg(not(target_arch = "wasm32"))]
+			recording: None,
+			#[cfg(not(target_arch = "wasm32"))]
+			use_pipeline_cache: false,
+		}
+	}
+}
+
+/// Renders frames with a fixed timestep and writes the layer shown in each frame
+/// to a numbered PNG sequence, e.g. to make videos without dropped frames.
+///
+/// Recording is started and stopped with the toggle key or
+/// `CanvasHandle::start_recording` and `CanvasHandle::stop_recording`.
+/// While recording, window sized layers are rendered at the recording size
+/// and `CanvasApp::resize` is called with it.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone)]
+pub struct RecordingConfig {
+	/// Directory the frames are written to, created if missing.
+	pub dir: std::path::PathBuf,
+	/// `CanvasApp::frame` is called with `1 / fps` as time per frame.
+	pub fps: f32,
+	pub width: u32,
+	pub height: u32,
+	pub toggle_key: Option<KeyCode>,
+	/// Starts recording right after initialization.
+	pub start_immediately: bool,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for RecordingConfig {
+	fn default() -> Self {
+		Self {
+			dir: "recording".into(),
+			fps: 60.0,
+			width: 1920,
+			height: 1080,
+			toggle_key: Some(KeyCode::F9),
+			start_immediately: false,
 		}
 	}
 }
@@ -192,6 +304,14 @@ where
 	}
 
 	pub fn start(self) {
+		self.try_start().unwrap_or_else(|err| panic!("{}", err));
+	}
+
+	/// Like `start`, but returns an error instead of panicking if the window, surface
+	/// or device can not be created, or the GPU pipelines of the initial layers fail.
+	///
+	/// On the web, initialization errors can not be returned and are logged instead.
+	pub fn try_start(self) -> Result<(), PainterError> {
 		let event_loop = self.event_loop;
 		let mut runner = self.runner;
 
@@ -199,18 +319,16 @@ where
 		let (tx, rx) = std::sync::mpsc::channel::<notify::Result<notify::Event>>();
 
 		#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
-		let mut watcher = notify::recommended_watcher(tx).unwrap();
+		let mut watcher = notify::recommended_watcher(tx)?;
 
 		#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
-		let path = std::env::current_dir().unwrap();
+		let path = std::env::current_dir().map_err(notify::Error::io)?;
 
 		#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
 		println!("Watching: {:?}", path);
 
 		#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
-		watcher
-			.watch(&path, notify::RecursiveMode::Recursive)
-			.unwrap();
+		watcher.watch(&path, notify::RecursiveMode::Recursive)?;
 
 		#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
 		let proxy = runner.event_loop_proxy.clone();
@@ -227,7 +345,7 @@ where
 
 							event.paths.iter().for_each(|path| {
 								if let Some(ext) = path.extension() {
-									if ext != "spv" {
+									if ext != "spv" && ext != "wgsl" {
 										return;
 									}
 
@@ -260,7 +378,12 @@ where
 			}
 		});
 
-		let _ = event_loop.run_app(&mut runner);
+		event_loop.run_app(&mut runner)?;
+
+		match runner.init_error.take() {
+			Some(err) => Err(err),
+			None => Ok(()),
+		}
 	}
 
 	pub fn get_handle(&self) -> CanvasHandle<UserEvent> {
@@ -294,6 +417,90 @@ where
 			}
 		}
 	}
+
+	/// Name of the persisted pipeline cache, see `AppConfig::use_pipeline_cache`.
+	#[cfg(not(target_arch = "wasm32"))]
+	fn pipeline_cache_key(&self) -> Option<String> {
+		if !self.config.use_pipeline_cache {
+			return None;
+		}
+		if !self.config.dev_state_key.is_empty() {
+			return Some(self.config.dev_state_key.to_string());
+		}
+		let exe = std::env::current_exe().ok()?;
+		Some(exe.file_stem()?.to_string_lossy().into_owned())
+	}
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<UserEvent, App, DevState> CanvasAppRunner<UserEvent, App, DevState>
+where
+	App: CanvasApp<UserEvent, DevState>,
+{
+	fn set_recording(&mut self, recording: bool) {
+		let WindowState::Initialized(painter, app) = &mut self.state else {
+			return;
+		};
+		let Some(config) = &self.config.recording else {
+			log::warn!("Recording is not configured, see AppConfig::recording");
+			return;
+		};
+		if self.is_recording == recording {
+			return;
+		}
+
+		if recording {
+			if let Err(e) = std::fs::create_dir_all(&config.dir) {
+				log::error!(
+					"Failed to create recording directory {:?}: {}",
+					config.dir,
+					e
+				);
+				return;
+			}
+			painter.set_render_size(Some(PhysicalSize::new(config.width, config.height)));
+			log::info!("Recording to {:?}", config.dir);
+		} else {
+			painter.set_render_size(None);
+			log::info!("Recording stopped after {} frames", self.recorded_frames);
+		}
+
+		self.is_recording = recording;
+		let size = painter.render_size();
+		app.resize(painter, size.width, size.height);
+		self.now = Instant::now();
+		painter.request_next_frame();
+	}
+
+	/// Writes the layer shown in the last frame to the recording directory.
+	/// Frames that did not show a layer are skipped.
+	fn record_frame(&mut self) {
+		let WindowState::Initialized(painter, _) = &self.state else {
+			return;
+		};
+		let Some(config) = &self.config.recording else {
+			return;
+		};
+		let Some(layer) = painter.shown_layer() else {
+			painter.request_next_frame();
+			return;
+		};
+
+		let path = config
+			.dir
+			.join(format!("frame_{:06}.png", self.recorded_frames));
+		match layer.save_png(painter, &path) {
+			Ok(()) => {
+				self.recorded_frames += 1;
+				// Keep rendering, also if the app does not request frames itself
+				painter.request_next_frame();
+			}
+			Err(e) => {
+				log::error!("Failed to write {:?}: {}", path, e);
+				self.set_recording(false);
+			}
+		}
+	}
 }
 
 impl<UserEvent, App, DevState> ApplicationHandler<CustomEvent<UserEvent>>
@@ -308,10 +515,11 @@ where
 			WindowState::Uninitialized => {
 				self.state = WindowState::Initializing;
 
+				let transparent = is_transparent_alpha_mode(self.config.alpha_mode);
 				#[cfg(not(target_arch = "wasm32"))]
-				let mut window_attributes = Window::default_attributes();
+				let mut window_attributes = Window::default_attributes().with_transparent(transparent);
 				#[cfg(target_arch = "wasm32")]
-				let window_attributes = Window::default_attributes();
+				let window_attributes = Window::default_attributes().with_transparent(transparent);
 
 				// Load and apply saved window state
 				#[cfg(not(target_arch = "wasm32"))]
@@ -332,12 +540,14 @@ where
 				let window = {
 					use winit::platform::web::WindowAttributesExtWebSys;
 
-					if let Some(canvas) = &self.config.canvas {
+					if let Some(canvas) = self.config.canvas.clone() {
 						// Use the provided canvas
-						let window = event_loop
+						let window = match event_loop
 							.create_window(window_attributes.with_canvas(Some(canvas.clone())))
-							.unwrap();
-						let window = Arc::new(window);
+						{
+							Ok(window) => Arc::new(window),
+							Err(err) => return self.exit_with_error(event_loop, err.into()),
+						};
 
 						// Set canvas attributes even for provided canvas
 						canvas
@@ -348,8 +558,10 @@ where
 						window
 					} else {
 						// Create a new canvas as before
-						let window = event_loop.create_window(window_attributes).unwrap();
-						let window = Arc::new(window);
+						let window = match event_loop.create_window(window_attributes) {
+							Ok(window) => Arc::new(window),
+							Err(err) => return self.exit_with_error(event_loop, err.into()),
+						};
 
 						use winit::platform::web::WindowExtWebSys;
 
@@ -376,16 +588,22 @@ where
 				};
 
 				#[cfg(not(target_arch = "wasm32"))]
-				let window = {
-					let window = event_loop.create_window(window_attributes).unwrap();
-					Arc::new(window)
+				let window = match event_loop.create_window(window_attributes) {
+					Ok(window) => Arc::new(window),
+					Err(err) => return self.exit_with_error(event_loop, err.into()),
 				};
 
-				let renderer_future = Painter::new(
+				let renderer_future = Painter::try_new(
 					window,
 					PainterConfig {
 						use_vsync: self.config.use_vsync,
 						features: self.config.features,
+						surface_format: self.config.surface_format,
+						alpha_mode: self.config.alpha_mode,
+						#[cfg(not(target_arch = "wasm32"))]
+						pipeline_cache_key: self.pipeline_cache_key(),
+						#[cfg(target_arch = "wasm32")]
+						pipeline_cache_key: None,
 					},
 				);
 
@@ -393,19 +611,23 @@ where
 				{
 					let event_loop_proxy = self.event_loop_proxy.clone();
 					spawn_local(async move {
-						let painter = renderer_future.await;
+						let event = match renderer_future.await {
+							Ok(painter) => CustomEvent::StateInitializationEvent(painter),
+							Err(err) => CustomEvent::InitializationError(err),
+						};
 
-						event_loop_proxy
-							.send_event(CustomEvent::StateInitializationEvent(painter))
-							.unwrap_or_else(|_| {
-								panic!("Failed to send initialization event");
-							});
+						event_loop_proxy.send_event(event).unwrap_or_else(|_| {
+							panic!("Failed to send initialization event");
+						});
 					});
 				}
 
 				#[cfg(not(target_arch = "wasm32"))]
 				{
-					let painter = pollster::block_on(renderer_future);
+					let painter = match pollster::block_on(renderer_future) {
+						Ok(painter) => painter,
+						Err(err) => return self.exit_with_error(event_loop, err),
+					};
 
 					self.event_loop_proxy
 						.send_event(CustomEvent::StateInitializationEvent(painter))
@@ -418,13 +640,13 @@ where
 		}
 	}
 
-	fn user_event(&mut self, _event_loop: &ActiveEventLoop, event: CustomEvent<UserEvent>) {
+	fn user_event(&mut self, event_loop: &ActiveEventLoop, event: CustomEvent<UserEvent>) {
 		match event {
 			CustomEvent::StateInitializationEvent(mut painter) => {
 				let mut app = App::init(&mut painter);
 
-				for i in 0..painter.layers.len() {
-					Layer(i).init_gpu_pipelines(&mut painter);
+				if let Err(err) = painter.try_warm_up_pipelines() {
+					return self.exit_with_error(event_loop, err);
 				}
 
 				let size = painter.canvas_size();
@@ -457,6 +679,19 @@ where
 
 				painter.request_next_frame();
 				self.state = WindowState::Initialized(painter, app);
+
+				#[cfg(not(target_arch = "wasm32"))]
+				if self
+					.config
+					.recording
+					.as_ref()
+					.is_some_and(|r| r.start_immediately)
+				{
+					self.set_recording(true);
+				}
+			}
+			CustomEvent::InitializationError(err) => {
+				self.exit_with_error(event_loop, err);
 			}
 			CustomEvent::UserEvent(user_event) => {
 				if let WindowState::Initialized(painter, app) = &mut self.state {
@@ -475,6 +710,10 @@ where
 					}
 				}
 			}
+			#[cfg(not(target_arch = "wasm32"))]
+			CustomEvent::SetRecording(recording) => {
+				self.set_recording(recording);
+			}
 		}
 	}
 
@@ -484,21 +723,28 @@ where
 		_window_id: WindowId,
 		event: WindowEvent,
 	) {
+		// Recording needs the whole runner, so it is handled after the event
+		#[cfg(not(target_arch = "wasm32"))]
+		let (mut record_frame, mut toggle_recording) = (false, false);
+
 		match &mut self.state {
 			WindowState::Initialized(painter, app) => {
 				match event {
 					WindowEvent::Resized(new_size) => {
 						// Reconfigure the surface with the new size
 						painter.resize(new_size);
-						app.resize(painter, new_size.width, new_size.height);
+						// Window sized layers keep the recording size while recording
+						let size = painter.render_size();
+						app.resize(painter, size.width, size.height);
 						// On macos the window needs to be redrawn manually after resizing
 						painter.request_next_frame();
 						self.is_resizing = true;
 
 						#[cfg(not(target_arch = "wasm32"))]
 						{
-							let window = painter.window();
-							if self.config.remember_window_dimensions {
+							if let Some(window) = painter.window()
+								&& self.config.remember_window_dimensions
+							{
 								let dim = WindowDimensions::from_window(
 									new_size,
 									window.outer_position().unwrap_or_default(),
@@ -510,8 +756,9 @@ where
 
 					#[cfg(not(target_arch = "wasm32"))]
 					WindowEvent::Moved(new_position) => {
-						let window = painter.window();
-						if self.config.remember_window_dimensions {
+						if let Some(window) = painter.window()
+							&& self.config.remember_window_dimensions
+						{
 							let dim =
 								WindowDimensions::from_window(window.inner_size(), new_position);
 							let _ = dim.save(self.config.dev_state_key);
@@ -525,6 +772,12 @@ where
 
 							let elapsed = if self.is_running { elapsed } else { 0.0 };
 
+							#[cfg(not(target_arch = "wasm32"))]
+							let elapsed = match &self.config.recording {
+								Some(r) if self.is_recording && self.is_running => 1.0 / r.fps,
+								_ => elapsed,
+							};
+
 							if self.config.show_fps && self.is_running {
 								self.frame_count += 1;
 								self.frame_time += elapsed;
@@ -536,6 +789,15 @@ where
 								}
 							}
 
+							#[cfg(not(target_arch = "wasm32"))]
+							{
+								record_frame = self.is_recording && self.is_running;
+								if record_frame {
+									// Only record layers shown in this frame
+									painter.shown_layer = None;
+								}
+							}
+
 							app.frame(painter, elapsed);
 
 							if let Some(err) = &painter.surface_error {
@@ -545,11 +807,8 @@ where
 											width: painter.config.width,
 											height: painter.config.height,
 										});
-										app.resize(
-											painter,
-											painter.config.width,
-											painter.config.height,
-										);
+										let size = painter.render_size();
+										app.resize(painter, size.width, size.height);
 										log::error!("Surface lost or outdated, resizing");
 									}
 									// The system is out of memory, we should probably quit
@@ -652,6 +911,13 @@ where
 								painter.request_next_frame();
 							}
 						}
+
+						#[cfg(not(target_arch = "wasm32"))]
+						if let Some(r) = &self.config.recording
+							&& r.toggle_key == Some(key)
+						{
+							toggle_recording = true;
+						}
 					}
 
 					WindowEvent::KeyboardInput {
@@ -676,6 +942,22 @@ where
 			}
 			_ => {}
 		}
+
+		#[cfg(not(target_arch = "wasm32"))]
+		{
+			if record_frame {
+				self.record_frame();
+			}
+			if toggle_recording {
+				self.set_recording(!self.is_recording);
+			}
+		}
+	}
+
+	fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+		if let WindowState::Initialized(painter, _) = &self.state {
+			painter.save_pipeline_cache();
+		}
 	}
 
 	fn device_event(
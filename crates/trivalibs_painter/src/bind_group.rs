@@ -1,9 +1,12 @@
 use crate::{
 	Painter,
-	binding::{BindingLayout, InstanceBinding, LayerBinding, LayerLayout, ValueBinding},
-	texture::TexViewKey,
+	binding::{BindingLayout, Buffer, InstanceBinding, LayerBinding, LayerLayout, ValueBinding},
+	layer::Layer,
+	registry::Handle,
+	texture::{TexViewKey, Texture},
+	texture_utils::is_float_filterable,
 };
-use std::collections::btree_map;
+use std::collections::{BTreeMap, btree_map};
 
 #[derive(Clone, Copy)]
 pub(crate) struct BindGroupLayout(pub(crate) usize);
@@ -25,8 +28,8 @@ impl BindGroupLayout {
 						visibility: l.visibility,
 						ty: wgpu::BindingType::Texture {
 							multisampled: false,
-							view_dimension: wgpu::TextureViewDimension::D2,
-							sample_type: wgpu::TextureSampleType::Float { filterable: true },
+							view_dimension: l.view_dimension,
+							sample_type: l.sample_type,
 						},
 						count: None,
 					})
@@ -139,64 +142,71 @@ impl ValuesBindGroupData {
 		}
 	}
 
+	/// Instances only differing in the elements of dynamic buffers share a GPU bind group.
 	pub(crate) fn to_gpu_bind_groups(&self, painter: &Painter) -> Vec<wgpu::BindGroup> {
+		let mut created: Vec<(Vec<ValueBinding>, wgpu::BindGroup)> = Vec::new();
+
 		self.data
 			.iter()
 			.map(|u| {
-				let entries = u
-					.iter()
-					.enumerate()
-					.map(|(i, u)| wgpu::BindGroupEntry {
-						binding: i as u32,
-						resource: value_to_resource(u, painter),
-					})
-					.collect::<Vec<_>>();
-
-				painter
-					.device
-					.create_bind_group(&wgpu::BindGroupDescriptor {
-						label: None,
-						layout: &painter.bind_group_layouts[self.layout.0],
-						entries: &entries,
-					})
+				let key = u.iter().map(|b| b.without_offset()).collect::<Vec<_>>();
+				if let Some((_, bind_group)) = created.iter().find(|(k, _)| *k == key) {
+					return bind_group.clone();
+				}
+
+				let bind_group = create_values_gpu_bind_group(painter, &self.layout, u);
+				created.push((key, bind_group.clone()));
+				bind_group
 			})
 			.collect::<Vec<_>>()
 	}
 }
 
+/// The texture views a layer bind group is created from.
+type LayerViews = Vec<(Texture, TexViewKey)>;
+
 #[derive(Clone)]
 pub(crate) struct LayerBindGroupData {
 	pub layout: BindGroupLayout,
 	pub data: Vec<Vec<LayerBinding>>,
+	/// GPU bind groups by the texture views they reference.
+	/// Layer bindings resolve to different textures when a layer swaps its ping-pong targets,
+	/// so each swap state gets its own entry and nothing is allocated once all states were rendered.
+	cache: BTreeMap<LayerViews, wgpu::BindGroup>,
+	/// The bind groups for the current frame, one per entry in `data`.
+	pub current: Vec<wgpu::BindGroup>,
 }
 
 impl LayerBindGroupData {
-	/// Helper function to create bind group entries from an iterator of LayerBindings
-	fn create_layer_entries<'a>(
-		bindings: impl Iterator<Item = &'a LayerBinding>,
-		painter: &'a Painter,
-	) -> Vec<wgpu::BindGroupEntry<'a>> {
-		bindings
-			.enumerate()
-			.map(|(i, u)| wgpu::BindGroupEntry {
-				binding: i as u32,
-				resource: layer_to_resource(u, painter),
-			})
-			.collect()
+	fn new(layout: BindGroupLayout, data: Vec<Vec<LayerBinding>>) -> Self {
+		Self {
+			layout,
+			data,
+			cache: BTreeMap::new(),
+			current: Vec::new(),
+		}
 	}
 
-	/// Helper function to create a bind group from entries
-	fn create_bind_group_from_entries<'a>(
-		painter: &'a Painter,
+	fn create_gpu_bind_group(
+		painter: &Painter,
 		layout: &BindGroupLayout,
-		entries: &'a [wgpu::BindGroupEntry<'a>],
+		views: &LayerViews,
 	) -> wgpu::BindGroup {
+		let entries = views
+			.iter()
+			.enumerate()
+			.map(|(i, (texture, key))| wgpu::BindGroupEntry {
+				binding: i as u32,
+				resource: wgpu::BindingResource::TextureView(texture.view(painter, key)),
+			})
+			.collect::<Vec<_>>();
+
 		painter
 			.device
 			.create_bind_group(&wgpu::BindGroupDescriptor {
 				label: None,
 				layout: &painter.bind_group_layouts[layout.0],
-				entries,
+				entries: &entries,
 			})
 	}
 
@@ -234,10 +244,7 @@ impl LayerBindGroupData {
 			bindings.sort_by(|(a, _), (b, _)| a.cmp(b));
 			let bindings = bindings.iter().map(|(_, b)| **b).collect::<Vec<_>>();
 
-			Some(Self {
-				layout,
-				data: vec![bindings],
-			})
+			Some(Self::new(layout, vec![bindings]))
 		} else {
 			// At least one instance has layer bindings
 			// Create per-instance bind groups (instance bindings override shape/layer)
@@ -257,63 +264,73 @@ impl LayerBindGroupData {
 				instances.push(bindings);
 			}
 
-			Some(Self {
-				layout,
-				data: instances,
-			})
+			Some(Self::new(layout, instances))
 		}
 	}
 
-	pub(crate) fn to_gpu_bind_groups(&self, painter: &Painter) -> Vec<wgpu::BindGroup> {
-		self.data
-			.iter()
-			.map(|bindings| {
-				let entries = Self::create_layer_entries(bindings.iter(), painter);
-				Self::create_bind_group_from_entries(painter, &self.layout, &entries)
-			})
-			.collect()
-	}
-
-	pub(crate) fn to_gpu_bind_group(&self, painter: &Painter) -> wgpu::BindGroup {
-		let entries = Self::create_layer_entries(self.data.first().unwrap().iter(), painter);
-		Self::create_bind_group_from_entries(painter, &self.layout, &entries)
-	}
-
-	pub(crate) fn to_gpu_bind_group_with_first(
-		&self,
+	/// Resolves the bindings to the current layer textures and updates `current`,
+	/// creating only the bind groups not cached yet.
+	///
+	/// `first` is prepended to each instance's bindings, used for the source texture of effects.
+	pub(crate) fn prepare_gpu_bind_groups(
+		&mut self,
 		painter: &Painter,
-		first: &LayerBinding,
-	) -> wgpu::BindGroup {
-		let entries = Self::create_layer_entries(
-			std::iter::once(first).chain(self.data.first().unwrap().iter()),
-			painter,
-		);
-		Self::create_bind_group_from_entries(painter, &self.layout, &entries)
+		first: Option<&LayerBinding>,
+	) {
+		self.current.clear();
+
+		for bindings in &self.data {
+			let views = first
+				.into_iter()
+				.chain(bindings.iter())
+				.map(|b| layer_binding_view(b, painter))
+				.collect::<Vec<_>>();
+
+			let bind_group = self.cache.entry(views).or_insert_with_key(|views| {
+				Self::create_gpu_bind_group(painter, &self.layout, views)
+			});
+
+			self.current.push(bind_group.clone());
+		}
 	}
 
-	/// Creates multiple bind groups (one per instance), each with the first binding prepended.
-	/// Used for effect rendering with instances where the source texture needs to be inserted
-	/// as the first binding for each instance.
-	pub(crate) fn to_gpu_bind_groups_with_first(
-		&self,
-		painter: &Painter,
-		first: &LayerBinding,
-	) -> Vec<wgpu::BindGroup> {
-		self.data
-			.iter()
-			.map(|bindings| {
-				let entries = Self::create_layer_entries(
-					std::iter::once(first).chain(bindings.iter()),
-					painter,
-				);
-				Self::create_bind_group_from_entries(painter, &self.layout, &entries)
-			})
-			.collect()
+	/// Drops all cached bind groups referencing the given texture,
+	/// e.g. because it was recreated on resize.
+	pub(crate) fn invalidate_texture(&mut self, texture: Texture) {
+		self.cache
+			.retain(|views, _| views.iter().all(|(t, _)| *t != texture));
 	}
 }
 
 pub(crate) struct BindGroupStorage {
 	pub(crate) bind_group: wgpu::BindGroup,
+	pub(crate) layout: BindGroupLayout,
+	pub(crate) bindings: Vec<ValueBinding>,
+	/// Offsets of the dynamic buffers in binding order.
+	pub(crate) offsets: Vec<u32>,
+}
+
+fn create_values_gpu_bind_group(
+	painter: &Painter,
+	layout: &BindGroupLayout,
+	bindings: &[ValueBinding],
+) -> wgpu::BindGroup {
+	let entries = bindings
+		.iter()
+		.enumerate()
+		.map(|(i, u)| wgpu::BindGroupEntry {
+			binding: i as u32,
+			resource: value_to_resource(u, painter),
+		})
+		.collect::<Vec<_>>();
+
+	painter
+		.device
+		.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: None,
+			layout: &painter.bind_group_layouts[layout.0],
+			entries: &entries,
+		})
 }
 
 fn value_to_resource<'a>(
@@ -329,53 +346,96 @@ fn value_to_resource<'a>(
 			let buffer = &painter.buffers[buffer.0];
 			buffer.as_entire_binding()
 		}
+		ValueBinding::DynamicBuffer { buffer, size, .. } => {
+			wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+				buffer: &painter.buffers[buffer.0],
+				offset: 0,
+				size: wgpu::BufferSize::new(*size as u64),
+			})
+		}
+		ValueBinding::StorageTexture(layer) => {
+			let l = &painter.layers[layer.0];
+			wgpu::BindingResource::TextureView(
+				l.current_source_texture()
+					.view(painter, &TexViewKey::AtMipLevel(0)),
+			)
+		}
 	}
 }
 
-fn layer_to_resource<'a>(
-	binding: &'a LayerBinding,
-	painter: &'a Painter,
-) -> wgpu::BindingResource<'a> {
+/// The texture and view a layer binding currently resolves to.
+fn layer_binding_view(binding: &LayerBinding, painter: &Painter) -> (Texture, TexViewKey) {
 	match binding {
 		LayerBinding::Source(layer) => {
 			let l = &painter.layers[layer.0];
-			wgpu::BindingResource::TextureView(l.current_source_texture().source_view(painter))
+			(*l.current_source_texture(), TexViewKey::WithAllMips)
 		}
 		LayerBinding::SourceAtMipLevel(layer, mip_level) => {
 			let l = &painter.layers[layer.0];
-			wgpu::BindingResource::TextureView(
-				l.current_source_texture()
-					.view(painter, &TexViewKey::AtMipLevel(*mip_level)),
+			(
+				*l.current_source_texture(),
+				TexViewKey::AtMipLevel(*mip_level),
 			)
 		}
 		LayerBinding::Depth(layer) => {
 			let l = &painter.layers[layer.0];
-			wgpu::BindingResource::TextureView(
-				l.depth_texture.unwrap().view(painter, &TexViewKey::Default),
-			)
+			(l.depth_texture.unwrap(), TexViewKey::DepthOnly)
 		}
+		LayerBinding::Texture(texture) => (*texture, TexViewKey::WithAllMips),
 		LayerBinding::AtIndex(layer, index) => {
 			let l = &painter.layers[layer.0];
-			wgpu::BindingResource::TextureView(
-				l.target_textures[*index].view(painter, &TexViewKey::WithAllMips),
-			)
+			(l.target_textures[*index], TexViewKey::WithAllMips)
 		}
 	}
 }
 
+fn layer_to_resource<'a>(
+	binding: &LayerBinding,
+	painter: &'a Painter,
+) -> wgpu::BindingResource<'a> {
+	let (texture, key) = layer_binding_view(binding, painter);
+	wgpu::BindingResource::TextureView(texture.view(painter, &key))
+}
+
 #[derive(Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Hash)]
-pub struct BindGroup(pub(crate) usize);
+pub struct BindGroup(pub(crate) Handle);
 
 impl BindGroup {
-	pub(crate) fn layer_gpu_bind_group(
-		painter: &mut Painter,
-		layer: LayerBinding,
-	) -> wgpu::BindGroup {
+	/// Sampler bind groups created on painter initialization, used by the fullscreen texture pipelines.
+	pub(crate) const NEAREST_SAMPLER: BindGroup = BindGroup(Handle {
+		index: 0,
+		generation: 0,
+	});
+	pub(crate) const LINEAR_SAMPLER: BindGroup = BindGroup(Handle {
+		index: 1,
+		generation: 0,
+	});
+	/// Nearest sampler bound as non-filtering sampler, for textures that are not filterable.
+	pub(crate) const UNFILTERED_SAMPLER: BindGroup = BindGroup(Handle {
+		index: 2,
+		generation: 0,
+	});
+
+	/// Frees the given bind groups, e.g. when the shape or layer using them is destroyed.
+	pub(crate) fn free(painter: &mut Painter, bind_groups: &[BindGroup]) {
+		for bg in bind_groups {
+			painter.bind_groups.remove(bg.0);
+		}
+	}
+
+	pub(crate) fn layer_gpu_bind_group(painter: &Painter, layer: LayerBinding) -> wgpu::BindGroup {
+		let (texture, _) = layer_binding_view(&layer, painter);
+		let layout = if is_float_filterable(texture.format(painter)) {
+			1
+		} else {
+			3
+		};
+
 		painter
 			.device
 			.create_bind_group(&wgpu::BindGroupDescriptor {
 				label: None,
-				layout: &painter.bind_group_layouts[1],
+				layout: &painter.bind_group_layouts[layout],
 				entries: &[wgpu::BindGroupEntry {
 					binding: 0,
 					resource: layer_to_resource(&layer, painter),
@@ -401,17 +461,58 @@ impl BindGroup {
 
 		if let Some(data) = data {
 			let bind_groups = data.to_gpu_bind_groups(painter);
-			let mut bind_group_indices = Vec::with_capacity(bind_groups.len());
-
-			for bind_group in bind_groups {
-				let index = painter.bind_groups.len();
-				painter.bind_groups.push(BindGroupStorage { bind_group });
-				bind_group_indices.push(BindGroup(index));
+			let mut bind_group_handles = Vec::with_capacity(bind_groups.len());
+
+			for (bind_group, bindings) in bind_groups.into_iter().zip(data.data) {
+				let offsets = bindings.iter().filter_map(|b| b.dynamic_offset()).collect();
+				let handle = painter.bind_groups.insert(BindGroupStorage {
+					bind_group,
+					layout: data.layout,
+					bindings,
+					offsets,
+				});
+				bind_group_handles.push(BindGroup(handle));
 			}
 
-			bind_group_indices
+			bind_group_handles
 		} else {
 			Vec::new()
 		}
 	}
+
+	/// Recreates all value bind groups that reference the given buffer.
+	/// Needed after the underlying gpu buffer was replaced, e.g. when a storage buffer grows.
+	pub(crate) fn refresh_buffer_bind_groups(painter: &mut Painter, buffer: Buffer) {
+		for i in painter.bind_groups.handles() {
+			let bg = &painter.bind_groups[i];
+			let uses_buffer = bg.bindings.iter().any(|b| match b {
+				ValueBinding::Buffer(buf) | ValueBinding::DynamicBuffer { buffer: buf, .. } => {
+					*buf == buffer
+				}
+				_ => false,
+			});
+
+			if uses_buffer {
+				let bind_group = create_values_gpu_bind_group(painter, &bg.layout, &bg.bindings);
+				painter.bind_groups[i].bind_group = bind_group;
+			}
+		}
+	}
+
+	/// Recreates all value bind groups that bind the layer as storage texture.
+	/// Needed after the current texture of the layer changed, e.g. on resize or after a swap.
+	pub(crate) fn refresh_storage_texture_bind_groups(painter: &mut Painter, layer: Layer) {
+		for i in painter.bind_groups.handles() {
+			let bg = &painter.bind_groups[i];
+			let uses_layer = bg
+				.bindings
+				.iter()
+				.any(|b| matches!(b, ValueBinding::StorageTexture(l) if *l == layer));
+
+			if uses_layer {
+				let bind_group = create_values_gpu_bind_group(painter, &bg.layout, &bg.bindings);
+				painter.bind_groups[i].bind_group = bind_group;
+			}
+		}
+	}
 }
@@ -6,8 +6,8 @@
 //!
 //! There are two main categories of bindings:
 //!
-//! - **ValueBindings**: Uniforms (buffers) and samplers that can be bound to shaders
-//! - **LayerBindings**: Texture layers that can be bound as inputs to shaders
+//! - **ValueBindings**: Uniforms and storage buffers, samplers and storage textures that can be bound to shaders
+//! - **LayerBindings**: Texture layers and loaded textures that can be bound as inputs to shaders
 //!
 //! ## Binding Override Hierarchy
 //!
@@ -35,7 +35,10 @@
 //! - `ValuesBindGroupData::from_bindings()` and `LayerBindGroupData::from_bindings()` in bind_group.rs
 //! - `render_shape()` and `render_effect()` in painter.rs
 
-use crate::{Painter, layer::Layer, painter::get_padded_size, sampler::Sampler};
+use crate::{
+	Painter, bind_group::BindGroup, layer::Layer, painter::get_padded_size, registry::Handle,
+	sampler::Sampler, texture::Texture,
+};
 use trivalibs_core::glam::{Mat3, Mat3A, Vec3, Vec3A};
 use wgpu::{BindingType, ShaderStages};
 
@@ -45,15 +48,94 @@ pub struct BindingLayout {
 	pub(crate) visibility: ShaderStages,
 }
 
+impl BindingLayout {
+	/// Adapts a sampler layout to textures of the given format, e.g.
+	/// `BINDING_SAMPLER_FRAG.for_format(Rgba32Float)` for a non-filtering sampler.
+	/// Depth formats get a comparison sampler, see `SamplerBuilder::with_depth_sampling`.
+	/// Other binding types are returned unchanged.
+	pub fn for_format(mut self, format: wgpu::TextureFormat) -> Self {
+		if let BindingType::Sampler(_) = self.binding_type {
+			self.binding_type = BindingType::Sampler(sampler_binding_type(format));
+		}
+		self
+	}
+}
+
 #[derive(Clone, Copy)]
 pub struct LayerLayout {
 	pub(crate) visibility: ShaderStages,
+	pub(crate) view_dimension: wgpu::TextureViewDimension,
+	pub(crate) sample_type: wgpu::TextureSampleType,
 }
 
-#[derive(Clone, Copy)]
+impl LayerLayout {
+	/// Adapts the layout to textures of the given format. The default layouts expect
+	/// filterable float textures, which 32-bit float, integer and depth formats are not.
+	///
+	/// 32-bit float textures are bound as non-filterable, also on devices with
+	/// `Features::FLOAT32_FILTERABLE`, so the layout is valid everywhere.
+	pub fn for_format(mut self, format: wgpu::TextureFormat) -> Self {
+		self.sample_type = texture_sample_type(format);
+		self
+	}
+}
+
+/// The sample type of textures in the given format. Depth-stencil formats are sampled by their depth.
+pub fn texture_sample_type(format: wgpu::TextureFormat) -> wgpu::TextureSampleType {
+	let aspect = if format.has_depth_aspect() {
+		Some(wgpu::TextureAspect::DepthOnly)
+	} else if format.has_stencil_aspect() {
+		Some(wgpu::TextureAspect::StencilOnly)
+	} else {
+		None
+	};
+
+	format
+		.sample_type(aspect, None)
+		.unwrap_or_else(|| panic!("Texture format {:?} can not be sampled", format))
+}
+
+/// The sampler binding type for textures in the given format.
+pub fn sampler_binding_type(format: wgpu::TextureFormat) -> wgpu::SamplerBindingType {
+	match texture_sample_type(format) {
+		wgpu::TextureSampleType::Float { filterable: true } => wgpu::SamplerBindingType::Filtering,
+		wgpu::TextureSampleType::Depth => wgpu::SamplerBindingType::Comparison,
+		_ => wgpu::SamplerBindingType::NonFiltering,
+	}
+}
+
+#[derive(Clone, Copy, PartialEq)]
 pub enum ValueBinding {
 	Buffer(Buffer),
+	/// An element of a `BindingDynamic`, bound with a dynamic offset.
+	DynamicBuffer {
+		buffer: Buffer,
+		size: u32,
+		offset: u32,
+	},
 	Sampler(Sampler),
+	StorageTexture(Layer),
+}
+
+impl ValueBinding {
+	/// The binding with dynamic offsets reset, identifying the bind group resource.
+	pub(crate) fn without_offset(&self) -> Self {
+		match *self {
+			ValueBinding::DynamicBuffer { buffer, size, .. } => ValueBinding::DynamicBuffer {
+				buffer,
+				size,
+				offset: 0,
+			},
+			binding => binding,
+		}
+	}
+
+	pub(crate) fn dynamic_offset(&self) -> Option<u32> {
+		match self {
+			ValueBinding::DynamicBuffer { offset, .. } => Some(*offset),
+			_ => None,
+		}
+	}
 }
 
 #[derive(Clone, Copy)]
@@ -62,15 +144,23 @@ pub enum LayerBinding {
 	AtIndex(Layer, usize),
 	SourceAtMipLevel(Layer, u32),
 	Depth(Layer),
+	Texture(Texture),
 }
 
-#[derive(Clone, Copy)]
-pub struct Buffer(pub(crate) usize);
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Buffer(pub(crate) Handle);
 
 impl Buffer {
 	pub fn binding(&self) -> ValueBinding {
 		ValueBinding::Buffer(*self)
 	}
+
+	/// Frees the gpu buffer. Bind groups still using it must not be rendered afterwards.
+	pub fn destroy(self, painter: &mut Painter) {
+		if let Some(buffer) = painter.buffers.remove(self.0) {
+			buffer.destroy();
+		}
+	}
 }
 
 #[derive(Clone, Copy)]
@@ -91,9 +181,7 @@ where
 			mapped_at_creation: false,
 		});
 
-		painter.buffers.push(buffer);
-
-		let buffer = Buffer(painter.buffers.len() - 1);
+		let buffer = Buffer(painter.buffers.insert(buffer));
 
 		let binding = BindingBuffer {
 			buffer,
@@ -115,6 +203,184 @@ where
 	pub fn binding(&self) -> ValueBinding {
 		ValueBinding::Buffer(self.buffer)
 	}
+
+	pub fn destroy(self, painter: &mut Painter) {
+		self.buffer.destroy(painter);
+	}
+}
+
+/// A pool of `count` uniforms of type `T` in a single buffer, bound with dynamic offsets.
+///
+/// Bind it with one of the `BINDING_DYNAMIC_BUFFER_*` layouts and pass `binding(i)` to each instance.
+/// Instances only differing in the elements they use share one bind group,
+/// which is set with a different offset per draw.
+#[derive(Clone, Copy)]
+pub struct BindingDynamic<T> {
+	buffer: Buffer,
+	count: usize,
+	stride: u64,
+	t: std::marker::PhantomData<T>,
+}
+
+impl<T> BindingDynamic<T>
+where
+	T: bytemuck::Pod,
+{
+	pub fn new(painter: &mut Painter, count: usize) -> Self {
+		if count == 0 {
+			panic!("Dynamic bindings need at least one element");
+		}
+
+		let stride = get_padded_size(std::mem::size_of::<T>() as u64)
+			.next_multiple_of(painter.device.limits().min_uniform_buffer_offset_alignment as u64);
+
+		let buffer = painter.device.create_buffer(&wgpu::BufferDescriptor {
+			label: None,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			size: stride * count as u64,
+			mapped_at_creation: false,
+		});
+
+		BindingDynamic {
+			buffer: Buffer(painter.buffers.insert(buffer)),
+			count,
+			stride,
+			t: std::marker::PhantomData,
+		}
+	}
+
+	pub fn update(&self, painter: &Painter, index: usize, data: T) {
+		let buffer = &painter.buffers[self.buffer.0];
+		painter.queue.write_buffer(
+			buffer,
+			self.offset(index) as u64,
+			bytemuck::cast_slice(&[data]),
+		);
+	}
+
+	/// Updates the first `data.len()` elements with a single buffer write.
+	pub fn update_all(&self, painter: &Painter, data: &[T]) {
+		if data.len() > self.count {
+			panic!(
+				"{} elements do not fit into a dynamic binding of {}",
+				data.len(),
+				self.count
+			);
+		}
+
+		let size = std::mem::size_of::<T>();
+		let mut bytes = vec![0u8; self.stride as usize * data.len()];
+		for (chunk, d) in bytes.chunks_exact_mut(self.stride as usize).zip(data) {
+			chunk[..size].copy_from_slice(bytemuck::bytes_of(d));
+		}
+
+		let buffer = &painter.buffers[self.buffer.0];
+		painter.queue.write_buffer(buffer, 0, &bytes);
+	}
+
+	pub fn count(&self) -> usize {
+		self.count
+	}
+
+	fn offset(&self, index: usize) -> u32 {
+		if index >= self.count {
+			panic!(
+				"Index {} out of range for a dynamic binding of {}",
+				index, self.count
+			);
+		}
+
+		(self.stride * index as u64) as u32
+	}
+
+	/// Binds the element at `index`.
+	pub fn binding(&self, index: usize) -> ValueBinding {
+		ValueBinding::DynamicBuffer {
+			buffer: self.buffer,
+			size: get_padded_size(std::mem::size_of::<T>() as u64) as u32,
+			offset: self.offset(index),
+		}
+	}
+
+	pub fn destroy(self, painter: &mut Painter) {
+		self.buffer.destroy(painter);
+	}
+}
+
+/// A storage buffer holding a runtime-sized array of `T`.
+///
+/// Bind it with one of the `BINDING_STORAGE_*` layouts. Read-only storage can be used in
+/// all shader stages, e.g. to read instance data written by a compute shader in a vertex shader.
+///
+/// Like `Form::update`, updating with more data than fits grows the gpu buffer,
+/// while smaller updates reuse it. Note that `arrayLength` in the shader therefore
+/// reports the buffer capacity, pass the current element count separately if it varies.
+#[derive(Clone, Copy)]
+pub struct BindingStorage<T> {
+	buffer: Buffer,
+	t: std::marker::PhantomData<T>,
+}
+
+fn create_storage_buffer(painter: &Painter, size: u64) -> wgpu::Buffer {
+	painter.device.create_buffer(&wgpu::BufferDescriptor {
+		label: None,
+		usage: wgpu::BufferUsages::STORAGE
+			| wgpu::BufferUsages::COPY_DST
+			| wgpu::BufferUsages::COPY_SRC,
+		size: get_padded_size(size),
+		mapped_at_creation: false,
+	})
+}
+
+impl<T> BindingStorage<T>
+where
+	T: bytemuck::Pod,
+{
+	pub fn new(painter: &mut Painter, data: &[T]) -> Self {
+		let storage = Self::new_with_len(painter, data.len());
+		storage.update(painter, data);
+		storage
+	}
+
+	/// Creates a zero initialized storage buffer for `len` elements,
+	/// e.g. as output of a compute shader.
+	pub fn new_with_len(painter: &mut Painter, len: usize) -> Self {
+		let buffer = create_storage_buffer(painter, (len * std::mem::size_of::<T>()) as u64);
+
+		BindingStorage {
+			buffer: Buffer(painter.buffers.insert(buffer)),
+			t: std::marker::PhantomData,
+		}
+	}
+
+	pub fn update(&self, painter: &mut Painter, data: &[T]) {
+		let bytes: &[u8] = bytemuck::cast_slice(data);
+		let size = get_padded_size(bytes.len() as u64);
+
+		if painter.buffers[self.buffer.0].size() < size {
+			let buffer = create_storage_buffer(painter, size);
+			painter.buffers[self.buffer.0].destroy();
+			painter.buffers[self.buffer.0] = buffer;
+			BindGroup::refresh_buffer_bind_groups(painter, self.buffer);
+		}
+
+		painter
+			.queue
+			.write_buffer(&painter.buffers[self.buffer.0], 0, bytes);
+	}
+
+	/// The number of elements that fit into the current gpu buffer.
+	pub fn capacity(&self, painter: &Painter) -> usize {
+		painter.buffers[self.buffer.0].size() as usize / std::mem::size_of::<T>()
+	}
+
+	pub fn binding(&self) -> ValueBinding {
+		ValueBinding::Buffer(self.buffer)
+	}
+
+	pub fn destroy(self, painter: &mut Painter) {
+		self.buffer.destroy(painter);
+	}
 }
 
 #[repr(C)]
@@ -156,6 +422,7 @@ impl BindingBuffer<Vec3U> {
 ///
 /// - `bindings`: Value bindings (buffers, samplers) to override, indexed by binding slot
 /// - `layers`: Layer bindings (textures) to override, indexed by binding slot
+/// - `push_constants`: Push constant data overriding the shape's, only used by shapes
 ///
 /// # Override Behavior
 ///
@@ -188,6 +455,14 @@ impl BindingBuffer<Vec3U> {
 pub struct InstanceBinding {
 	pub bindings: Vec<(u32, ValueBinding)>,
 	pub layers: Vec<(u32, LayerBinding)>,
+	pub push_constants: Option<Vec<u8>>,
+}
+
+impl InstanceBinding {
+	pub fn with_push_constants<T: bytemuck::Pod>(mut self, data: T) -> Self {
+		self.push_constants = Some(bytemuck::bytes_of(&data).to_vec());
+		self
+	}
 }
 
 impl Default for InstanceBinding {
@@ -195,6 +470,7 @@ impl Default for InstanceBinding {
 		Self {
 			bindings: Vec::with_capacity(0),
 			layers: Vec::with_capacity(0),
+			push_constants: None,
 		}
 	}
 }
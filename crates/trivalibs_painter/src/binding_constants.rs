@@ -28,6 +28,96 @@ pub const BINDING_BUFFER_BOTH: BindingLayout = BindingLayout {
 	},
 };
 
+pub const BINDING_BUFFER_COMPUTE: BindingLayout = BindingLayout {
+	visibility: wgpu::ShaderStages::COMPUTE,
+	binding_type: BindingType::Buffer {
+		ty: wgpu::BufferBindingType::Uniform,
+		has_dynamic_offset: false,
+		min_binding_size: None,
+	},
+};
+
+pub const BINDING_DYNAMIC_BUFFER_VERT: BindingLayout = BindingLayout {
+	visibility: wgpu::ShaderStages::VERTEX,
+	binding_type: BindingType::Buffer {
+		ty: wgpu::BufferBindingType::Uniform,
+		has_dynamic_offset: true,
+		min_binding_size: None,
+	},
+};
+
+pub const BINDING_DYNAMIC_BUFFER_FRAG: BindingLayout = BindingLayout {
+	visibility: wgpu::ShaderStages::FRAGMENT,
+	binding_type: BindingType::Buffer {
+		ty: wgpu::BufferBindingType::Uniform,
+		has_dynamic_offset: true,
+		min_binding_size: None,
+	},
+};
+
+pub const BINDING_DYNAMIC_BUFFER_BOTH: BindingLayout = BindingLayout {
+	visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+	binding_type: BindingType::Buffer {
+		ty: wgpu::BufferBindingType::Uniform,
+		has_dynamic_offset: true,
+		min_binding_size: None,
+	},
+};
+
+pub const BINDING_DYNAMIC_BUFFER_COMPUTE: BindingLayout = BindingLayout {
+	visibility: wgpu::ShaderStages::COMPUTE,
+	binding_type: BindingType::Buffer {
+		ty: wgpu::BufferBindingType::Uniform,
+		has_dynamic_offset: true,
+		min_binding_size: None,
+	},
+};
+
+pub const BINDING_STORAGE_VERT: BindingLayout = BindingLayout {
+	visibility: wgpu::ShaderStages::VERTEX,
+	binding_type: BindingType::Buffer {
+		ty: wgpu::BufferBindingType::Storage { read_only: true },
+		has_dynamic_offset: false,
+		min_binding_size: None,
+	},
+};
+
+pub const BINDING_STORAGE_FRAG: BindingLayout = BindingLayout {
+	visibility: wgpu::ShaderStages::FRAGMENT,
+	binding_type: BindingType::Buffer {
+		ty: wgpu::BufferBindingType::Storage { read_only: true },
+		has_dynamic_offset: false,
+		min_binding_size: None,
+	},
+};
+
+pub const BINDING_STORAGE_BOTH: BindingLayout = BindingLayout {
+	visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+	binding_type: BindingType::Buffer {
+		ty: wgpu::BufferBindingType::Storage { read_only: true },
+		has_dynamic_offset: false,
+		min_binding_size: None,
+	},
+};
+
+pub const BINDING_STORAGE_COMPUTE: BindingLayout = BindingLayout {
+	visibility: wgpu::ShaderStages::COMPUTE,
+	binding_type: BindingType::Buffer {
+		ty: wgpu::BufferBindingType::Storage { read_only: true },
+		has_dynamic_offset: false,
+		min_binding_size: None,
+	},
+};
+
+pub const BINDING_STORAGE_RW_COMPUTE: BindingLayout = BindingLayout {
+	visibility: wgpu::ShaderStages::COMPUTE,
+	binding_type: BindingType::Buffer {
+		ty: wgpu::BufferBindingType::Storage { read_only: false },
+		has_dynamic_offset: false,
+		min_binding_size: None,
+	},
+};
+
 pub const BINDING_SAMPLER_VERT: BindingLayout = BindingLayout {
 	visibility: wgpu::ShaderStages::VERTEX,
 	binding_type: BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
@@ -43,14 +133,119 @@ pub const BINDING_SAMPLER_BOTH: BindingLayout = BindingLayout {
 	binding_type: BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
 };
 
+pub const BINDING_SAMPLER_COMPUTE: BindingLayout = BindingLayout {
+	visibility: wgpu::ShaderStages::COMPUTE,
+	binding_type: BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+};
+
 pub const BINDING_LAYER_VERT: LayerLayout = LayerLayout {
 	visibility: wgpu::ShaderStages::VERTEX,
+	view_dimension: wgpu::TextureViewDimension::D2,
+	sample_type: wgpu::TextureSampleType::Float { filterable: true },
 };
 
 pub const BINDING_LAYER_FRAG: LayerLayout = LayerLayout {
 	visibility: wgpu::ShaderStages::FRAGMENT,
+	view_dimension: wgpu::TextureViewDimension::D2,
+	sample_type: wgpu::TextureSampleType::Float { filterable: true },
 };
 
 pub const BINDING_LAYER_BOTH: LayerLayout = LayerLayout {
 	visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+	view_dimension: wgpu::TextureViewDimension::D2,
+	sample_type: wgpu::TextureSampleType::Float { filterable: true },
 };
+
+pub const BINDING_LAYER_COMPUTE: LayerLayout = LayerLayout {
+	visibility: wgpu::ShaderStages::COMPUTE,
+	view_dimension: wgpu::TextureViewDimension::D2,
+	sample_type: wgpu::TextureSampleType::Float { filterable: true },
+};
+
+pub const BINDING_TEX2D_ARRAY_VERT: LayerLayout = LayerLayout {
+	visibility: wgpu::ShaderStages::VERTEX,
+	view_dimension: wgpu::TextureViewDimension::D2Array,
+	sample_type: wgpu::TextureSampleType::Float { filterable: true },
+};
+
+pub const BINDING_TEX2D_ARRAY_FRAG: LayerLayout = LayerLayout {
+	visibility: wgpu::ShaderStages::FRAGMENT,
+	view_dimension: wgpu::TextureViewDimension::D2Array,
+	sample_type: wgpu::TextureSampleType::Float { filterable: true },
+};
+
+pub const BINDING_TEX2D_ARRAY_BOTH: LayerLayout = LayerLayout {
+	visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+	view_dimension: wgpu::TextureViewDimension::D2Array,
+	sample_type: wgpu::TextureSampleType::Float { filterable: true },
+};
+
+pub const BINDING_TEX2D_ARRAY_COMPUTE: LayerLayout = LayerLayout {
+	visibility: wgpu::ShaderStages::COMPUTE,
+	view_dimension: wgpu::TextureViewDimension::D2Array,
+	sample_type: wgpu::TextureSampleType::Float { filterable: true },
+};
+
+pub const BINDING_CUBE_VERT: LayerLayout = LayerLayout {
+	visibility: wgpu::ShaderStages::VERTEX,
+	view_dimension: wgpu::TextureViewDimension::Cube,
+	sample_type: wgpu::TextureSampleType::Float { filterable: true },
+};
+
+pub const BINDING_CUBE_FRAG: LayerLayout = LayerLayout {
+	visibility: wgpu::ShaderStages::FRAGMENT,
+	view_dimension: wgpu::TextureViewDimension::Cube,
+	sample_type: wgpu::TextureSampleType::Float { filterable: true },
+};
+
+pub const BINDING_CUBE_BOTH: LayerLayout = LayerLayout {
+	visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+	view_dimension: wgpu::TextureViewDimension::Cube,
+	sample_type: wgpu::TextureSampleType::Float { filterable: true },
+};
+
+pub const BINDING_CUBE_COMPUTE: LayerLayout = LayerLayout {
+	visibility: wgpu::ShaderStages::COMPUTE,
+	view_dimension: wgpu::TextureViewDimension::Cube,
+	sample_type: wgpu::TextureSampleType::Float { filterable: true },
+};
+
+pub const BINDING_TEX3D_VERT: LayerLayout = LayerLayout {
+	visibility: wgpu::ShaderStages::VERTEX,
+	view_dimension: wgpu::TextureViewDimension::D3,
+	sample_type: wgpu::TextureSampleType::Float { filterable: true },
+};
+
+pub const BINDING_TEX3D_FRAG: LayerLayout = LayerLayout {
+	visibility: wgpu::ShaderStages::FRAGMENT,
+	view_dimension: wgpu::TextureViewDimension::D3,
+	sample_type: wgpu::TextureSampleType::Float { filterable: true },
+};
+
+pub const BINDING_TEX3D_BOTH: LayerLayout = LayerLayout {
+	visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+	view_dimension: wgpu::TextureViewDimension::D3,
+	sample_type: wgpu::TextureSampleType::Float { filterable: true },
+};
+
+pub const BINDING_TEX3D_COMPUTE: LayerLayout = LayerLayout {
+	visibility: wgpu::ShaderStages::COMPUTE,
+	view_dimension: wgpu::TextureViewDimension::D3,
+	sample_type: wgpu::TextureSampleType::Float { filterable: true },
+};
+
+/// Layout for a layer bound via `Layer::storage_binding` in a compute shader.
+/// The format has to match the layer format.
+pub const fn binding_storage_texture_compute(
+	format: wgpu::TextureFormat,
+	access: wgpu::StorageTextureAccess,
+) -> BindingLayout {
+	BindingLayout {
+		visibility: wgpu::ShaderStages::COMPUTE,
+		binding_type: BindingType::StorageTexture {
+			access,
+			format,
+			view_dimension: wgpu::TextureViewDimension::D2,
+		},
+	}
+}
@@ -0,0 +1,345 @@
+use crate::{
+	Painter,
+	bind_group::{BindGroup, BindGroupLayout, LayerBindGroupData},
+	binding::{BindingLayout, LayerBinding, LayerLayout, ValueBinding},
+	error::PainterError,
+	registry::Handle,
+	shade::{ShaderCode, layouts_from_props},
+};
+use trivalibs_core::utils::default;
+
+pub(crate) struct ComputeShadeStorage {
+	pub compute_path: Option<String>,
+	pub compute_code: Option<ShaderCode>,
+	pub pipeline_layout: wgpu::PipelineLayout,
+	pub binding_layout: Option<BindGroupLayout>,
+	pub layers_layout: Option<BindGroupLayout>,
+	pub value_bindings_length: usize,
+	pub layer_bindings_length: usize,
+	pub pipeline: Option<wgpu::ComputePipeline>,
+}
+
+#[derive(Default)]
+pub struct ComputeShadeProps {
+	pub bindings: Vec<BindingLayout>,
+	pub layers: Vec<LayerLayout>,
+}
+
+/// A compute shader program and its binding contract.
+///
+/// Value bindings (uniform buffers, samplers, storage textures) are bound to group 0.
+/// Layer bindings (sampled textures) follow in the next group, which is group 1,
+/// or group 0 if the shade has no value bindings. This is the same as for render shades.
+#[derive(Clone, Copy, Debug)]
+pub struct ComputeShade(pub(crate) Handle);
+
+impl ComputeShade {
+	pub fn new(painter: &mut Painter, props: ComputeShadeProps) -> Self {
+		let bindings_len = props.bindings.len();
+		let layers_len = props.layers.len();
+
+		let (pipeline_layout, binding_layout, layers_layout, _) =
+			layouts_from_props(painter, &props.bindings, &props.layers, None);
+
+		let s = ComputeShadeStorage {
+			compute_path: None,
+			compute_code: None,
+			pipeline_layout,
+			binding_layout,
+			layers_layout,
+			value_bindings_length: bindings_len,
+			layer_bindings_length: layers_len,
+			pipeline: None,
+		};
+
+		ComputeShade(painter.compute_shades.insert(s))
+	}
+
+	/// Frees the compute shade and its pipeline.
+	///
+	/// Computes using this shade have to be destroyed as well,
+	/// they panic when dispatched afterwards.
+	pub fn destroy(self, painter: &mut Painter) {
+		painter.compute_shades.remove(self.0);
+	}
+
+	fn set_compute_code(&self, painter: &mut Painter, code: ShaderCode) {
+		let s = &mut painter.compute_shades[self.0];
+		s.compute_code = Some(code);
+		// Recreated lazily on next dispatch
+		s.pipeline = None;
+	}
+
+	pub fn set_compute_bytes(&self, painter: &mut Painter, bytes: Vec<u8>) {
+		self.set_compute_code(painter, ShaderCode::SpirV(bytes));
+	}
+
+	/// Sets WGSL source code for the compute stage.
+	pub fn set_compute_wgsl(&self, painter: &mut Painter, code: &str) {
+		self.set_compute_code(painter, ShaderCode::Wgsl(code.to_string()));
+	}
+
+	pub(crate) fn load_compute_from_path(&self, painter: &mut Painter) -> Result<(), PainterError> {
+		if let Some(shader_path) = &painter.compute_shades[self.0].compute_path {
+			let code = ShaderCode::read(shader_path)
+				.map_err(|err| PainterError::read_file(shader_path, err))?;
+			self.set_compute_code(painter, code);
+		}
+		Ok(())
+	}
+
+	pub fn set_compute_path(&self, painter: &mut Painter, path: &str) {
+		self.try_set_compute_path(painter, path)
+			.unwrap_or_else(|err| panic!("{}", err));
+	}
+
+	/// Like `set_compute_path`, but returns an error if the file can not be read.
+	/// The path is kept for hot reloading either way.
+	pub fn try_set_compute_path(
+		&self,
+		painter: &mut Painter,
+		path: &str,
+	) -> Result<(), PainterError> {
+		painter.compute_shades[self.0].compute_path = Some(path.to_string());
+		self.load_compute_from_path(painter)
+	}
+
+	/// Creates the pipeline if it does not exist yet.
+	pub(crate) fn ensure_pipeline(&self, painter: &mut Painter) -> Result<(), PainterError> {
+		if painter.compute_shades[self.0].pipeline.is_some() {
+			return Ok(());
+		}
+		painter.check_pipeline_cache();
+
+		let s = &painter.compute_shades[self.0];
+		let code = s.compute_code.as_ref().ok_or(PainterError::MissingShader {
+			stage: wgpu::ShaderStages::COMPUTE,
+		})?;
+
+		let module = painter
+			.device
+			.create_shader_module(wgpu::ShaderModuleDescriptor {
+				label: None,
+				source: code.source(),
+			});
+
+		let pipeline = painter
+			.device
+			.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+				label: None,
+				layout: Some(&s.pipeline_layout),
+				module: &module,
+				entry_point: None,
+				compilation_options: default(),
+				cache: painter.pipeline_cache(),
+			});
+
+		painter.compute_shades[self.0].pipeline = Some(pipeline);
+		Ok(())
+	}
+}
+
+pub struct ComputeShadeBuilder<'b> {
+	props: ComputeShadeProps,
+	painter: &'b mut Painter,
+}
+
+impl<'b> ComputeShadeBuilder<'b> {
+	pub fn new(painter: &'b mut Painter) -> Self {
+		ComputeShadeBuilder {
+			props: ComputeShadeProps::default(),
+			painter,
+		}
+	}
+
+	pub fn create(self) -> ComputeShade {
+		ComputeShade::new(self.painter, self.props)
+	}
+
+	pub fn with_bindings<I>(mut self, bindings: I) -> Self
+	where
+		I: IntoIterator<Item = BindingLayout>,
+	{
+		self.props.bindings = bindings.into_iter().collect();
+		self
+	}
+
+	pub fn with_layers<I>(mut self, layers: I) -> Self
+	where
+		I: IntoIterator<Item = LayerLayout>,
+	{
+		self.props.layers = layers.into_iter().collect();
+		self
+	}
+}
+
+#[derive(Clone)]
+pub struct ComputeProps {
+	pub bindings: Vec<(u32, ValueBinding)>,
+	pub layers: Vec<(u32, LayerBinding)>,
+	pub workgroups: (u32, u32, u32),
+}
+
+impl Default for ComputeProps {
+	fn default() -> Self {
+		ComputeProps {
+			bindings: Vec::with_capacity(0),
+			layers: Vec::with_capacity(0),
+			workgroups: (1, 1, 1),
+		}
+	}
+}
+
+pub(crate) struct ComputeStorage {
+	pub shade: ComputeShade,
+	pub bind_group: Option<BindGroup>,
+	pub layer_bind_group_data: Option<LayerBindGroupData>,
+	pub workgroups: (u32, u32, u32),
+}
+
+/// A dispatchable compute pass: a [`ComputeShade`] with its bindings and workgroup count.
+///
+/// Dispatch it with `Painter::dispatch`. Dispatches can be freely interleaved with `Painter::paint`.
+#[derive(Clone, Copy, Debug)]
+pub struct Compute(pub(crate) Handle);
+
+impl Compute {
+	pub fn new(painter: &mut Painter, shade: ComputeShade, props: ComputeProps) -> Self {
+		let sd = &painter.compute_shades[shade.0];
+		let value_bindings_length = sd.value_bindings_length;
+		let binding_layout = sd.binding_layout;
+		let layer_bindings_length = sd.layer_bindings_length;
+		let layers_layout = sd.layers_layout;
+
+		let bind_group = BindGroup::values_bind_groups(
+			painter,
+			value_bindings_length,
+			binding_layout,
+			&props.bindings,
+			&[],
+			&[],
+		)
+		.first()
+		.copied();
+
+		let layer_bind_group_data = LayerBindGroupData::from_bindings(
+			layer_bindings_length,
+			layers_layout,
+			&props.layers,
+			&[],
+			&[],
+		);
+
+		let compute = ComputeStorage {
+			shade,
+			bind_group,
+			layer_bind_group_data,
+			workgroups: props.workgroups,
+		};
+
+		Compute(painter.computes.insert(compute))
+	}
+
+	/// Frees the bind groups of the compute.
+	///
+	/// Its shade and the bound buffers and layers are not destroyed.
+	pub fn destroy(self, painter: &mut Painter) {
+		let Some(storage) = painter.computes.remove(self.0) else {
+			return;
+		};
+		if let Some(bind_group) = storage.bind_group {
+			BindGroup::free(painter, &[bind_group]);
+		}
+	}
+
+	/// Sets the number of workgroups dispatched in x, y and z direction.
+	pub fn set_workgroups(&self, painter: &mut Painter, x: u32, y: u32, z: u32) {
+		painter.computes[self.0].workgroups = (x, y, z);
+	}
+
+	pub fn shade(&self, painter: &Painter) -> ComputeShade {
+		painter.computes[self.0].shade
+	}
+}
+
+/// Builder for creating new [`Compute`]s.
+///
+/// # Default values for [`ComputeProps`]:
+/// - `workgroups`: `(1, 1, 1)`
+///
+/// # Example
+/// ```ignore
+/// let compute = painter
+///     .compute(shade)
+///     .with_bindings([(0, particles.binding())])
+///     .with_workgroups(count.div_ceil(64), 1, 1)
+///     .create();
+///
+/// painter.dispatch(compute);
+/// ```
+pub struct ComputeBuilder<'a> {
+	shade: ComputeShade,
+	props: ComputeProps,
+	painter: &'a mut Painter,
+}
+
+impl<'a> ComputeBuilder<'a> {
+	pub fn new(painter: &'a mut Painter, shade: ComputeShade) -> Self {
+		ComputeBuilder {
+			shade,
+			painter,
+			props: ComputeProps::default(),
+		}
+	}
+
+	pub fn create(self) -> Compute {
+		Compute::new(self.painter, self.shade, self.props)
+	}
+
+	pub fn with_bindings<I>(mut self, bindings: I) -> Self
+	where
+		I: IntoIterator<Item = (u32, ValueBinding)>,
+	{
+		self.props.bindings = bindings.into_iter().collect();
+		self
+	}
+
+	pub fn with_layers<I>(mut self, layers: I) -> Self
+	where
+		I: IntoIterator<Item = (u32, LayerBinding)>,
+	{
+		self.props.layers = layers.into_iter().collect();
+		self
+	}
+
+	pub fn with_workgroups(mut self, x: u32, y: u32, z: u32) -> Self {
+		self.props.workgroups = (x, y, z);
+		self
+	}
+}
+
+#[macro_export]
+macro_rules! load_compute_shader {
+	($shade:expr, $painter:expr, $path:expr) => {
+		#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+		{
+			let current_file = file!();
+			let current_dir = std::path::Path::new(current_file).parent().unwrap();
+			let full_path = current_dir.join($path);
+			let full_path = std::fs::canonicalize(full_path).unwrap();
+			let full_path = full_path.to_str().unwrap();
+			println!("loading shader: {:?}", full_path);
+			$shade.set_compute_path($painter, full_path);
+		}
+
+		#[cfg(any(not(debug_assertions), target_arch = "wasm32"))]
+		{
+			if $path.ends_with(".wgsl") {
+				let code = std::str::from_utf8(include_bytes!($path)).unwrap();
+				$shade.set_compute_wgsl($painter, code);
+			} else {
+				$shade.set_compute_bytes($painter, include_bytes!($path).to_vec());
+			}
+		}
+	};
+}
@@ -1,6 +1,8 @@
 use crate::{
 	Painter,
 	binding::{InstanceBinding, LayerBinding, ValueBinding},
+	layer::Layer,
+	registry::Handle,
 	shade::Shade,
 };
 
@@ -12,6 +14,8 @@ pub struct EffectProps {
 	pub blend_state: wgpu::BlendState,
 	pub dst_mip_level: Option<u32>,
 	pub src_mip_level: Option<u32>,
+	/// Name of the effect in frame timings and GPU debugging tools.
+	pub label: Option<String>,
 }
 
 impl Default for EffectProps {
@@ -23,6 +27,7 @@ impl Default for EffectProps {
 			blend_state: wgpu::BlendState::REPLACE,
 			dst_mip_level: None,
 			src_mip_level: None,
+			label: None,
 		}
 	}
 }
@@ -36,15 +41,16 @@ pub(crate) struct EffectStorage {
 	pub blend_state: wgpu::BlendState,
 	pub dst_mip_level: Option<u32>,
 	pub src_mip_level: Option<u32>,
+	pub label: Option<String>,
 }
 
 #[derive(Clone, Copy, Debug)]
-pub struct Effect(pub(crate) usize);
+pub struct Effect(pub(crate) Handle);
 
 impl Effect {
 	pub fn new(painter: &mut Painter, shade: Shade, props: EffectProps) -> Self {
 		let pipeline_key = vec![
-			(shade.0 as u16).to_le_bytes().to_vec(),
+			shade.0.key_bytes().to_vec(),
 			vec![
 				props.blend_state.alpha.dst_factor as u8,
 				props.blend_state.alpha.src_factor as u8,
@@ -67,11 +73,22 @@ impl Effect {
 			blend_state: props.blend_state,
 			dst_mip_level: props.dst_mip_level,
 			src_mip_level: props.src_mip_level,
+			label: props.label,
 		};
 
-		painter.effects.push(effect);
+		Self(painter.effects.insert(effect))
+	}
+
+	/// Removes the effect from all layers and frees its bind groups.
+	///
+	/// The shade of the effect is not destroyed, as it can be shared by other effects.
+	pub fn destroy(self, painter: &mut Painter) {
+		for h in painter.layers.handles() {
+			Layer(h).remove_effect(painter, self);
+		}
 
-		Self(painter.effects.len() - 1)
+		painter.effects.remove(self.0);
+		painter.cleanup_pipelines();
 	}
 
 	pub fn has_mip_target(&self, painter: &Painter) -> bool {
@@ -140,4 +157,9 @@ impl<'a> EffectBuilder<'a> {
 		self.props.src_mip_level = Some(src_mip_level);
 		self
 	}
+
+	pub fn with_label(mut self, label: &str) -> Self {
+		self.props.label = Some(label.to_string());
+		self
+	}
 }
@@ -0,0 +1,144 @@
+use crate::reflect::ShaderInterfaceError;
+use std::fmt;
+
+/// Errors returned by the `try_*` variants of painter constructors, builders and the app runner.
+///
+/// The non `try_*` variants panic with the message of the error instead.
+#[derive(Debug)]
+pub enum PainterError {
+	/// The event loop could not be created or exited with an error.
+	EventLoop(winit::error::EventLoopError),
+	/// The window for the canvas could not be created.
+	CreateWindow(winit::error::OsError),
+	CreateSurface(wgpu::CreateSurfaceError),
+	/// No adapter is compatible with the surface, or no adapter is available at all.
+	RequestAdapter(wgpu::RequestAdapterError),
+	RequestDevice(wgpu::RequestDeviceError),
+	/// A shader or image file could not be read.
+	ReadFile {
+		path: String,
+		source: std::io::Error,
+	},
+	DecodePng(png::DecodingError),
+	/// A shader stage has no code, neither loaded from a path nor set as bytes or WGSL source.
+	MissingShader {
+		stage: wgpu::ShaderStages,
+	},
+	/// The shader code does not match the bindings or vertex attributes of its shade.
+	ShaderInterface(ShaderInterfaceError),
+	/// Builder properties that can not be combined, or are not supported by the device.
+	InvalidProps(String),
+	/// Layers that depend on each other, see `Painter::paint_order`.
+	/// Holds the labels of the layers in the cycle, starting and ending with the same layer.
+	CyclicDependency(Vec<String>),
+	/// The shader file watcher for hot reloading could not be started.
+	Watch(notify::Error),
+}
+
+impl PainterError {
+	pub(crate) fn read_file(path: impl AsRef<std::path::Path>, source: std::io::Error) -> Self {
+		PainterError::ReadFile {
+			path: path.as_ref().display().to_string(),
+			source,
+		}
+	}
+}
+
+impl fmt::Display for PainterError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			PainterError::EventLoop(err) => write!(f, "Event loop error: {}", err),
+			PainterError::CreateWindow(err) => write!(f, "Failed to create window: {}", err),
+			PainterError::CreateSurface(err) => write!(f, "Failed to create surface: {}", err),
+			PainterError::RequestAdapter(err) => {
+				write!(f, "Failed to find an appropriate adapter: {}", err)
+			}
+			PainterError::RequestDevice(err) => write!(f, "Failed to create device: {}", err),
+			PainterError::ReadFile { path, source } => {
+				write!(f, "Failed to read file {}: {}", path, source)
+			}
+			PainterError::DecodePng(err) => write!(f, "Failed to decode PNG image: {}", err),
+			PainterError::MissingShader { stage } => {
+				let stage = match *stage {
+					wgpu::ShaderStages::VERTEX => "Vertex",
+					wgpu::ShaderStages::FRAGMENT => "Fragment",
+					_ => "Compute",
+				};
+				write!(f, "{} shader code is missing", stage)
+			}
+			PainterError::ShaderInterface(err) => err.fmt(f),
+			PainterError::InvalidProps(message) => f.write_str(message),
+			PainterError::CyclicDependency(cycle) => {
+				write!(f, "Cyclic layer dependency: {}", cycle.join(" -> "))
+			}
+			PainterError::Watch(err) => write!(f, "Failed to watch shader files: {}", err),
+		}
+	}
+}
+
+impl std::error::Error for PainterError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			PainterError::EventLoop(err) => Some(err),
+			PainterError::CreateWindow(err) => Some(err),
+			PainterError::CreateSurface(err) => Some(err),
+			PainterError::RequestAdapter(err) => Some(err),
+			PainterError::RequestDevice(err) => Some(err),
+			PainterError::ReadFile { source, .. } => Some(source),
+			PainterError::DecodePng(err) => Some(err),
+			PainterError::ShaderInterface(err) => Some(err),
+			PainterError::Watch(err) => Some(err),
+			PainterError::MissingShader { .. }
+			| PainterError::InvalidProps(_)
+			| PainterError::CyclicDependency(_) => None,
+		}
+	}
+}
+
+impl From<winit::error::EventLoopError> for PainterError {
+	fn from(err: winit::error::EventLoopError) -> Self {
+		PainterError::EventLoop(err)
+	}
+}
+
+impl From<winit::error::OsError> for PainterError {
+	fn from(err: winit::error::OsError) -> Self {
+		PainterError::CreateWindow(err)
+	}
+}
+
+impl From<wgpu::CreateSurfaceError> for PainterError {
+	fn from(err: wgpu::CreateSurfaceError) -> Self {
+		PainterError::CreateSurface(err)
+	}
+}
+
+impl From<wgpu::RequestAdapterError> for PainterError {
+	fn from(err: wgpu::RequestAdapterError) -> Self {
+		PainterError::RequestAdapter(err)
+	}
+}
+
+impl From<wgpu::RequestDeviceError> for PainterError {
+	fn from(err: wgpu::RequestDeviceError) -> Self {
+		PainterError::RequestDevice(err)
+	}
+}
+
+impl From<png::DecodingError> for PainterError {
+	fn from(err: png::DecodingError) -> Self {
+		PainterError::DecodePng(err)
+	}
+}
+
+impl From<ShaderInterfaceError> for PainterError {
+	fn from(err: ShaderInterfaceError) -> Self {
+		PainterError::ShaderInterface(err)
+	}
+}
+
+impl From<notify::Error> for PainterError {
+	fn from(err: notify::Error) -> Self {
+		PainterError::Watch(err)
+	}
+}
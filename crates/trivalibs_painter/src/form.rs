@@ -1,4 +1,5 @@
-use crate::{Painter, painter::get_padded_size};
+use crate::{Painter, painter::get_padded_size, registry::Handle};
+use std::collections::HashSet;
 use trivalibs_core::rendering::BufferedGeometry;
 
 #[derive(Clone, Copy)]
@@ -26,12 +27,29 @@ pub(crate) struct FormGPUBuffers {
 	pub index_buffer_max_size: u64,
 	pub index_buffer_current_size: u64,
 	pub index_count: u32,
+
+	/// Copy of the triangle indices, only kept on devices without line polygon mode
+	/// to derive line lists for wireframe shapes.
+	pub index_data: Vec<u32>,
+	pub line_index_buffer: Option<wgpu::Buffer>,
+	pub line_index_buffer_max_size: u64,
+	pub line_index_count: u32,
+}
+
+pub(crate) struct FormInstanceBuffer {
+	pub buffer: wgpu::Buffer,
+	pub max_size: u64,
+	pub current_size: u64,
 }
 
 pub(crate) struct FormStorage {
 	pub buffers: Vec<FormGPUBuffers>,
 	pub currently_active_buffers: usize,
+	pub instance_buffer: Option<FormInstanceBuffer>,
+	pub instance_count: u32,
 	pub props: FormProps,
+	/// Whether line list index buffers are maintained for wireframe shapes.
+	pub line_list: bool,
 }
 
 fn create_form_gpu_buffers(device: &wgpu::Device, vertex_size: u64) -> FormGPUBuffers {
@@ -52,9 +70,82 @@ fn create_form_gpu_buffers(device: &wgpu::Device, vertex_size: u64) -> FormGPUBu
 		index_buffer_max_size: 0,
 		index_buffer_current_size: 0,
 		index_count: 0,
+		index_data: Vec::with_capacity(0),
+		line_index_buffer: None,
+		line_index_buffer_max_size: 0,
+		line_index_count: 0,
 	}
 }
 
+/// Converts the triangles of a triangle list or strip into a line list with one entry per edge.
+/// Edges shared by several triangles are only drawn once. Without index data, the triangles are formed by consecutive vertices.
+pub fn triangles_to_line_list(
+	topology: wgpu::PrimitiveTopology,
+	indices: Option<&[u32]>,
+	vertex_count: u32,
+) -> Vec<u32> {
+	let sequential: Vec<u32>;
+	let indices = match indices {
+		Some(indices) => indices,
+		None => {
+			sequential = (0..vertex_count).collect();
+			&sequential
+		}
+	};
+
+	let triangles: Vec<[u32; 3]> = match topology {
+		wgpu::PrimitiveTopology::TriangleList => indices
+			.chunks_exact(3)
+			.map(|t| [t[0], t[1], t[2]])
+			.collect(),
+		wgpu::PrimitiveTopology::TriangleStrip => {
+			indices.windows(3).map(|t| [t[0], t[1], t[2]]).collect()
+		}
+		_ => Vec::with_capacity(0),
+	};
+
+	let mut edges = HashSet::with_capacity(triangles.len() * 3);
+	let mut lines = Vec::with_capacity(triangles.len() * 6);
+	for [a, b, c] in triangles {
+		for (from, to) in [(a, b), (b, c), (c, a)] {
+			// Skip collapsed edges of degenerate triangles, e.g. the ones joining strips
+			if from != to && edges.insert((from.min(to), from.max(to))) {
+				lines.push(from);
+				lines.push(to);
+			}
+		}
+	}
+	lines
+}
+
+fn write_line_indices(
+	device: &wgpu::Device,
+	queue: &wgpu::Queue,
+	f_buf: &mut FormGPUBuffers,
+	topology: wgpu::PrimitiveTopology,
+) {
+	let indices = (f_buf.index_count > 0).then_some(f_buf.index_data.as_slice());
+	let lines = triangles_to_line_list(topology, indices, f_buf.vertex_count);
+	let data: &[u8] = bytemuck::cast_slice(&lines);
+	let padded_size = get_padded_size(data.len() as u64);
+
+	if f_buf.line_index_buffer.is_none() || f_buf.line_index_buffer_max_size < padded_size {
+		if let Some(b) = &f_buf.line_index_buffer {
+			b.destroy();
+		}
+		f_buf.line_index_buffer = Some(device.create_buffer(&wgpu::BufferDescriptor {
+			label: None,
+			usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+			size: padded_size,
+			mapped_at_creation: false,
+		}));
+		f_buf.line_index_buffer_max_size = padded_size;
+	}
+
+	f_buf.line_index_count = lines.len() as u32;
+	queue.write_buffer(f_buf.line_index_buffer.as_ref().unwrap(), 0, data);
+}
+
 pub struct FormData<'a, T>
 where
 	T: bytemuck::Pod + bytemuck::Zeroable,
@@ -118,7 +209,7 @@ impl<'a, T: bytemuck::Pod> Into<FormBuffer<'a>> for &'a Vec<T> {
 }
 
 #[derive(Clone, Copy)]
-pub struct Form(pub(crate) usize);
+pub struct Form(pub(crate) Handle);
 
 impl Form {
 	pub fn update_all<'a, I, B>(&self, painter: &mut Painter, buffers: I)
@@ -128,6 +219,10 @@ impl Form {
 	{
 		let f = &mut painter.forms[self.0];
 		let buffers: Vec<FormBuffer<'a>> = buffers.into_iter().map(|b| b.into()).collect();
+		let keep_index_data = !painter
+			.device
+			.features()
+			.contains(wgpu::Features::POLYGON_MODE_LINE);
 
 		// Allocate new buffers if we need more than we currently have
 		while f.buffers.len() < buffers.len() {
@@ -186,9 +281,21 @@ impl Form {
 
 				let index_buffer = f_buf.index_buffer.as_ref().unwrap();
 				painter.queue.write_buffer(index_buffer, 0, &index_data);
+
+				if keep_index_data {
+					f_buf.index_data = index_data
+						.chunks_exact(4)
+						.map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+						.collect();
+				}
 			} else {
 				f_buf.index_count = 0;
 				f_buf.index_buffer_current_size = 0;
+				f_buf.index_data.clear();
+			}
+
+			if f.line_list {
+				write_line_indices(&painter.device, &painter.queue, f_buf, f.props.topology);
 			}
 		}
 
@@ -200,6 +307,72 @@ impl Form {
 		self.update_all(painter, vec![buffer.into()]);
 	}
 
+	/// Updates the per-instance vertex buffer and sets the instance count to the number of items.
+	///
+	/// The data layout has to match the instance attributes of the shade,
+	/// see `ShadeBuilder::with_instance_attributes`. All instances are drawn in a single draw call.
+	pub fn update_instance_data<T: bytemuck::Pod>(&self, painter: &mut Painter, instances: &[T]) {
+		self.update_instance_bytes(
+			painter,
+			bytemuck::cast_slice(instances),
+			instances.len() as u32,
+		);
+	}
+
+	fn update_instance_bytes(&self, painter: &mut Painter, data: &[u8], count: u32) {
+		let f = &mut painter.forms[self.0];
+		let size = data.len() as u64;
+		let padded_size = get_padded_size(size);
+
+		// If buffer doesn't exist yet or new data is larger, (re)create it
+		if f.instance_buffer
+			.as_ref()
+			.is_none_or(|b| b.max_size < padded_size)
+		{
+			if let Some(b) = &f.instance_buffer {
+				b.buffer.destroy();
+			}
+			f.instance_buffer = Some(FormInstanceBuffer {
+				buffer: painter.device.create_buffer(&wgpu::BufferDescriptor {
+					label: None,
+					usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+					size: padded_size,
+					mapped_at_creation: false,
+				}),
+				max_size: padded_size,
+				current_size: 0,
+			});
+		}
+
+		let b = f.instance_buffer.as_mut().unwrap();
+		b.current_size = size;
+		f.instance_count = count;
+
+		painter.queue.write_buffer(&b.buffer, 0, data);
+	}
+
+	/// Maintains line list index buffers next to the triangle data,
+	/// used to render wireframes on devices without line polygon mode.
+	pub(crate) fn enable_line_list(&self, painter: &mut Painter) {
+		let f = &mut painter.forms[self.0];
+		if f.line_list {
+			return;
+		}
+		f.line_list = true;
+
+		for f_buf in f.buffers.iter_mut() {
+			write_line_indices(&painter.device, &painter.queue, f_buf, f.props.topology);
+		}
+	}
+
+	/// Sets the number of instances drawn per draw call.
+	///
+	/// Useful for instancing without instance buffer, e.g. reading per-instance data
+	/// from a storage buffer by instance index. Defaults to 1.
+	pub fn set_instance_count(&self, painter: &mut Painter, count: u32) {
+		painter.forms[self.0].instance_count = count;
+	}
+
 	pub fn new_with_sizes(painter: &mut Painter, sizes: &[u64], props: FormProps) -> Self {
 		let mut buffers = Vec::with_capacity(sizes.len());
 		for size in sizes {
@@ -209,13 +382,13 @@ impl Form {
 		let f = FormStorage {
 			buffers,
 			currently_active_buffers: 0, // No data yet - will be set by update_all
+			instance_buffer: None,
+			instance_count: 1,
 			props,
+			line_list: false,
 		};
 
-		let i = painter.forms.len();
-		painter.forms.push(f);
-
-		return Form(i);
+		Form(painter.forms.insert(f))
 	}
 
 	pub fn new<'a, I, B>(painter: &mut Painter, buffers: I, props: FormProps) -> Self
@@ -229,12 +402,35 @@ impl Form {
 
 		form
 	}
+
+	/// Frees the gpu buffers of the form.
+	///
+	/// Shapes using this form have to be destroyed as well, they panic when rendered afterwards.
+	pub fn destroy(self, painter: &mut Painter) {
+		let Some(f) = painter.forms.remove(self.0) else {
+			return;
+		};
+
+		for b in f.buffers {
+			b.vertex_buffer.destroy();
+			if let Some(index_buffer) = b.index_buffer {
+				index_buffer.destroy();
+			}
+			if let Some(line_index_buffer) = b.line_index_buffer {
+				line_index_buffer.destroy();
+			}
+		}
+		if let Some(instance_buffer) = f.instance_buffer {
+			instance_buffer.buffer.destroy();
+		}
+	}
 }
 
 pub struct FormBuilder<'a, 'b> {
 	painter: &'a mut Painter,
 	buffers: Vec<FormBuffer<'b>>,
 	sizes: Vec<u64>,
+	instances: Option<(&'b [u8], u32)>,
 	props: FormProps,
 }
 
@@ -244,16 +440,22 @@ impl<'a, 'b> FormBuilder<'a, 'b> {
 			painter,
 			buffers: Vec::with_capacity(1),
 			sizes: Vec::with_capacity(1),
+			instances: None,
 			props: FormProps::default(),
 		}
 	}
 
 	pub fn create(self) -> Form {
-		if self.sizes.len() == 0 {
-			return Form::new(self.painter, self.buffers, self.props);
+		let f = if self.sizes.len() == 0 {
+			Form::new(self.painter, self.buffers, self.props)
+		} else {
+			let f = Form::new_with_sizes(self.painter, &self.sizes, self.props);
+			f.update_all(self.painter, self.buffers);
+			f
+		};
+		if let Some((data, count)) = self.instances {
+			f.update_instance_bytes(self.painter, data, count);
 		}
-		let f = Form::new_with_sizes(self.painter, &self.sizes, self.props);
-		f.update_all(self.painter, self.buffers);
 		f
 	}
 
@@ -284,6 +486,12 @@ impl<'a, 'b> FormBuilder<'a, 'b> {
 		self
 	}
 
+	/// Sets the per-instance vertex data, see `Form::update_instance_data`.
+	pub fn with_instance_data<T: bytemuck::Pod>(mut self, instances: &'b [T]) -> Self {
+		self.instances = Some((bytemuck::cast_slice(instances), instances.len() as u32));
+		self
+	}
+
 	pub fn with_topology(mut self, topology: wgpu::PrimitiveTopology) -> Self {
 		self.props.topology = topology;
 		self
@@ -294,3 +502,46 @@ impl<'a, 'b> FormBuilder<'a, 'b> {
 		self
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use wgpu::PrimitiveTopology;
+
+	#[test]
+	fn line_list_draws_shared_edges_once() {
+		// Two triangles of a quad, sharing the diagonal 0-2
+		let lines = triangles_to_line_list(
+			PrimitiveTopology::TriangleList,
+			Some(&[0, 1, 2, 0, 2, 3]),
+			4,
+		);
+
+		assert_eq!(lines, vec![0, 1, 1, 2, 2, 0, 2, 3, 3, 0]);
+	}
+
+	#[test]
+	fn line_list_without_indices_uses_vertex_order() {
+		let lines = triangles_to_line_list(PrimitiveTopology::TriangleList, None, 4);
+		assert_eq!(lines, vec![0, 1, 1, 2, 2, 0]);
+
+		let lines = triangles_to_line_list(PrimitiveTopology::TriangleStrip, None, 4);
+		assert_eq!(lines, vec![0, 1, 1, 2, 2, 0, 2, 3, 3, 1]);
+	}
+
+	#[test]
+	fn line_list_skips_degenerate_edges() {
+		// Repeated indices form the degenerate triangles 2-3-3, 3-3-4, 3-4-4 and 4-4-5
+		let indices = [0, 1, 2, 3, 3, 4, 4, 5];
+		let lines = triangles_to_line_list(PrimitiveTopology::TriangleStrip, Some(&indices), 6);
+
+		assert!(lines.chunks_exact(2).all(|l| l[0] != l[1]));
+		assert_eq!(lines, vec![0, 1, 1, 2, 2, 0, 2, 3, 3, 1, 3, 4, 4, 5]);
+	}
+
+	#[test]
+	fn line_list_is_empty_for_other_topologies() {
+		let lines = triangles_to_line_list(PrimitiveTopology::LineList, Some(&[0, 1, 2]), 3);
+		assert!(lines.is_empty());
+	}
+}
@@ -5,12 +5,17 @@ use crate::{
 	bind_group::{BindGroup, LayerBindGroupData},
 	binding::{InstanceBinding, LayerBinding, LayerLayout, ValueBinding},
 	effect::Effect,
+	error::PainterError,
 	prelude::{BINDING_LAYER_BOTH, BINDING_LAYER_FRAG, BINDING_LAYER_VERT},
+	registry::Handle,
 	shade::Shade,
 	shape::Shape,
-	texture::{MipMapCount, Texture, Texture2DProps},
+	texture::{
+		MipMapCount, TexViewKey, TexelComponent, Texture, TextureKind, TextureProps, TextureRegion,
+	},
 	texture_utils::map_format_to_u8,
 };
+use std::cell::Cell;
 
 /// Describes the instance rendering strategy to use based on binding configurations.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -54,9 +59,22 @@ pub struct LayerProps<'a> {
 	pub formats: Vec<wgpu::TextureFormat>,
 	pub clear_color: Option<wgpu::Color>,
 	pub depth_test: bool,
+	pub depth_format: wgpu::TextureFormat,
+	/// Value the depth buffer is cleared to before painting shapes, `None` keeps the previous content.
+	pub depth_clear: Option<f32>,
+	/// Value the stencil buffer is cleared to, if the depth format has a stencil aspect.
+	pub stencil_clear: Option<u32>,
+	/// Render into the depth texture of another layer instead of creating one.
+	pub shared_depth: Option<Layer>,
 	pub layer_layout: LayerLayout,
-	pub multisampled: bool,
+	/// Number of samples per pixel, 1 disables multisampling.
+	pub sample_count: u32,
 	pub mips: Option<MipMapCount>,
+	pub storage_texture: bool,
+	/// Render into the slices of a 2D array, cubemap or 3D texture, one slice per paint.
+	pub texture_kind: TextureKind,
+	/// Name of the layer in frame timings and GPU debugging tools.
+	pub label: Option<String>,
 }
 
 impl Default for LayerProps<'_> {
@@ -74,8 +92,15 @@ impl Default for LayerProps<'_> {
 			layer_layout: BINDING_LAYER_FRAG,
 			clear_color: None,
 			depth_test: false,
-			multisampled: false,
+			depth_format: wgpu::TextureFormat::Depth24Plus,
+			depth_clear: Some(1.0),
+			stencil_clear: Some(0),
+			shared_depth: None,
+			sample_count: 1,
 			mips: None,
+			storage_texture: false,
+			texture_kind: TextureKind::D2,
+			label: None,
 		}
 	}
 }
@@ -283,6 +308,10 @@ pub(crate) struct LayerStorage {
 	pub effects: Vec<EffectData>,
 	pub target_textures: Vec<Texture>,
 	pub depth_texture: Option<Texture>,
+	/// The layer that owns the shared depth texture, which resizes and destroys it.
+	pub shared_depth: Option<Layer>,
+	pub depth_clear: Option<f32>,
+	pub stencil_clear: Option<u32>,
 	pub width: u32,
 	pub height: u32,
 	pub use_window_size: bool,
@@ -290,12 +319,21 @@ pub(crate) struct LayerStorage {
 	pub pipeline_key: Vec<u8>,
 	pub formats: Vec<wgpu::TextureFormat>,
 	pub multisampled_textures: Vec<Texture>,
+	pub sample_count: u32,
 	pub current_target: usize,
 	pub texture_count: usize,
 	pub is_multi_target: bool,
 	pub bindings: Vec<(u32, ValueBinding)>,
 	pub layers: Vec<(u32, LayerBinding)>,
 	pub mips: Option<MipMapCount>,
+	pub texture_kind: TextureKind,
+	/// The array layer, cube face or depth slice the shapes are rendered into.
+	pub target_slice: u32,
+	pub label: Option<String>,
+	pub static_texture: bool,
+	/// True if the static texture changed since the layer was last painted.
+	/// A `Cell`, so that `Layer::update_static_data` only needs a shared painter.
+	pub static_texture_dirty: Cell<bool>,
 }
 
 impl LayerStorage {
@@ -316,56 +354,175 @@ impl LayerStorage {
 
 		&self.target_textures[idx - 1]
 	}
+
+	/// The render target view of a target texture and its depth slice for 3D textures.
+	pub(crate) fn target_attachment<'a>(
+		&self,
+		painter: &'a Painter,
+		texture: &Texture,
+	) -> (&'a wgpu::TextureView, Option<u32>) {
+		match self.texture_kind {
+			TextureKind::D2 => (texture.target_view(painter), None),
+			TextureKind::D3(_) => (texture.target_view(painter), Some(self.target_slice)),
+			_ => (
+				texture.view(painter, &TexViewKey::Slice(self.target_slice)),
+				None,
+			),
+		}
+	}
+}
+
+/// Sample counts usable for render attachments of the given format.
+/// Without adapter specific format features only the counts guaranteed by WebGPU are used.
+fn supported_sample_counts(painter: &Painter, format: TextureFormat) -> Vec<u32> {
+	let features = painter.device.features();
+	let flags = if features.contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) {
+		painter.adapter.get_texture_format_features(format).flags
+	} else {
+		format.guaranteed_format_features(features).flags
+	};
+
+	[1, 2, 4, 8, 16]
+		.into_iter()
+		.filter(|&c| flags.sample_count_supported(c))
+		.collect()
 }
 
-#[derive(Clone, Copy)]
-pub struct Layer(pub(crate) usize);
+fn validate_sample_count(
+	painter: &Painter,
+	format: TextureFormat,
+	sample_count: u32,
+) -> Result<(), PainterError> {
+	let supported = supported_sample_counts(painter, format);
+	if !supported.contains(&sample_count) {
+		return Err(PainterError::InvalidProps(format!(
+			"Sample count {} is not supported for format {:?}. Supported counts: {:?}",
+			sample_count, format, supported
+		)));
+	}
+	Ok(())
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Layer(pub(crate) Handle);
 
 impl Layer {
 	pub fn new(painter: &mut Painter, props: LayerProps) -> Self {
+		Self::try_new(painter, props).unwrap_or_else(|err| panic!("{}", err))
+	}
+
+	/// Like `new`, but returns an error if the props can not be combined
+	/// or the sample count is not supported by the device.
+	pub fn try_new(painter: &mut Painter, props: LayerProps) -> Result<Self, PainterError> {
+		let invalid = |message: &str| Err(PainterError::InvalidProps(message.to_string()));
+
 		if props.static_texture && props.shapes.len() > 0 {
-			panic!("A layer can only either contain a static texture or render shapes, not both")
+			return invalid(
+				"A layer can only either contain a static texture or render shapes, not both",
+			);
+		}
+
+		if props.texture_kind != TextureKind::D2 {
+			if !props.effects.is_empty() {
+				return invalid("Effects are only supported in layers with 2D textures");
+			}
+			if matches!(props.texture_kind, TextureKind::D3(_)) && props.sample_count > 1 {
+				return invalid("Multisampling is not supported in layers with 3D textures");
+			}
+		}
+
+		if let Some(shared) = props.shared_depth {
+			if painter.layers[shared.0].depth_texture.is_none() {
+				return invalid(
+					"A layer can only share the depth texture of a layer with depth test",
+				);
+			}
+			if painter.layers[shared.0].sample_count != props.sample_count {
+				return invalid(
+					"A layer can only share the depth texture of a layer with the same sample count",
+				);
+			}
+			let owner = &painter.layers[shared.0];
+			let use_window_size = props.width == 0 || props.height == 0;
+			if owner.use_window_size != use_window_size
+				|| (!use_window_size && (owner.width, owner.height) != (props.width, props.height))
+			{
+				return invalid(
+					"A layer can only share the depth texture of a layer with the same size",
+				);
+			}
+		}
+
+		let swapping_effect_count = props
+			.effects
+			.iter()
+			.filter(|e| !e.has_mip_target(painter) && !e.has_mip_source(painter))
+			.count();
+
+		let use_swap_targets = swapping_effect_count > 1
+			|| ((props.shapes.len() > 0 || props.static_texture) && swapping_effect_count > 0);
+
+		let format_len = props.formats.len();
+		let is_multi_target = format_len > 1;
+
+		if is_multi_target && use_swap_targets {
+			return invalid(
+				"Postprocessing is not supported with multiple targets. Only sketches or one effect can be used.",
+			);
 		}
 
 		let use_window_size = props.width == 0 || props.height == 0;
+		let window_size = painter.render_size();
 		let width = if use_window_size {
-			painter.config.width
+			window_size.width
 		} else {
 			props.width
 		};
 		let height = if use_window_size {
-			painter.config.height
+			window_size.height
 		} else {
 			props.height
 		};
 
-		let depth_texture = props
-			.depth_test
-			.then(|| Texture::create_depth(painter, width, height, props.multisampled));
+		let formats_to_validate = if props.formats.is_empty() {
+			vec![painter.config.format]
+		} else {
+			props.formats.clone()
+		};
+		for format in formats_to_validate
+			.into_iter()
+			.chain(props.depth_test.then_some(props.depth_format))
+		{
+			validate_sample_count(painter, format, props.sample_count)?;
+		}
+
+		let multisampled = props.sample_count > 1;
+
+		let depth_texture = if let Some(shared) = props.shared_depth {
+			painter.layers[shared.0].depth_texture
+		} else {
+			props.depth_test.then(|| {
+				Texture::create_depth(
+					painter,
+					width,
+					height,
+					props.depth_format,
+					props.sample_count,
+				)
+			})
+		};
+
+		let depth_format = depth_texture.map(|t| painter.textures[t.0].texture.format());
 
 		let pipeline_key = vec![
-			vec![(props.depth_test as u8)],
-			vec![props.multisampled as u8],
+			vec![depth_format.map_or(0, map_format_to_u8)],
+			vec![props.sample_count as u8],
 			props.formats.iter().map(|f| map_format_to_u8(*f)).collect(),
 		]
 		.into_iter()
 		.flatten()
 		.collect();
 
-		let layer = Layer(painter.layers.len());
-
-		let swapping_effect_count = props
-			.effects
-			.iter()
-			.filter(|e| !e.has_mip_target(painter) && !e.has_mip_source(painter))
-			.count();
-
-		let use_swap_targets = swapping_effect_count > 1
-			|| ((props.shapes.len() > 0 || props.static_texture) && swapping_effect_count > 0);
-
-		let format_len = props.formats.len();
-		let is_multi_target = format_len > 1;
-
 		let texture_count = if is_multi_target {
 			format_len
 		} else {
@@ -374,47 +531,47 @@ impl Layer {
 
 		let mut target_textures = Vec::with_capacity(texture_count);
 		let mut multisampled_textures =
-			Vec::with_capacity(if props.multisampled { texture_count } else { 0 });
+			Vec::with_capacity(if multisampled { texture_count } else { 0 });
 		let mut formats = Vec::with_capacity(texture_count);
 
-		let mut usage =
-			wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING;
+		let mut usage = wgpu::TextureUsages::RENDER_ATTACHMENT
+			| wgpu::TextureUsages::TEXTURE_BINDING
+			| wgpu::TextureUsages::COPY_SRC;
 		if props.static_texture {
 			usage |= wgpu::TextureUsages::COPY_DST;
 		}
+		if props.storage_texture {
+			usage |= wgpu::TextureUsages::STORAGE_BINDING;
+		}
 
 		if is_multi_target {
-			if use_swap_targets {
-				panic!(
-					"Postprocessing is not supported with multiple targets. Only sketches or one effect can be used."
-				);
-			}
-
 			for format in props.formats {
-				let tex = Texture::create_2d(
+				let tex = Texture::create(
 					painter,
 					width,
 					height,
-					Texture2DProps {
+					TextureProps {
 						format,
 						usage,
 						mips: props.mips,
+						kind: props.texture_kind,
 					},
-					false,
+					1,
 				);
 				target_textures.push(tex);
 
-				if props.multisampled {
-					multisampled_textures.push(Texture::create_2d(
+				if multisampled {
+					multisampled_textures.push(Texture::create(
 						painter,
 						width,
 						height,
-						Texture2DProps {
+						TextureProps {
 							format,
 							usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
 							mips: None,
+							kind: TextureKind::D2,
 						},
-						true,
+						props.sample_count,
 					));
 				}
 
@@ -424,32 +581,34 @@ impl Layer {
 			let format = *props.formats.get(0).unwrap_or(&painter.config.format);
 
 			for _ in 0..texture_count {
-				let tex = Texture::create_2d(
+				let tex = Texture::create(
 					painter,
 					width,
 					height,
-					Texture2DProps {
+					TextureProps {
 						format,
 						usage,
 						mips: props.mips,
+						kind: props.texture_kind,
 					},
-					false,
+					1,
 				);
 
 				target_textures.push(tex);
 			}
 
-			if props.multisampled {
-				multisampled_textures.push(Texture::create_2d(
+			if multisampled {
+				multisampled_textures.push(Texture::create(
 					painter,
 					width,
 					height,
-					Texture2DProps {
+					TextureProps {
 						format,
 						usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
 						mips: None,
+						kind: TextureKind::D2,
 					},
-					true,
+					props.sample_count,
 				));
 			}
 
@@ -475,7 +634,11 @@ impl Layer {
 			height,
 			target_textures,
 			depth_texture,
+			shared_depth: props.shared_depth,
+			depth_clear: props.depth_clear,
+			stencil_clear: props.stencil_clear,
 			multisampled_textures,
+			sample_count: props.sample_count,
 			use_window_size,
 			clear_color: props.clear_color,
 			formats,
@@ -486,9 +649,14 @@ impl Layer {
 			bindings: props.bindings,
 			layers: props.layers,
 			mips: props.mips,
+			texture_kind: props.texture_kind,
+			target_slice: 0,
+			label: props.label,
+			static_texture: props.static_texture,
+			static_texture_dirty: Cell::new(props.static_texture),
 		};
 
-		painter.layers.push(storage);
+		let layer = Layer(painter.layers.insert(storage));
 
 		if props.effects.iter().any(|e| e.has_mip_target(painter)) {
 			let textures = painter.layers[layer.0].target_textures.clone();
@@ -501,7 +669,7 @@ impl Layer {
 			layer.update_static_data(painter, data);
 		}
 
-		layer
+		Ok(layer)
 	}
 
 	/// This function is called by after the CanvasApp::init function automatically.
@@ -513,6 +681,13 @@ impl Layer {
 	/// Alternatively, LayerBuilder::create_and_init can be used to create and initialize.
 	/// Or Painter::init_and_paint can be used to initialize and paint in one call.
 	pub fn init_gpu_pipelines(&self, painter: &mut Painter) {
+		self.try_init_gpu_pipelines(painter)
+			.unwrap_or_else(|err| panic!("{}", err));
+	}
+
+	/// Like `init_gpu_pipelines`, but returns an error if a shader is missing
+	/// or does not match its shade. Pipelines created before the error are kept.
+	pub fn try_init_gpu_pipelines(&self, painter: &mut Painter) -> Result<(), PainterError> {
 		let shapes: Vec<Shape> = painter.layers[self.0]
 			.shapes
 			.iter()
@@ -527,19 +702,83 @@ impl Layer {
 
 		for s in shapes {
 			let key = painter.get_shape_pipeline_key(s, *self);
-			painter.ensure_shape_pipeline(&key, s, *self);
+			painter.ensure_shape_pipeline(&key, s, *self)?;
 		}
 
 		for e in effects {
 			let key = painter.get_effect_pipeline_key(e, *self);
-			painter.ensure_effect_pipeline(&key, e, *self);
+			painter.ensure_effect_pipeline(&key, e, *self)?;
 		}
+
+		Ok(())
 	}
 
 	pub fn update_static_data(&self, painter: &Painter, data: &[u8]) {
-		painter.layers[self.0]
-			.current_source_texture()
-			.fill_2d(painter, data);
+		let l = &painter.layers[self.0];
+		l.current_source_texture().fill_2d(painter, data);
+		l.static_texture_dirty.set(true);
+	}
+
+	/// Writes a region of the static texture, e.g. the parts of a CPU canvas that changed.
+	/// See `Texture::write_region` for the data layout.
+	///
+	/// Uses reusable staging buffers, so the texture can be updated every frame without reallocations.
+	/// Mipmaps are regenerated after writes to mip level 0.
+	pub fn update_static_region<T: TexelComponent>(
+		&self,
+		painter: &mut Painter,
+		region: TextureRegion,
+		data: &[T],
+	) {
+		let texture = *painter.layers[self.0].current_source_texture();
+		texture.write_region_staged(painter, region, data);
+		if region.mip_level == 0 {
+			let mut encoder = painter.begin_encoder();
+			texture.encode_mips(painter, &mut encoder);
+			painter.end_encoder(encoder);
+		}
+		painter.layers[self.0].static_texture_dirty.set(true);
+	}
+
+	/// The layers whose textures this layer reads, through the layer bindings of the layer,
+	/// its shapes and effects and their instances, or a shared depth texture.
+	///
+	/// Bindings of the layer's own textures are not included.
+	pub fn dependencies(&self, painter: &Painter) -> Vec<Layer> {
+		let l = &painter.layers[self.0];
+
+		let shape_layers = l.shapes.iter().flat_map(|sd| {
+			let s = &painter.shapes[sd.shape.0];
+			s.layers
+				.iter()
+				.chain(s.instances.iter().flat_map(|i| i.layers.iter()))
+		});
+		let effect_layers = l.effects.iter().flat_map(|ed| {
+			let e = &painter.effects[ed.effect.0];
+			e.layers
+				.iter()
+				.chain(e.instances.iter().flat_map(|i| i.layers.iter()))
+		});
+
+		let mut dependencies: Vec<Layer> = l
+			.layers
+			.iter()
+			.chain(shape_layers)
+			.chain(effect_layers)
+			.filter_map(|(_, binding)| match binding {
+				LayerBinding::Source(layer)
+				| LayerBinding::AtIndex(layer, _)
+				| LayerBinding::SourceAtMipLevel(layer, _)
+				| LayerBinding::Depth(layer) => Some(*layer),
+				LayerBinding::Texture(_) => None,
+			})
+			.chain(l.shared_depth)
+			.filter(|layer| layer != self)
+			.collect();
+
+		dependencies.sort_by_key(|layer| layer.0);
+		dependencies.dedup();
+		dependencies
 	}
 
 	pub fn binding(&self) -> LayerBinding {
@@ -558,10 +797,32 @@ impl Layer {
 		LayerBinding::AtIndex(*self, index)
 	}
 
+	/// Binds the current layer texture as storage texture, to be written by compute shaders.
+	///
+	/// The layer has to be created with `LayerBuilder::with_storage_texture`.
+	pub fn storage_binding(&self) -> ValueBinding {
+		ValueBinding::StorageTexture(*self)
+	}
+
 	pub fn set_clear_color(&mut self, painter: &mut Painter, color: Option<wgpu::Color>) {
 		painter.layers[self.0].clear_color = color;
 	}
 
+	/// Selects the array layer, cube face or depth slice the next paint renders into.
+	///
+	/// Binding the layer samples all slices, e.g. a cubemap can be filled by painting each face.
+	pub fn set_target_slice(&self, painter: &mut Painter, slice: u32) {
+		let l = &mut painter.layers[self.0];
+		let slice_count = l.texture_kind.slice_count();
+		if slice >= slice_count {
+			panic!(
+				"Slice {} is out of range for a layer with {} slices",
+				slice, slice_count
+			);
+		}
+		l.target_slice = slice;
+	}
+
 	/// Updates all layer-level bindings at once.
 	///
 	/// Layer-level bindings serve as defaults for all shapes and effects in this layer.
@@ -630,19 +891,36 @@ impl Layer {
 	/// shapes is efficient.
 	///
 	pub fn set_shapes(&self, painter: &mut Painter, shapes: Vec<Shape>) {
+		self.try_set_shapes(painter, shapes)
+			.unwrap_or_else(|err| panic!("{}", err));
+	}
+
+	/// Like `set_shapes`, but returns an error if a shader is missing or does not match its shade.
+	/// The layer keeps its previous shapes in that case.
+	pub fn try_set_shapes(
+		&self,
+		painter: &mut Painter,
+		shapes: Vec<Shape>,
+	) -> Result<(), PainterError> {
+		// Ensure pipelines exist for all shapes (will reuse cached if available)
+		for &shape in &shapes {
+			let key = painter.get_shape_pipeline_key(shape, *self);
+			painter.ensure_shape_pipeline(&key, shape, *self)?;
+		}
+
 		let layer_bindings = painter.layers[self.0].bindings.clone();
 		let layer_layers = painter.layers[self.0].layers.clone();
 
-		painter.layers[self.0].shapes = shapes
+		let new_shapes = shapes
 			.iter()
 			.map(|&shape| ShapeData::new(painter, shape, &layer_bindings, &layer_layers))
 			.collect();
+		let old_shapes = std::mem::replace(&mut painter.layers[self.0].shapes, new_shapes);
 
-		// Ensure pipelines exist for all shapes (will reuse cached if available)
-		for shape in shapes {
-			let key = painter.get_shape_pipeline_key(shape, *self);
-			painter.ensure_shape_pipeline(&key, shape, *self);
+		for sd in old_shapes {
+			BindGroup::free(painter, &sd.bind_groups);
 		}
+		Ok(())
 	}
 
 	/// Adds a single shape to the layer.
@@ -651,37 +929,140 @@ impl Layer {
 	/// If you need to replace all shapes or add multiple shapes at once, use `set_shapes()` instead.
 	///
 	pub fn add_shape(&self, painter: &mut Painter, shape: Shape) {
+		self.try_add_shape(painter, shape)
+			.unwrap_or_else(|err| panic!("{}", err));
+	}
+
+	/// Like `add_shape`, but returns an error if a shader is missing or does not match its shade.
+	/// The shape is not added in that case.
+	pub fn try_add_shape(&self, painter: &mut Painter, shape: Shape) -> Result<(), PainterError> {
+		// Ensure pipeline exists for this shape
+		let key = painter.get_shape_pipeline_key(shape, *self);
+		painter.ensure_shape_pipeline(&key, shape, *self)?;
+
 		let layer_bindings = painter.layers[self.0].bindings.clone();
 		let layer_layers = painter.layers[self.0].layers.clone();
 
 		let shape_data = ShapeData::new(painter, shape, &layer_bindings, &layer_layers);
 		painter.layers[self.0].shapes.push(shape_data);
-
-		// Ensure pipeline exists for this shape
-		let key = painter.get_shape_pipeline_key(shape, *self);
-		painter.ensure_shape_pipeline(&key, shape, *self);
+		Ok(())
 	}
 
 	/// Removes a specific shape from the layer.
 	///
 	/// This filters out all occurrences of the given shape from the layer's shape list.
 	/// If the shape appears multiple times, all instances will be removed.
+	/// The shape itself stays alive and can be added again, use `Shape::destroy` to free it.
 	///
 	pub fn remove_shape(&self, painter: &mut Painter, shape: Shape) {
-		painter.layers[self.0]
+		let (removed, kept) = painter.layers[self.0]
 			.shapes
-			.retain(|sd| sd.shape.0 != shape.0);
+			.drain(..)
+			.partition::<Vec<_>, _>(|sd| sd.shape.0 == shape.0);
+		painter.layers[self.0].shapes = kept;
+
+		for sd in removed {
+			BindGroup::free(painter, &sd.bind_groups);
+		}
 	}
 
+	/// Removes a specific effect from the layer.
+	///
+	/// The effect itself stays alive, use `Effect::destroy` to free it.
+	///
+	pub(crate) fn remove_effect(&self, painter: &mut Painter, effect: Effect) {
+		let (removed, kept) = painter.layers[self.0]
+			.effects
+			.drain(..)
+			.partition::<Vec<_>, _>(|ed| ed.effect.0 == effect.0);
+		painter.layers[self.0].effects = kept;
+
+		for ed in removed {
+			BindGroup::free(painter, &ed.bind_groups);
+		}
+	}
+
+	/// Frees the layer's render targets and bind groups.
+	///
+	/// Shapes and effects rendered by this layer are not destroyed,
+	/// but any other shape, effect or layer still binding this layer will panic when rendered.
+	///
+	/// If other layers share the depth texture of this layer, it is kept alive and handed over
+	/// to the first of them, which then clears and resizes it.
+	///
+	pub fn destroy(self, painter: &mut Painter) {
+		let Some(storage) = painter.layers.remove(self.0) else {
+			return;
+		};
+
+		let depth_sharers = self.depth_sharers(painter);
+		if let Some((&new_owner, others)) = depth_sharers.split_first() {
+			let owner = &mut painter.layers[new_owner.0];
+			owner.shared_depth = None;
+			owner.depth_clear = storage.depth_clear;
+			owner.stencil_clear = storage.stencil_clear;
+			for layer in others {
+				painter.layers[layer.0].shared_depth = Some(new_owner);
+			}
+		}
+
+		if painter.shown_layer == Some(self) {
+			painter.shown_layer = None;
+		}
+
+		for sd in &storage.shapes {
+			BindGroup::free(painter, &sd.bind_groups);
+		}
+		for ed in &storage.effects {
+			BindGroup::free(painter, &ed.bind_groups);
+		}
+
+		let textures = storage
+			.target_textures
+			.iter()
+			.chain(
+				storage
+					.depth_texture
+					.iter()
+					.filter(|_| storage.shared_depth.is_none() && depth_sharers.is_empty()),
+			)
+			.chain(storage.multisampled_textures.iter());
+		for texture in textures {
+			texture.destroy(painter);
+		}
+
+		painter.cleanup_pipelines();
+	}
+
+	/// Resizes the layer's textures, 0 for width or height follows the window size.
+	///
+	/// Layers sharing a depth texture are always resized together.
 	pub fn resize(&mut self, painter: &mut Painter, width: u32, height: u32) {
+		let owner = painter.layers[self.0].shared_depth.unwrap_or(*self);
+		for layer in std::iter::once(owner).chain(owner.depth_sharers(painter)) {
+			layer.resize_textures(painter, width, height);
+		}
+	}
+
+	/// The layers rendering into the depth texture owned by this layer.
+	fn depth_sharers(&self, painter: &Painter) -> Vec<Layer> {
+		painter
+			.layers
+			.iter()
+			.filter_map(|(h, l)| (l.shared_depth == Some(*self)).then_some(Layer(h)))
+			.collect()
+	}
+
+	fn resize_textures(&self, painter: &mut Painter, width: u32, height: u32) {
 		let use_window_size = width == 0 || height == 0;
+		let window_size = painter.render_size();
 		let width = if use_window_size {
-			painter.config.width
+			window_size.width
 		} else {
 			width
 		};
 		let height = if use_window_size {
-			painter.config.height
+			window_size.height
 		} else {
 			height
 		};
@@ -696,42 +1077,49 @@ impl Layer {
 		storage.use_window_size = use_window_size;
 
 		let targets = storage.target_textures.clone();
-		let depth_texture = storage.depth_texture.clone();
+		let depth_texture = storage
+			.depth_texture
+			.filter(|_| storage.shared_depth.is_none());
 		let multisampled_textures = storage.multisampled_textures.clone();
+		let sample_count = storage.sample_count;
 		let mips = storage.mips;
 
 		for texture in targets.iter() {
-			let format = painter.textures[texture.0].texture.format();
-			texture.replace_2d(
+			let t = &painter.textures[texture.0].texture;
+			let format = t.format();
+			let usage = t.usage();
+			let kind = painter.textures[texture.0].kind;
+			texture.replace(
 				painter,
 				width,
 				height,
-				Texture2DProps {
+				TextureProps {
 					format,
-					usage: wgpu::TextureUsages::RENDER_ATTACHMENT
-						| wgpu::TextureUsages::TEXTURE_BINDING,
+					usage,
 					mips,
+					kind,
 				},
-				false,
+				1,
 			);
 		}
 
 		if let Some(depth_texture) = depth_texture {
-			depth_texture.replace_depth(painter, width, height, !multisampled_textures.is_empty());
+			depth_texture.replace_depth(painter, width, height, sample_count);
 		}
 
 		for t in multisampled_textures {
 			let format = painter.textures[t.0].texture.format();
-			t.replace_2d(
+			t.replace(
 				painter,
 				width,
 				height,
-				Texture2DProps {
+				TextureProps {
 					format,
 					usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
 					mips: None,
+					kind: TextureKind::D2,
 				},
-				true,
+				sample_count,
 			);
 		}
 
@@ -752,6 +1140,55 @@ impl Layer {
 		let storage = &painter.layers[self.0];
 		storage.target_textures[0].get_mip_level_count(painter)
 	}
+
+	/// Reads the current layer texture back to CPU memory.
+	///
+	/// This is the same texture that is used by `Layer::binding`.
+	/// The bytes are tightly packed rows in the layer's texture format.
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn read_pixels(&self, painter: &Painter) -> Vec<u8> {
+		painter.layers[self.0]
+			.current_source_texture()
+			.read_pixels(painter, 0)
+	}
+
+	/// Reads a mip level of the target texture at the given index back to CPU memory.
+	/// Used for layers with multiple targets, like `Layer::binding_at`.
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn read_pixels_at(&self, painter: &Painter, index: usize, mip_level: u32) -> Vec<u8> {
+		painter.layers[self.0].target_textures[index].read_pixels(painter, mip_level)
+	}
+
+	/// Reads a mip level of the current layer texture back to CPU memory.
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn read_pixels_at_mip_level(&self, painter: &Painter, mip_level: u32) -> Vec<u8> {
+		painter.layers[self.0]
+			.current_source_texture()
+			.read_pixels(painter, mip_level)
+	}
+
+	/// Writes the current layer texture to a PNG file.
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn save_png(
+		&self,
+		painter: &Painter,
+		path: impl AsRef<std::path::Path>,
+	) -> std::io::Result<()> {
+		painter.layers[self.0]
+			.current_source_texture()
+			.save_png(painter, 0, path)
+	}
+
+	/// Writes the target texture at the given index to a PNG file.
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn save_png_at(
+		&self,
+		painter: &Painter,
+		index: usize,
+		path: impl AsRef<std::path::Path>,
+	) -> std::io::Result<()> {
+		painter.layers[self.0].target_textures[index].save_png(painter, 0, path)
+	}
 }
 
 /// A builder for creating a new [`Layer`].
@@ -761,7 +1198,10 @@ impl Layer {
 /// - `layer_layout`: BINDING_LAYER_FRAG
 /// - `clear_color`: None
 /// - `depth_test`: false
-/// - `multisampled`: false
+/// - `depth_format`: Depth24Plus
+/// - `depth_clear`: 1.0, `stencil_clear`: 0
+/// - `sample_count`: 1
+/// - `texture_kind`: D2
 ///
 /// # Example
 /// ```
@@ -789,6 +1229,10 @@ impl<'a, 'b> LayerBuilder<'a, 'b> {
 		Layer::new(self.painter, self.props)
 	}
 
+	pub fn try_create(self) -> Result<Layer, PainterError> {
+		Layer::try_new(self.painter, self.props)
+	}
+
 	/// Creates a layer and initializes the its gpu pipelines.
 	///
 	/// Layers created in the App::init function are automatically initialized.
@@ -802,6 +1246,12 @@ impl<'a, 'b> LayerBuilder<'a, 'b> {
 		layer
 	}
 
+	pub fn try_create_and_init(self) -> Result<Layer, PainterError> {
+		let layer = Layer::try_new(self.painter, self.props)?;
+		layer.try_init_gpu_pipelines(self.painter)?;
+		Ok(layer)
+	}
+
 	/// Creates a layer, initializes the its gpu pipelines and immediately renders it.
 	pub fn create_and_paint(self) -> Layer {
 		let layer = Layer::new(self.painter, self.props);
@@ -810,6 +1260,13 @@ impl<'a, 'b> LayerBuilder<'a, 'b> {
 		layer
 	}
 
+	pub fn try_create_and_paint(self) -> Result<Layer, PainterError> {
+		let layer = Layer::try_new(self.painter, self.props)?;
+		layer.try_init_gpu_pipelines(self.painter)?;
+		self.painter.paint(layer);
+		Ok(layer)
+	}
+
 	pub fn with_static_texture(mut self) -> Self {
 		self.props.static_texture = true;
 		self
@@ -900,6 +1357,40 @@ impl<'a, 'b> LayerBuilder<'a, 'b> {
 		self
 	}
 
+	/// Enables depth test with the given depth format,
+	/// e.g. `Depth32Float` for precision or `Depth24PlusStencil8` for stencil masking.
+	pub fn with_depth_format(mut self, format: wgpu::TextureFormat) -> Self {
+		self.props.depth_test = true;
+		self.props.depth_format = format;
+		self
+	}
+
+	/// Keeps the previous depth and stencil content instead of clearing it before painting.
+	pub fn with_depth_load(mut self) -> Self {
+		self.props.depth_clear = None;
+		self.props.stencil_clear = None;
+		self
+	}
+
+	pub fn with_stencil_clear(mut self, value: u32) -> Self {
+		self.props.stencil_clear = Some(value);
+		self
+	}
+
+	/// Renders into the depth texture of another layer, which has to use depth test
+	/// and the same size and multisampling as this layer.
+	/// Both layers are resized together, either of them can be destroyed first.
+	///
+	/// The depth content is loaded instead of cleared, so shapes of this layer
+	/// are tested against the depth painted by the other layer.
+	pub fn with_shared_depth(mut self, layer: Layer) -> Self {
+		self.props.depth_test = true;
+		self.props.shared_depth = Some(layer);
+		self.props.depth_clear = None;
+		self.props.stencil_clear = None;
+		self
+	}
+
 	pub fn with_layer_layout_vert(mut self) -> Self {
 		self.props.layer_layout = BINDING_LAYER_VERT;
 		self
@@ -910,8 +1401,18 @@ impl<'a, 'b> LayerBuilder<'a, 'b> {
 		self
 	}
 
-	pub fn with_multisampling(mut self) -> Self {
-		self.props.multisampled = true;
+	/// Enables 4x multisampling, which is supported on all platforms.
+	pub fn with_multisampling(self) -> Self {
+		self.with_multisampling_count(4)
+	}
+
+	/// Enables multisampling with the given number of samples per pixel.
+	///
+	/// Counts other than 1 and 4 depend on the adapter and the layer formats. They are available
+	/// where the adapter has the `TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES` feature, which the painter
+	/// enables when present. `try_create` returns an error for unsupported counts, `create` panics.
+	pub fn with_multisampling_count(mut self, count: u32) -> Self {
+		self.props.sample_count = count;
 		self
 	}
 
@@ -924,6 +1425,27 @@ impl<'a, 'b> LayerBuilder<'a, 'b> {
 		self.props.mips = Some(MipMapCount::Max(max));
 		self
 	}
+
+	/// Allows the layer texture to be bound as storage texture in compute shaders.
+	/// The layer format must support storage access, e.g. `Rgba8Unorm` or `Rgba16Float`.
+	pub fn with_storage_texture(mut self) -> Self {
+		self.props.storage_texture = true;
+		self
+	}
+
+	/// Creates 2D array, cubemap or 3D target textures. Shapes render into the slice
+	/// selected with `Layer::set_target_slice`, effects are not supported.
+	///
+	/// Bind the layer with a matching layout, e.g. `BINDING_CUBE_FRAG` for cubemaps.
+	pub fn with_texture_kind(mut self, kind: TextureKind) -> Self {
+		self.props.texture_kind = kind;
+		self
+	}
+
+	pub fn with_label(mut self, label: &str) -> Self {
+		self.props.label = Some(label.to_string());
+		self
+	}
 }
 
 /// A builder for creating a new [`Layer`] with a single [`Effect`].
@@ -962,6 +1484,7 @@ pub struct SingleEffectLayerBuilder<'a> {
 	format: Option<wgpu::TextureFormat>,
 	clear_color: Option<wgpu::Color>,
 	mips: Option<MipMapCount>,
+	label: Option<String>,
 }
 
 impl<'a> SingleEffectLayerBuilder<'a> {
@@ -980,6 +1503,7 @@ impl<'a> SingleEffectLayerBuilder<'a> {
 			format: None,
 			clear_color: None,
 			mips: None,
+			label: None,
 		}
 	}
 
@@ -988,6 +1512,11 @@ impl<'a> SingleEffectLayerBuilder<'a> {
 		Layer::new(painter, props)
 	}
 
+	pub fn try_create(self) -> Result<Layer, PainterError> {
+		let (painter, props) = self.into_layer_props();
+		Layer::try_new(painter, props)
+	}
+
 	/// Creates a layer and immediately initializes its GPU pipelines.
 	///
 	/// Layers created during app initialization are initialized automatically,
@@ -1000,6 +1529,13 @@ impl<'a> SingleEffectLayerBuilder<'a> {
 		layer
 	}
 
+	pub fn try_create_and_init(self) -> Result<Layer, PainterError> {
+		let (painter, props) = self.into_layer_props();
+		let layer = Layer::try_new(painter, props)?;
+		layer.try_init_gpu_pipelines(painter)?;
+		Ok(layer)
+	}
+
 	// Effect builder methods
 
 	pub fn with_bindings<I>(mut self, bindings: I) -> Self
@@ -1078,6 +1614,12 @@ impl<'a> SingleEffectLayerBuilder<'a> {
 		self
 	}
 
+	/// Names both the layer and its effect.
+	pub fn with_label(mut self, label: &str) -> Self {
+		self.label = Some(label.to_string());
+		self
+	}
+
 	fn into_layer_props(self) -> (&'a mut Painter, LayerProps<'a>) {
 		let SingleEffectLayerBuilder {
 			painter,
@@ -1093,6 +1635,7 @@ impl<'a> SingleEffectLayerBuilder<'a> {
 			format,
 			clear_color,
 			mips,
+			label,
 		} = self;
 
 		let effect = Effect::new(
@@ -1105,6 +1648,7 @@ impl<'a> SingleEffectLayerBuilder<'a> {
 				blend_state,
 				dst_mip_level,
 				src_mip_level,
+				label: label.clone(),
 			},
 		);
 
@@ -1126,10 +1670,10 @@ impl<'a> SingleEffectLayerBuilder<'a> {
 				height,
 				formats,
 				clear_color,
-				depth_test: false,
 				layer_layout: BINDING_LAYER_FRAG,
-				multisampled: false,
 				mips,
+				label,
+				..LayerProps::default()
 			},
 		)
 	}
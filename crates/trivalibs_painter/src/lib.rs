@@ -1,3 +1,4 @@
+pub use half;
 pub use wgpu;
 pub use winit;
 
@@ -7,13 +8,20 @@ pub mod app;
 pub mod bind_group;
 pub mod binding;
 pub mod binding_constants;
+pub mod compute;
 #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
 pub(crate) mod dev_state;
 pub mod effect;
+pub mod error;
 pub mod events;
 pub mod form;
 pub mod layer;
 pub(crate) mod pipeline;
+pub(crate) mod pipeline_cache;
+pub mod profiler;
+pub(crate) mod push_constants;
+pub mod reflect;
+pub(crate) mod registry;
 pub mod sampler;
 pub mod shade;
 pub mod shaders;
@@ -26,16 +34,23 @@ pub(crate) mod window_dimensions;
 pub mod prelude {
 	pub use crate::{
 		app::{AppConfig, CanvasApp, Event, KeyCode, PointerButton},
-		binding::{BindingBuffer, InstanceBinding, Mat3U, Vec3U},
+		binding::{BindingBuffer, BindingDynamic, BindingStorage, InstanceBinding, Mat3U, Vec3U},
 		binding_constants::*,
+		compute::{Compute, ComputeShade},
 		effect::EffectProps,
+		error::PainterError,
 		form::FormProps,
 		layer::{Layer, LayerProps},
-		load_fragment_shader, load_vertex_shader,
-		painter::Painter,
+		load_compute_shader, load_fragment_shader, load_vertex_shader,
+		painter::{Painter, PainterConfig, SurfaceFormat},
+		profiler::PassTiming,
 		sampler::{Sampler, SamplerProps},
 		shade::{Shade, ShadeEffectProps, ShadeProps},
 		shape::{Shape, ShapeProps},
+		texture::{TexelComponent, Texture, TextureKind, TextureRegion},
 		wgpu::{self, TextureFormat::*, VertexFormat::*},
 	};
+
+	#[cfg(not(target_arch = "wasm32"))]
+	pub use crate::app::RecordingConfig;
 }
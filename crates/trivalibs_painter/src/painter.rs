@@ -1,18 +1,30 @@
 use crate::{
-	bind_group::{BindGroup, BindGroupLayout, BindGroupStorage, LayerBindGroupData},
-	binding::{BindingBuffer, LayerBinding, Mat3U, ValueBinding, Vec3U},
+	bind_group::{BindGroup, BindGroupLayout, BindGroupStorage},
+	binding::{BindingBuffer, BindingDynamic, BindingStorage, Mat3U, ValueBinding, Vec3U},
+	compute::{
+		Compute, ComputeBuilder, ComputeShade, ComputeShadeBuilder, ComputeShadeStorage,
+		ComputeStorage,
+	},
 	effect::{Effect, EffectBuilder, EffectStorage},
+	error::PainterError,
 	form::{Form, FormBuffer, FormBuilder, FormStorage},
 	layer::{
 		InstanceRenderingStrategy, Layer, LayerBuilder, LayerStorage, SingleEffectLayerBuilder,
 	},
 	pipeline::PipelineStorage,
+	pipeline_cache::{PersistentPipelineCache, hash_shader_code},
 	prelude::{BINDING_LAYER_FRAG, BINDING_SAMPLER_FRAG},
+	profiler::{PassTiming, Profiler},
+	registry::Registry,
 	sampler::{Sampler, SamplerBuilder, SamplerProps},
 	shade::{AttribsFormat, Shade, ShadeBuilder, ShadeEffectBuilder, ShadeStorage},
 	shaders::FULL_SCREEN_QUAD,
 	shape::{Shape, ShapeBuilder, ShapeStorage},
-	texture::{TexViewKey, TextureStorage},
+	texture::{
+		PngSource, TexViewKey, Texture, TextureBuilder, TextureDataBuilder, TextureKind,
+		TextureProps, TextureStorage,
+	},
+	texture_utils::is_float_filterable,
 };
 use std::{collections::BTreeMap, sync::Arc};
 use trivalibs_core::{
@@ -23,9 +35,12 @@ use wgpu::RenderPassColorAttachment;
 use winit::window::Window;
 
 pub(crate) const FULL_SCREEN_TEXTURE_PIPELINE: &'static [u8] = &[0xff, 0xff];
+/// Fullscreen texture pipeline for textures that are not filterable, e.g. `Rgba32Float`.
+pub(crate) const FULL_SCREEN_UNFILTERED_TEXTURE_PIPELINE: &[u8] = &[0xff, 0xfd];
 
 pub struct Painter {
-	pub surface: wgpu::Surface<'static>,
+	/// The window surface. `None` for headless painters.
+	pub surface: Option<wgpu::Surface<'static>>,
 	pub config: wgpu::SurfaceConfiguration,
 	pub adapter: wgpu::Adapter,
 	pub device: wgpu::Device,
@@ -33,35 +48,267 @@ pub struct Painter {
 
 	pub surface_error: Option<wgpu::SurfaceError>,
 
-	window: Arc<Window>,
-	pub(crate) forms: Vec<FormStorage>,
-	pub(crate) shades: Vec<ShadeStorage>,
-	pub(crate) textures: Vec<TextureStorage>,
-	pub(crate) buffers: Vec<wgpu::Buffer>,
+	window: Option<Arc<Window>>,
+	pub(crate) forms: Registry<FormStorage>,
+	pub(crate) shades: Registry<ShadeStorage>,
+	pub(crate) textures: Registry<TextureStorage>,
+	pub(crate) buffers: Registry<wgpu::Buffer>,
 	pub(crate) samplers: Vec<wgpu::Sampler>,
-	pub(crate) shapes: Vec<ShapeStorage>,
-	pub(crate) effects: Vec<EffectStorage>,
-	pub(crate) layers: Vec<LayerStorage>,
-	pub(crate) bind_groups: Vec<BindGroupStorage>,
+	pub(crate) shapes: Registry<ShapeStorage>,
+	pub(crate) effects: Registry<EffectStorage>,
+	pub(crate) layers: Registry<LayerStorage>,
+	pub(crate) bind_groups: Registry<BindGroupStorage>,
 	pub(crate) bind_group_layouts: Vec<wgpu::BindGroupLayout>,
+	/// Uniform layouts replacing push constants, by shader stages and size, see `PushConstantsLayout`.
+	pub(crate) push_constants_fallback_layouts: BTreeMap<(u32, u32), BindGroupLayout>,
 	pub(crate) pipelines: BTreeMap<Vec<u8>, PipelineStorage>,
+	pub(crate) compute_shades: Registry<ComputeShadeStorage>,
+	pub(crate) computes: Registry<ComputeStorage>,
 	pub(crate) fullscreen_quad_shader: wgpu::ShaderModule,
+	/// Offscreen texture that `show` renders into when there is no surface.
+	pub(crate) headless_target: Option<Texture>,
+	/// Size of window sized layers if it differs from the surface, see `set_render_size`.
+	render_size: Option<(u32, u32)>,
+	/// The layer passed to the last `show` call.
+	pub(crate) shown_layer: Option<Layer>,
+	/// Recording state while inside `Painter::frame`.
+	frame: Option<FrameState>,
+	profiler: Profiler,
+	/// Applied to the shown layer, depends on the surface format, see `SurfaceFormat`.
+	pub(crate) show_transfer: ShowTransfer,
+	/// See `PainterConfig::pipeline_cache_key`.
+	pipeline_cache: Option<PersistentPipelineCache>,
+	/// Upload buffers of `Texture::write_region_staged`, created on first use.
+	pub(crate) staging_belt: Option<wgpu::util::StagingBelt>,
+}
+
+/// Commands recorded during a `Painter::frame` call, submitted together at its end.
+#[derive(Default)]
+struct FrameState {
+	encoder: Option<wgpu::CommandEncoder>,
+	/// The surface texture acquired by `show`, presented after submission.
+	surface_texture: Option<wgpu::SurfaceTexture>,
+}
+
+/// Preferred format of the window surface, and how the shown layer is encoded for it.
+///
+/// Formats that are not supported by the surface fall back to `Srgb`,
+/// except `Linear`, which keeps display encoded layers, see its docs.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum SurfaceFormat {
+	/// The first format reported by the surface, shown layers are written as is.
+	#[default]
+	Auto,
+	/// An sRGB format, shown layers contain linear colors that are encoded by the GPU.
+	/// Where only linear 8-bit formats are available, e.g. in browsers,
+	/// the show pass applies the sRGB transfer function itself.
+	Srgb,
+	/// An 8-bit linear format, shown layers contain display encoded colors and are written as is.
+	/// Without one, the first format of the surface is used. If that format encodes or expects
+	/// linear colors, i.e. an sRGB or float format, the show pass decodes the layer first.
+	Linear,
+	/// `Rgba16Float` with linear colors in the extended sRGB color space (scRGB).
+	/// Shown layers are written as is and can exceed 1.0 for HDR displays.
+	HdrLinear,
+	/// `Rgba16Float` with extended sRGB encoded colors, as expected by platforms that
+	/// use a non-linear color space for float surfaces. Shown layers contain linear colors,
+	/// the show pass applies the sRGB transfer function, mirrored for negative values.
+	HdrExtendedSrgb,
+}
+
+impl SurfaceFormat {
+	/// Picks a format from the ones supported by the surface, in order of preference.
+	fn choose(self, formats: &[wgpu::TextureFormat]) -> wgpu::TextureFormat {
+		use wgpu::TextureFormat::*;
+		let find = |pred: fn(&wgpu::TextureFormat) -> bool| formats.iter().copied().find(pred);
+
+		let format = match self {
+			SurfaceFormat::Auto => Some(formats[0]),
+			SurfaceFormat::Srgb => {
+				find(|f| f.is_srgb()).or(find(|f| matches!(f, Bgra8Unorm | Rgba8Unorm)))
+			}
+			SurfaceFormat::Linear => find(|f| matches!(f, Bgra8Unorm | Rgba8Unorm)),
+			SurfaceFormat::HdrLinear | SurfaceFormat::HdrExtendedSrgb => {
+				find(|f| *f == Rgba16Float)
+			}
+		};
+
+		if let Some(format) = format {
+			return format;
+		}
+		if self == SurfaceFormat::Srgb {
+			return formats[0];
+		}
+		if self == SurfaceFormat::Linear {
+			log::warn!(
+				"Surface format {:?} is not supported, using {:?}",
+				self,
+				formats[0]
+			);
+			return formats[0];
+		}
+		log::warn!("Surface format {:?} is not supported, using sRGB", self);
+		SurfaceFormat::Srgb.choose(formats)
+	}
+
+	/// The transfer function the show pass applies for this preference and the chosen format.
+	/// Preferences for linear colors that fell back to a linear 8-bit format are encoded as sRGB,
+	/// display encoded colors that fell back to an sRGB or float format are decoded.
+	fn transfer(self, format: wgpu::TextureFormat) -> ShowTransfer {
+		use wgpu::TextureFormat::*;
+		match (self, format) {
+			(SurfaceFormat::Linear, format) if format.is_srgb() || format == Rgba16Float => {
+				ShowTransfer::SrgbDecode
+			}
+			(
+				SurfaceFormat::Srgb | SurfaceFormat::HdrLinear | SurfaceFormat::HdrExtendedSrgb,
+				Bgra8Unorm | Rgba8Unorm,
+			) => ShowTransfer::Srgb,
+			(SurfaceFormat::HdrExtendedSrgb, Rgba16Float) => ShowTransfer::Srgb,
+			_ => ShowTransfer::None,
+		}
+	}
+}
+
+/// Transfer function applied to the shown layer by the show pass, see `SurfaceFormat`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum ShowTransfer {
+	None,
+	/// Encodes linear colors with the extended sRGB transfer function.
+	Srgb,
+	/// Decodes sRGB encoded colors to linear, the inverse of `Srgb`.
+	SrgbDecode,
 }
 
-pub(crate) struct PainterConfig {
+/// Uses the requested alpha mode if the surface supports it, the first supported mode otherwise.
+fn choose_alpha_mode(
+	alpha_modes: &[wgpu::CompositeAlphaMode],
+	requested: wgpu::CompositeAlphaMode,
+) -> wgpu::CompositeAlphaMode {
+	if alpha_modes.contains(&requested) {
+		return requested;
+	}
+	if requested != wgpu::CompositeAlphaMode::Auto {
+		log::warn!(
+			"Surface alpha mode {:?} is not supported, using {:?}",
+			requested,
+			alpha_modes[0]
+		);
+	}
+	alpha_modes[0]
+}
+
+/// Whether the alpha mode composites the surface with the content behind the window.
+pub(crate) fn is_transparent_alpha_mode(alpha_mode: wgpu::CompositeAlphaMode) -> bool {
+	matches!(
+		alpha_mode,
+		wgpu::CompositeAlphaMode::PreMultiplied | wgpu::CompositeAlphaMode::PostMultiplied
+	)
+}
+
+pub struct PainterConfig {
 	pub use_vsync: bool,
 	pub features: Option<wgpu::Features>,
+	pub surface_format: SurfaceFormat,
+	/// Alpha mode of the surface, `PreMultiplied` or `PostMultiplied` for transparent windows.
+	/// The show pass writes the shown layer without blending, so with `PreMultiplied`
+	/// the layer has to contain colors premultiplied by alpha.
+	/// Defaults to `Auto`, the first mode supported by the surface.
+	pub alpha_mode: wgpu::CompositeAlphaMode,
+	/// Persists compiled pipelines between runs under this name, in the user config directory.
+	/// Only used on adapters that support pipeline caches (Vulkan). Defaults to `None`.
+	///
+	/// The saved pipelines are discarded as a whole when any shader changed since the last run.
+	pub pipeline_cache_key: Option<String>,
+}
+
+impl Default for PainterConfig {
+	fn default() -> Self {
+		Self {
+			use_vsync: true,
+			features: None,
+			surface_format: SurfaceFormat::Auto,
+			alpha_mode: wgpu::CompositeAlphaMode::Auto,
+			pipeline_cache_key: None,
+		}
+	}
+}
+
+async fn request_device(
+	adapter: &wgpu::Adapter,
+	painter_config: &PainterConfig,
+) -> Result<(wgpu::Device, wgpu::Queue), PainterError> {
+	// Request compute capable limits where the adapter supports them (everything but WebGL).
+	// Enable non-fill polygon modes where available, shapes fall back to line and point lists otherwise.
+	let polygon_mode_features = adapter.features()
+		& (wgpu::Features::POLYGON_MODE_LINE | wgpu::Features::POLYGON_MODE_POINT);
+	// Enable push constants where available, shades fall back to dynamic-offset uniforms otherwise.
+	let push_constant_features = adapter.features() & wgpu::Features::PUSH_CONSTANTS;
+	// Enable GPU timings where available, profiling falls back to CPU timings otherwise.
+	let timestamp_features = adapter.features() & wgpu::Features::TIMESTAMP_QUERY;
+	// Enable adapter specific format features where available, so layers can use every sample count
+	// the adapter supports. Only the sample counts guaranteed by WebGPU are accepted otherwise.
+	let format_features =
+		adapter.features() & wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
+	// Enable pipeline caches where available and requested, pipelines are compiled on every run otherwise.
+	let pipeline_cache_features = if painter_config.pipeline_cache_key.is_some() {
+		adapter.features() & wgpu::Features::PIPELINE_CACHE
+	} else {
+		wgpu::Features::empty()
+	};
+
+	let mut limits = if adapter
+		.get_downlevel_capabilities()
+		.flags
+		.contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+	{
+		wgpu::Limits::downlevel_defaults()
+	} else {
+		wgpu::Limits::downlevel_webgl2_defaults()
+	};
+	limits.max_push_constant_size = adapter.limits().max_push_constant_size;
+
+	adapter
+		.request_device(&wgpu::DeviceDescriptor {
+			label: None,
+			required_features: painter_config.features.unwrap_or(wgpu::Features::empty())
+				| polygon_mode_features
+				| push_constant_features
+				| timestamp_features
+				| format_features
+				| pipeline_cache_features,
+			// Make sure we use the texture resolution limits from the adapter, so we can support images the size of the swapchain.
+			required_limits: limits.using_resolution(adapter.limits()),
+			memory_hints: wgpu::MemoryHints::MemoryUsage,
+			trace: wgpu::Trace::Off,
+		})
+		.await
+		.map_err(PainterError::from)
+}
+
+fn headless_target_props(format: wgpu::TextureFormat) -> TextureProps {
+	TextureProps {
+		format,
+		usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+			| wgpu::TextureUsages::TEXTURE_BINDING
+			| wgpu::TextureUsages::COPY_SRC,
+		mips: None,
+		kind: TextureKind::D2,
+	}
 }
 
 impl Painter {
-	pub(crate) async fn new(window: Arc<Window>, painter_config: PainterConfig) -> Self {
+	pub(crate) async fn try_new(
+		window: Arc<Window>,
+		painter_config: PainterConfig,
+	) -> Result<Self, PainterError> {
 		let mut size = window.inner_size();
 		size.width = size.width.max(1);
 		size.height = size.height.max(1);
 
 		let instance = wgpu::Instance::default();
 
-		let surface = instance.create_surface(window.clone()).unwrap();
+		let surface = instance.create_surface(window.clone())?;
 		let adapter = instance
 			.request_adapter(&wgpu::RequestAdapterOptions {
 				power_preference: wgpu::PowerPreference::default(),
@@ -69,27 +316,15 @@ impl Painter {
 				// Request an adapter which can render to our surface
 				compatible_surface: Some(&surface),
 			})
-			.await
-			.expect("Failed to find an appropriate adapter");
+			.await?;
 
 		// Create the logical device and command queue
-		let (device, queue) = adapter
-			.request_device(&wgpu::DeviceDescriptor {
-				label: None,
-				required_features: painter_config.features.unwrap_or(wgpu::Features::empty()),
-				// Make sure we use the texture resolution limits from the adapter, so we can support images the size of the swapchain.
-				required_limits: wgpu::Limits::downlevel_webgl2_defaults()
-					.using_resolution(adapter.limits()),
-				memory_hints: wgpu::MemoryHints::MemoryUsage,
-				trace: wgpu::Trace::Off,
-			})
-			.await
-			.expect("Failed to create device");
+		let (device, queue) = request_device(&adapter, &painter_config).await?;
 
 		let surface_caps = surface.get_capabilities(&adapter);
 		let config = wgpu::SurfaceConfiguration {
 			usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-			format: surface_caps.formats[0],
+			format: painter_config.surface_format.choose(&surface_caps.formats),
 			width: size.width,
 			height: size.height,
 			present_mode: if painter_config.use_vsync {
@@ -97,18 +332,119 @@ impl Painter {
 			} else {
 				wgpu::PresentMode::AutoNoVsync
 			},
-			alpha_mode: surface_caps.alpha_modes[0],
+			alpha_mode: choose_alpha_mode(&surface_caps.alpha_modes, painter_config.alpha_mode),
 			view_formats: vec![],
 			desired_maximum_frame_latency: 2,
 		};
+		log::info!(
+			"Surface format: {:?}, alpha mode: {:?}",
+			config.format,
+			config.alpha_mode
+		);
 
 		surface.configure(&device, &config);
 
+		Ok(Self::init(
+			Some(surface),
+			Some(window),
+			config,
+			adapter,
+			device,
+			queue,
+			&painter_config,
+		))
+	}
+
+	/// Creates a painter without a window or surface.
+	///
+	/// Useful for CLI tools, batch renderers and integration tests.
+	/// Layers can be painted and composed as usual. `show` renders into an offscreen texture
+	/// of the given size instead of presenting to a surface.
+	///
+	/// If no hardware adapter is available, a software fallback adapter is used.
+	pub async fn headless(width: u32, height: u32, painter_config: PainterConfig) -> Self {
+		Self::try_headless(width, height, painter_config)
+			.await
+			.unwrap_or_else(|err| panic!("{}", err))
+	}
+
+	/// Like `headless`, but returns an error if no adapter or device is available.
+	pub async fn try_headless(
+		width: u32,
+		height: u32,
+		painter_config: PainterConfig,
+	) -> Result<Self, PainterError> {
+		let instance = wgpu::Instance::default();
+
+		let mut options = wgpu::RequestAdapterOptions {
+			power_preference: wgpu::PowerPreference::default(),
+			force_fallback_adapter: false,
+			compatible_surface: None,
+		};
+
+		let adapter = match instance.request_adapter(&options).await {
+			Ok(adapter) => adapter,
+			Err(_) => {
+				options.force_fallback_adapter = true;
+				instance.request_adapter(&options).await?
+			}
+		};
+
+		let (device, queue) = request_device(&adapter, &painter_config).await?;
+
+		// The offscreen target supports the formats and alpha modes of a typical surface
+		let config = wgpu::SurfaceConfiguration {
+			usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+			format: painter_config.surface_format.choose(&[
+				wgpu::TextureFormat::Rgba8UnormSrgb,
+				wgpu::TextureFormat::Rgba8Unorm,
+				wgpu::TextureFormat::Rgba16Float,
+			]),
+			width: width.max(1),
+			height: height.max(1),
+			present_mode: wgpu::PresentMode::Fifo,
+			alpha_mode: choose_alpha_mode(
+				&[
+					wgpu::CompositeAlphaMode::Opaque,
+					wgpu::CompositeAlphaMode::PreMultiplied,
+					wgpu::CompositeAlphaMode::PostMultiplied,
+				],
+				painter_config.alpha_mode,
+			),
+			view_formats: vec![],
+			desired_maximum_frame_latency: 2,
+		};
+
+		let mut painter = Self::init(None, None, config, adapter, device, queue, &painter_config);
+
+		let props = headless_target_props(painter.config.format);
+		let target = Texture::create(&mut painter, width.max(1), height.max(1), props, 1);
+		painter.headless_target = Some(target);
+
+		Ok(painter)
+	}
+
+	fn init(
+		surface: Option<wgpu::Surface<'static>>,
+		window: Option<Arc<Window>>,
+		config: wgpu::SurfaceConfiguration,
+		adapter: wgpu::Adapter,
+		device: wgpu::Device,
+		queue: wgpu::Queue,
+		painter_config: &PainterConfig,
+	) -> Self {
+		let pipeline_cache = painter_config
+			.pipeline_cache_key
+			.as_ref()
+			.and_then(|key| PersistentPipelineCache::load(key, &adapter.get_info(), &device));
+
 		let fullscreen_quad_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
 			label: Some("Fullscreen Quad Shader"),
 			source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(FULL_SCREEN_QUAD)),
 		});
 
+		let show_transfer = painter_config.surface_format.transfer(config.format);
+
 		let mut painter = Self {
 			surface,
 			config,
@@ -116,28 +452,50 @@ impl Painter {
 			device,
 			queue,
 			surface_error: None,
-			window: window.clone(),
-			forms: Vec::with_capacity(8),
-			shades: Vec::with_capacity(8),
-			textures: Vec::with_capacity(8),
-			buffers: Vec::with_capacity(32),
+			window,
+			forms: Registry::with_capacity(8),
+			shades: Registry::with_capacity(8),
+			textures: Registry::with_capacity(8),
+			buffers: Registry::with_capacity(32),
 			samplers: Vec::with_capacity(8),
-			shapes: Vec::with_capacity(8),
-			effects: Vec::with_capacity(8),
-			layers: Vec::with_capacity(8),
+			shapes: Registry::with_capacity(8),
+			effects: Registry::with_capacity(8),
+			layers: Registry::with_capacity(8),
 			bind_group_layouts: Vec::with_capacity(8),
-			bind_groups: Vec::with_capacity(8),
+			push_constants_fallback_layouts: BTreeMap::new(),
+			bind_groups: Registry::with_capacity(8),
 			pipelines: BTreeMap::new(),
+			compute_shades: Registry::with_capacity(0),
+			computes: Registry::with_capacity(0),
 			fullscreen_quad_shader,
+			headless_target: None,
+			render_size: None,
+			shown_layer: None,
+			frame: None,
+			profiler: Profiler::default(),
+			show_transfer,
+			pipeline_cache,
+			staging_belt: None,
 		};
 
 		Sampler::create(&mut painter, SamplerProps::NEAREST);
 		Sampler::create(&mut painter, SamplerProps::LINEAR);
 
+		// Bind group layouts 0 and 1, used by the fullscreen texture pipelines,
+		// and 2 and 3 for their variant with unfilterable textures
 		let layer_sampler_layout =
 			BindGroupLayout::values(&mut painter, &[BINDING_SAMPLER_FRAG]).unwrap();
-		let layer_texture_layout =
-			BindGroupLayout::layers(&mut painter, &[BINDING_LAYER_FRAG]).unwrap();
+		BindGroupLayout::layers(&mut painter, &[BINDING_LAYER_FRAG]).unwrap();
+		let unfiltered_sampler_layout = BindGroupLayout::values(
+			&mut painter,
+			&[BINDING_SAMPLER_FRAG.for_format(wgpu::TextureFormat::Rgba32Float)],
+		)
+		.unwrap();
+		BindGroupLayout::layers(
+			&mut painter,
+			&[BINDING_LAYER_FRAG.for_format(wgpu::TextureFormat::Rgba32Float)],
+		)
+		.unwrap();
 
 		BindGroup::values_bind_groups(
 			&mut painter,
@@ -157,57 +515,20 @@ impl Painter {
 			&vec![(0, Sampler(1).binding())],
 		);
 
-		let fullscreen_quad_pipeline_layout =
-			painter
-				.device
-				.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-					label: None,
-					bind_group_layouts: &[
-						&painter.bind_group_layouts[layer_sampler_layout.0],
-						&painter.bind_group_layouts[layer_texture_layout.0],
-					],
-					push_constant_ranges: &[],
-				});
+		BindGroup::values_bind_groups(
+			&mut painter,
+			1,
+			Some(unfiltered_sampler_layout),
+			&Vec::with_capacity(0),
+			&Vec::with_capacity(0),
+			&[(0, Sampler(0).binding())],
+		);
 
-		let fullscreen_quad_pipeline =
-			painter
-				.device
-				.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-					label: None,
-					layout: Some(&fullscreen_quad_pipeline_layout),
-					vertex: wgpu::VertexState {
-						module: &painter.fullscreen_quad_shader,
-						entry_point: Some("vs_main"),
-						buffers: &[],
-						compilation_options: default(),
-					},
-					fragment: Some(wgpu::FragmentState {
-						module: &painter.fullscreen_quad_shader,
-						entry_point: Some("fs_main"),
-						targets: &[Some(wgpu::ColorTargetState {
-							format: painter.config.format,
-							blend: Some(wgpu::BlendState::REPLACE),
-							write_mask: wgpu::ColorWrites::ALL,
-						})],
-						compilation_options: default(),
-					}),
-					primitive: wgpu::PrimitiveState {
-						topology: wgpu::PrimitiveTopology::TriangleStrip,
-						strip_index_format: None,
-						front_face: wgpu::FrontFace::Cw,
-						cull_mode: None,
-						polygon_mode: wgpu::PolygonMode::Fill,
-						..default()
-					},
-					depth_stencil: None,
-					multisample: wgpu::MultisampleState {
-						count: 1,
-						mask: !0,
-						alpha_to_coverage_enabled: false,
-					},
-					multiview: None,
-					cache: None,
-				});
+		let fullscreen_quad_pipeline = painter.create_fullscreen_texture_pipeline(
+			painter.config.format,
+			true,
+			painter.show_transfer,
+		);
 
 		painter.pipelines.insert(
 			FULL_SCREEN_TEXTURE_PIPELINE.to_vec(),
@@ -219,11 +540,140 @@ impl Painter {
 			},
 		);
 
+		let unfiltered_quad_pipeline = painter.create_fullscreen_texture_pipeline(
+			painter.config.format,
+			false,
+			painter.show_transfer,
+		);
+
+		painter.pipelines.insert(
+			FULL_SCREEN_UNFILTERED_TEXTURE_PIPELINE.to_vec(),
+			PipelineStorage {
+				pipeline: unfiltered_quad_pipeline,
+				layer: None,
+				shape: None,
+				effect: None,
+			},
+		);
+
 		painter
 	}
 
-	pub fn window(&self) -> &Arc<Window> {
-		&self.window
+	/// Creates a pipeline that copies a texture onto a fullscreen target of the given format,
+	/// used to show layers on the surface and to generate mipmaps.
+	/// Unfilterable textures are bound with the layouts 2 and 3 and a non-filtering sampler.
+	/// The transfer selects the fragment entry point, see `ShowTransfer`.
+	pub(crate) fn create_fullscreen_texture_pipeline(
+		&self,
+		format: wgpu::TextureFormat,
+		filterable: bool,
+		transfer: ShowTransfer,
+	) -> wgpu::RenderPipeline {
+		let (sampler_layout, layer_layout) = if filterable { (0, 1) } else { (2, 3) };
+		let pipeline_layout = self
+			.device
+			.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+				label: None,
+				bind_group_layouts: &[
+					&self.bind_group_layouts[sampler_layout],
+					&self.bind_group_layouts[layer_layout],
+				],
+				push_constant_ranges: &[],
+			});
+
+		self.device
+			.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+				label: None,
+				layout: Some(&pipeline_layout),
+				vertex: wgpu::VertexState {
+					module: &self.fullscreen_quad_shader,
+					entry_point: Some("vs_main"),
+					buffers: &[],
+					compilation_options: default(),
+				},
+				fragment: Some(wgpu::FragmentState {
+					module: &self.fullscreen_quad_shader,
+					entry_point: Some(match transfer {
+						ShowTransfer::None => "fs_main",
+						ShowTransfer::Srgb => "fs_main_srgb",
+						ShowTransfer::SrgbDecode => "fs_main_srgb_decode",
+					}),
+					targets: &[Some(wgpu::ColorTargetState {
+						format,
+						blend: None,
+						write_mask: wgpu::ColorWrites::ALL,
+					})],
+					compilation_options: default(),
+				}),
+				primitive: wgpu::PrimitiveState {
+					topology: wgpu::PrimitiveTopology::TriangleStrip,
+					strip_index_format: None,
+					front_face: wgpu::FrontFace::Cw,
+					cull_mode: None,
+					polygon_mode: wgpu::PolygonMode::Fill,
+					..default()
+				},
+				depth_stencil: None,
+				multisample: wgpu::MultisampleState {
+					count: 1,
+					mask: !0,
+					alpha_to_coverage_enabled: false,
+				},
+				multiview: None,
+				cache: self.pipeline_cache(),
+			})
+	}
+
+	/// The cache to create pipelines with, see `PainterConfig::pipeline_cache_key`.
+	pub(crate) fn pipeline_cache(&self) -> Option<&wgpu::PipelineCache> {
+		self.pipeline_cache.as_ref().map(|c| &c.cache)
+	}
+
+	/// Hash of all shader code, to detect if a persisted pipeline cache is outdated.
+	fn shader_hash(&self) -> u64 {
+		let shades = self
+			.shades
+			.iter()
+			.flat_map(|(_, s)| [s.vertex_code.as_ref(), s.fragment_code.as_ref()]);
+		let compute_shades = self
+			.compute_shades
+			.iter()
+			.map(|(_, s)| s.compute_code.as_ref());
+		hash_shader_code(shades.chain(compute_shades).flatten().map(|c| c.as_bytes()))
+	}
+
+	/// Discards the loaded pipeline cache if the shaders changed since it was saved.
+	/// Must be called before creating shader pipelines.
+	pub(crate) fn check_pipeline_cache(&mut self) {
+		if self
+			.pipeline_cache
+			.as_ref()
+			.is_some_and(|c| c.needs_shader_check())
+		{
+			let hash = self.shader_hash();
+			let cache = self.pipeline_cache.as_mut().unwrap();
+			cache.check_shaders(&self.device, hash);
+		}
+	}
+
+	/// Writes the pipeline cache to disk, so the next run can skip compiling pipelines.
+	/// Called automatically when a `CanvasApp` exits.
+	/// Does nothing if no `PainterConfig::pipeline_cache_key` is set or the adapter has no cache support.
+	pub fn save_pipeline_cache(&self) {
+		if let Some(cache) = &self.pipeline_cache
+			&& let Err(err) = cache.save(self.shader_hash())
+		{
+			log::error!("Failed to save pipeline cache: {}", err);
+		}
+	}
+
+	/// The window this painter renders to. `None` for headless painters.
+	pub fn window(&self) -> Option<&Arc<Window>> {
+		self.window.as_ref()
+	}
+
+	pub fn is_headless(&self) -> bool {
+		self.surface.is_none()
 	}
 
 	// form helpers
@@ -259,6 +709,17 @@ impl Painter {
 		SamplerBuilder::new(self)
 	}
 
+	pub fn texture<'b>(&mut self, width: u32, height: u32) -> TextureDataBuilder<'_, 'b> {
+		TextureDataBuilder::new(self, width, height)
+	}
+
+	pub fn texture_from_png<'b>(
+		&mut self,
+		source: impl Into<PngSource<'b>>,
+	) -> TextureBuilder<'_, 'b> {
+		TextureBuilder::new(self, source)
+	}
+
 	pub fn sampler_nearest(&self) -> Sampler {
 		Sampler(0)
 	}
@@ -287,11 +748,28 @@ impl Painter {
 		SingleEffectLayerBuilder::new(self, shade)
 	}
 
+	// compute utils
+
+	pub fn compute_shade(&mut self) -> ComputeShadeBuilder<'_> {
+		ComputeShadeBuilder::new(self)
+	}
+
+	pub fn compute(&mut self, shade: ComputeShade) -> ComputeBuilder<'_> {
+		ComputeBuilder::new(self, shade)
+	}
+
 	// binding utils
 
 	pub fn bind_buff<T: bytemuck::Pod>(&mut self, data: T) -> BindingBuffer<T> {
 		BindingBuffer::new(self, data)
 	}
+	/// A pool of `count` uniforms in one buffer, see [`BindingDynamic`].
+	pub fn bind_dynamic<T: bytemuck::Pod>(&mut self, count: usize) -> BindingDynamic<T> {
+		BindingDynamic::new(self, count)
+	}
+	pub fn bind_storage<T: bytemuck::Pod>(&mut self, data: &[T]) -> BindingStorage<T> {
+		BindingStorage::new(self, data)
+	}
 	pub fn bind_mat3(&mut self) -> BindingBuffer<Mat3U> {
 		self.bind_buff(Mat3U(Mat3A::IDENTITY))
 	}
@@ -358,28 +836,88 @@ impl Painter {
 	// general utils
 
 	pub fn request_next_frame(&self) {
-		self.window.request_redraw();
+		if let Some(window) = &self.window {
+			window.request_redraw();
+		}
 	}
 
 	pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
 		self.config.width = new_size.width.max(1);
 		self.config.height = new_size.height.max(1);
-		self.surface.configure(&self.device, &self.config);
 
-		let layer_idxs: Vec<usize> = self
+		if let Some(surface) = &self.surface {
+			surface.configure(&self.device, &self.config);
+		}
+
+		if let Some(target) = self.headless_target {
+			let (width, height) = (self.config.width, self.config.height);
+			let props = headless_target_props(self.config.format);
+			target.replace(self, width, height, props, 1);
+		}
+
+		self.resize_window_sized_layers();
+	}
+
+	/// Renders window sized layers at the given size instead of the surface size,
+	/// e.g. to record frames at a fixed resolution. `show` scales them to the surface.
+	/// `None` resets them to the surface size.
+	pub fn set_render_size(&mut self, size: Option<winit::dpi::PhysicalSize<u32>>) {
+		self.render_size = size.map(|s| (s.width.max(1), s.height.max(1)));
+		self.resize_window_sized_layers();
+	}
+
+	/// The size of window sized layers.
+	pub fn render_size(&self) -> winit::dpi::PhysicalSize<u32> {
+		let (width, height) = self
+			.render_size
+			.unwrap_or((self.config.width, self.config.height));
+		winit::dpi::PhysicalSize::new(width, height)
+	}
+
+	fn resize_window_sized_layers(&mut self) {
+		let layer_handles: Vec<_> = self
 			.layers
 			.iter()
-			.enumerate()
-			.filter_map(|(i, l)| if l.use_window_size { Some(i) } else { None })
+			.filter_map(|(h, l)| if l.use_window_size { Some(h) } else { None })
 			.collect();
 
-		for idx in layer_idxs {
-			Layer(idx).resize(self, 0, 0);
+		for h in layer_handles {
+			Layer(h).resize(self, 0, 0);
 		}
 	}
 
+	/// The layer passed to the last `show` call.
+	pub fn shown_layer(&self) -> Option<Layer> {
+		self.shown_layer
+	}
+
+	/// Enables timing of the shape and effect passes of all painted layers, see `frame_timings`.
+	///
+	/// Passes are timed on the GPU with timestamp queries if the device supports
+	/// `Features::TIMESTAMP_QUERY`, and on the CPU while recording them in any case.
+	pub fn set_profiling(&mut self, enabled: bool) {
+		if enabled != self.profiler.is_enabled() {
+			self.profiler
+				.set_enabled(&self.device, &self.queue, enabled);
+		}
+	}
+
+	/// The pass timings of the last profiled frame whose GPU timings are resolved,
+	/// in the order the passes were painted.
+	///
+	/// A frame ends with `Painter::frame`, or with `show` when painting outside of it.
+	/// GPU timings are read back asynchronously, so they lag a few frames behind.
+	/// Empty if profiling is disabled, see `set_profiling`.
+	pub fn frame_timings(&mut self) -> &[PassTiming] {
+		self.profiler.collect(&self.device);
+		self.profiler.timings()
+	}
+
 	pub fn canvas_size(&self) -> winit::dpi::PhysicalSize<u32> {
-		self.window.inner_size()
+		self.window.as_ref().map_or(
+			winit::dpi::PhysicalSize::new(self.config.width, self.config.height),
+			|w| w.inner_size(),
+		)
 	}
 
 	pub(crate) fn get_shape_pipeline_key(&self, shape: Shape, layer: Layer) -> Vec<u8> {
@@ -388,16 +926,20 @@ impl Painter {
 		[sp.pipeline_key.as_slice(), l.pipeline_key.as_slice()].concat()
 	}
 
+	/// Creates the pipeline if it is not cached yet, after validating the shader interface.
 	pub(crate) fn ensure_shape_pipeline<'a>(
 		&'a mut self,
 		pipeline_key: &Vec<u8>,
 		shape: Shape,
 		layer: Layer,
-	) {
+	) -> Result<(), PainterError> {
 		if !self.pipelines.contains_key(pipeline_key) {
-			let pipeline = PipelineStorage::create_shape_pipeline(self, shape, layer);
+			self.shapes[shape.0].shade.validate(self)?;
+			self.check_pipeline_cache();
+			let pipeline = PipelineStorage::create_shape_pipeline(self, shape, layer)?;
 			self.pipelines.insert(pipeline_key.clone(), pipeline);
 		}
+		Ok(())
 	}
 
 	pub(crate) fn get_effect_pipeline_key(&self, effect: Effect, layer: Layer) -> Vec<u8> {
@@ -406,16 +948,53 @@ impl Painter {
 		[effect_key, layer_key].concat()
 	}
 
+	/// Creates the pipeline if it is not cached yet, after validating the shader interface.
 	pub(crate) fn ensure_effect_pipeline<'a>(
 		&mut self,
 		pipeline_key: &Vec<u8>,
 		effect: Effect,
 		layer: Layer,
-	) {
+	) -> Result<(), PainterError> {
 		if !self.pipelines.contains_key(pipeline_key) {
-			let pipeline = PipelineStorage::create_effect_pipeline(self, effect, layer);
+			self.effects[effect.0].shade.validate(self)?;
+			self.check_pipeline_cache();
+			let pipeline = PipelineStorage::create_effect_pipeline(self, effect, layer)?;
 			self.pipelines.insert(pipeline_key.to_vec(), pipeline);
 		}
+		Ok(())
+	}
+
+	/// Drops cached pipelines that are no longer used by any shape or effect in any layer.
+	///
+	/// Kept pipelines are reassigned to a live shape or effect and layer,
+	/// so they can still be recreated on shader reload after their creators were destroyed.
+	pub(crate) fn cleanup_pipelines(&mut self) {
+		let mut used = BTreeMap::new();
+		for (h, l) in self.layers.iter() {
+			let layer = Layer(h);
+			for sd in &l.shapes {
+				let key = self.get_shape_pipeline_key(sd.shape, layer);
+				used.entry(key).or_insert((Some(sd.shape), None, layer));
+			}
+			for ed in &l.effects {
+				let key = self.get_effect_pipeline_key(ed.effect, layer);
+				used.entry(key).or_insert((None, Some(ed.effect), layer));
+			}
+		}
+
+		self.pipelines.retain(|key, p| {
+			// Internal pipelines (fullscreen, mipmaps) are not owned by a layer
+			if p.layer.is_none() {
+				return true;
+			}
+			if let Some((shape, effect, layer)) = used.get(key) {
+				p.shape = *shape;
+				p.effect = *effect;
+				p.layer = Some(*layer);
+				return true;
+			}
+			false
+		});
 	}
 
 	/// Renders a shape with optimized instance handling.
@@ -438,24 +1017,61 @@ impl Painter {
 		let s = &self.shapes[shape.0];
 		let f = &self.forms[s.form.0];
 
-		let draw = |pass: &mut wgpu::RenderPass, binding: Option<BindGroup>| {
+		let uses_instance_buffer = self.shades[s.shade.0].instance_attribs.is_some();
+		if uses_instance_buffer
+			&& f.instance_buffer
+				.as_ref()
+				.is_none_or(|b| b.current_size == 0)
+		{
+			// Nothing to draw until instance data is provided, or for an empty instance list
+			return;
+		}
+		if f.instance_count == 0 {
+			return;
+		}
+
+		let instances = 0..f.instance_count;
+
+		let draw = |pass: &mut wgpu::RenderPass, binding: Option<BindGroup>, instance: usize| {
 			if let Some(binding) = binding {
-				pass.set_bind_group(0, &self.bind_groups[binding.0].bind_group, &[]);
+				pass.set_bind_group(
+					0,
+					&self.bind_groups[binding.0].bind_group,
+					&self.bind_groups[binding.0].offsets,
+				);
+			}
+
+			if let Some(pc) = &s.push_constants {
+				pc.apply(pass, instance);
+			}
+
+			if uses_instance_buffer && let Some(b) = &f.instance_buffer {
+				pass.set_vertex_buffer(1, b.buffer.slice(..b.current_size));
 			}
 
 			for i in 0..f.currently_active_buffers {
 				let b = &f.buffers[i];
 				pass.set_vertex_buffer(0, b.vertex_buffer.slice(..b.vertex_buffer_current_size));
-				if let Some(index_buffer) = &b.index_buffer
+				if s.line_list {
+					if let Some(line_index_buffer) = &b.line_index_buffer
+						&& b.line_index_count > 0
+					{
+						pass.set_index_buffer(
+							line_index_buffer.slice(..),
+							wgpu::IndexFormat::Uint32,
+						);
+						pass.draw_indexed(0..b.line_index_count, 0, instances.clone());
+					}
+				} else if let Some(index_buffer) = &b.index_buffer
 					&& b.index_count > 0
 				{
 					pass.set_index_buffer(
 						index_buffer.slice(..b.index_buffer_current_size),
 						wgpu::IndexFormat::Uint32,
 					);
-					pass.draw_indexed(0..b.index_count, 0, 0..1);
+					pass.draw_indexed(0..b.index_count, 0, instances.clone());
 				} else {
-					pass.draw(0..b.vertex_count, 0..1);
+					pass.draw(0..b.vertex_count, instances.clone());
 				}
 			}
 		};
@@ -464,6 +1080,10 @@ impl Painter {
 		let pipeline = &self.pipelines[&pipeline_key];
 		pass.set_pipeline(&pipeline.pipeline);
 
+		if self.layers[layer.0].depth_texture.is_some() {
+			pass.set_stencil_reference(s.stencil_reference);
+		}
+
 		// Get bindings from shape_data
 		let bind_groups = &shape_data.bind_groups;
 
@@ -473,37 +1093,44 @@ impl Painter {
 				// Case 1: No instances - both bindings ≤ 1
 				// Set both bind groups once and do single draw
 				if let Some(layer_bind_group_data) = &shape_data.layer_bind_group_data {
-					let layer_bind_group = layer_bind_group_data.to_gpu_bind_group(self);
-					pass.set_bind_group(1, &layer_bind_group, &[]);
+					pass.set_bind_group(1, &layer_bind_group_data.current[0], &[]);
 				}
 
 				let value_binding = bind_groups.first().copied();
-				draw(pass, value_binding);
+				draw(pass, value_binding, 0);
+
+				// Instances only differing in their push constants
+				let push_constant_draws = s.push_constants.as_ref().map_or(1, |pc| pc.data.len());
+				for i in 1..push_constant_draws {
+					draw(pass, None, i);
+				}
 			}
 			InstanceRenderingStrategy::ValueBindingsVary => {
 				// Case 2: Only value bindings vary (values > 1, layers ≤ 1)
 				// Set layer bindings once, iterate through value bindings
 				if let Some(layer_bind_group_data) = &shape_data.layer_bind_group_data {
-					let layer_bind_group = layer_bind_group_data.to_gpu_bind_group(self);
-					pass.set_bind_group(1, &layer_bind_group, &[]);
+					pass.set_bind_group(1, &layer_bind_group_data.current[0], &[]);
 				}
 
-				for value_bg in bind_groups {
-					draw(pass, Some(*value_bg));
+				for (i, value_bg) in bind_groups.iter().enumerate() {
+					draw(pass, Some(*value_bg), i);
 				}
 			}
 			InstanceRenderingStrategy::LayerBindingsVary => {
 				// Case 3: Only layer bindings vary (layers > 1, values ≤ 1)
 				// Set value bindings once, iterate through layer bindings
 				if let Some(value_bg) = bind_groups.first() {
-					pass.set_bind_group(0, &self.bind_groups[value_bg.0].bind_group, &[]);
+					pass.set_bind_group(
+						0,
+						&self.bind_groups[value_bg.0].bind_group,
+						&self.bind_groups[value_bg.0].offsets,
+					);
 				}
 
 				if let Some(layer_bind_group_data) = &shape_data.layer_bind_group_data {
-					let layer_bind_groups = layer_bind_group_data.to_gpu_bind_groups(self);
-					for layer_bg in layer_bind_groups {
-						pass.set_bind_group(1, &layer_bg, &[]);
-						draw(pass, None);
+					for (i, layer_bg) in layer_bind_group_data.current.iter().enumerate() {
+						pass.set_bind_group(1, layer_bg, &[]);
+						draw(pass, None, i);
 					}
 				}
 			}
@@ -511,41 +1138,88 @@ impl Painter {
 				// Case 4: Both bindings vary (both > 1)
 				// Iterate through all instances, setting both bind groups per draw
 				if let Some(layer_bind_group_data) = &shape_data.layer_bind_group_data {
-					let layer_bind_groups = layer_bind_group_data.to_gpu_bind_groups(self);
-
-					for (value_bg, layer_bg) in bind_groups.iter().zip(layer_bind_groups.iter()) {
-						pass.set_bind_group(0, &self.bind_groups[value_bg.0].bind_group, &[]);
+					let layer_bind_groups = &layer_bind_group_data.current;
+
+					for (i, (value_bg, layer_bg)) in
+						bind_groups.iter().zip(layer_bind_groups.iter()).enumerate()
+					{
+						pass.set_bind_group(
+							0,
+							&self.bind_groups[value_bg.0].bind_group,
+							&self.bind_groups[value_bg.0].offsets,
+						);
 						pass.set_bind_group(1, layer_bg, &[]);
-						draw(pass, None);
+						draw(pass, None, i);
 					}
 				}
 			}
 		}
 	}
 
-	/// Helper to create a single layer bind group, optionally prepending the source binding.
-	fn create_layer_bind_group(
-		&self,
-		layer_bind_group_data: &LayerBindGroupData,
-		source_binding: Option<&LayerBinding>,
-	) -> wgpu::BindGroup {
-		if let Some(binding) = source_binding {
-			layer_bind_group_data.to_gpu_bind_group_with_first(self, binding)
-		} else {
-			layer_bind_group_data.to_gpu_bind_group(self)
+	/// Updates the layer bind groups of all shapes in the layer to the current layer textures.
+	fn prepare_shape_layer_bind_groups(&mut self, layer: Layer) {
+		for i in 0..self.layers[layer.0].shapes.len() {
+			let mut data = self.layers[layer.0].shapes[i].layer_bind_group_data.take();
+			if let Some(data) = &mut data {
+				data.prepare_gpu_bind_groups(self, None);
+			}
+			self.layers[layer.0].shapes[i].layer_bind_group_data = data;
 		}
 	}
 
-	/// Helper to create multiple layer bind groups, optionally prepending the source binding to each.
-	fn create_layer_bind_groups(
-		&self,
-		layer_bind_group_data: &LayerBindGroupData,
-		source_binding: Option<&LayerBinding>,
-	) -> Vec<wgpu::BindGroup> {
-		if let Some(binding) = source_binding {
-			layer_bind_group_data.to_gpu_bind_groups_with_first(self, binding)
+	/// Updates the layer bind groups of an effect to the current layer textures.
+	/// Unless skipped, the current source texture of the layer is bound first.
+	fn prepare_effect_layer_bind_groups(
+		&mut self,
+		effect_index: usize,
+		layer: Layer,
+		skip_source: bool,
+	) {
+		let effect = self.layers[layer.0].effects[effect_index].effect;
+
+		let source_binding = if !skip_source {
+			Some(
+				if let Some(src_mip_level) = self.effects[effect.0].src_mip_level {
+					layer.binding_at_mip_level(src_mip_level)
+				} else {
+					layer.binding()
+				},
+			)
 		} else {
-			layer_bind_group_data.to_gpu_bind_groups(self)
+			None
+		};
+
+		let mut data = self.layers[layer.0].effects[effect_index]
+			.layer_bind_group_data
+			.take();
+		if let Some(data) = &mut data {
+			data.prepare_gpu_bind_groups(self, source_binding.as_ref());
+		}
+		self.layers[layer.0].effects[effect_index].layer_bind_group_data = data;
+	}
+
+	/// Drops cached layer bind groups referencing the texture, after it was recreated.
+	pub(crate) fn invalidate_layer_bind_groups(&mut self, texture: Texture) {
+		for h in self.layers.handles() {
+			let l = &mut self.layers[h];
+			let shape_data = l.shapes.iter_mut().map(|sd| &mut sd.layer_bind_group_data);
+			let effect_data = l.effects.iter_mut().map(|ed| &mut ed.layer_bind_group_data);
+			for data in shape_data.chain(effect_data).flatten() {
+				data.invalidate_texture(texture);
+			}
+		}
+
+		for h in self.computes.handles() {
+			if let Some(data) = &mut self.computes[h].layer_bind_group_data {
+				data.invalidate_texture(texture);
+			}
+		}
+
+		// Value bind groups resolve storage textures when created, so recreate them as well
+		for h in self.layers.handles() {
+			if self.layers[h].target_textures.contains(&texture) {
+				BindGroup::refresh_storage_texture_bind_groups(self, Layer(h));
+			}
 		}
 	}
 
@@ -563,7 +1237,13 @@ impl Painter {
 	///    Iterate through all instances, setting both bind groups per draw
 	///
 	/// This respects the override hierarchy: Layer → Effect → Instance
-	fn render_effect(&self, effect_index: usize, layer: Layer, skip_source: bool) {
+	fn render_effect(
+		&self,
+		encoder: &mut wgpu::CommandEncoder,
+		effect_index: usize,
+		layer: Layer,
+		timestamp_writes: Option<wgpu::RenderPassTimestampWrites<'_>>,
+	) {
 		let l = &self.layers[layer.0];
 		let effect_data = &l.effects[effect_index];
 		let effect = effect_data.effect;
@@ -576,13 +1256,9 @@ impl Painter {
 			l.current_target_texture().target_view(self)
 		};
 
-		let mut encoder = self
-			.device
-			.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-
 		{
 			let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-				label: None,
+				label: e.label.as_deref(),
 				color_attachments: &[Some(wgpu::RenderPassColorAttachment {
 					view,
 					resolve_target: None,
@@ -595,7 +1271,7 @@ impl Painter {
 					depth_slice: None,
 				})],
 				depth_stencil_attachment: None,
-				timestamp_writes: None,
+				timestamp_writes,
 				occlusion_query_set: None,
 			});
 
@@ -606,32 +1282,21 @@ impl Painter {
 			// Get bindings from effect_data
 			let bind_groups = &effect_data.bind_groups;
 
-			// Pre-compute source binding if needed (avoids duplicate computation in all cases)
-			let source_binding = if !skip_source {
-				Some(if let Some(src_mip_level) = e.src_mip_level {
-					layer.binding_at_mip_level(src_mip_level)
-				} else {
-					layer.binding()
-				})
-			} else {
-				None
-			};
-
 			// Use pre-computed rendering strategy
 			match effect_data.rendering_strategy {
 				InstanceRenderingStrategy::NoInstances => {
 					// Case 1: No instances - both bindings ≤ 1
 					// Set both bind groups once and do single draw
 					if let Some(layer_bind_group_data) = &effect_data.layer_bind_group_data {
-						let layer_bind_group = self.create_layer_bind_group(
-							layer_bind_group_data,
-							source_binding.as_ref(),
-						);
-						pass.set_bind_group(1, &layer_bind_group, &[]);
+						pass.set_bind_group(1, &layer_bind_group_data.current[0], &[]);
 					}
 
 					if let Some(value_bg) = bind_groups.first() {
-						pass.set_bind_group(0, &self.bind_groups[value_bg.0].bind_group, &[]);
+						pass.set_bind_group(
+							0,
+							&self.bind_groups[value_bg.0].bind_group,
+							&self.bind_groups[value_bg.0].offsets,
+						);
 					}
 					pass.draw(0..3, 0..1);
 				}
@@ -639,15 +1304,15 @@ impl Painter {
 					// Case 2: Only value bindings vary (values > 1, layers ≤ 1)
 					// Set layer bindings once, iterate through value bindings
 					if let Some(layer_bind_group_data) = &effect_data.layer_bind_group_data {
-						let layer_bind_group = self.create_layer_bind_group(
-							layer_bind_group_data,
-							source_binding.as_ref(),
-						);
-						pass.set_bind_group(1, &layer_bind_group, &[]);
+						pass.set_bind_group(1, &layer_bind_group_data.current[0], &[]);
 					}
 
 					for value_bg in bind_groups {
-						pass.set_bind_group(0, &self.bind_groups[value_bg.0].bind_group, &[]);
+						pass.set_bind_group(
+							0,
+							&self.bind_groups[value_bg.0].bind_group,
+							&self.bind_groups[value_bg.0].offsets,
+						);
 						pass.draw(0..3, 0..1);
 					}
 				}
@@ -655,16 +1320,16 @@ impl Painter {
 					// Case 3: Only layer bindings vary (layers > 1, values ≤ 1)
 					// Set value bindings once, iterate through layer bindings
 					if let Some(value_bg) = bind_groups.first() {
-						pass.set_bind_group(0, &self.bind_groups[value_bg.0].bind_group, &[]);
+						pass.set_bind_group(
+							0,
+							&self.bind_groups[value_bg.0].bind_group,
+							&self.bind_groups[value_bg.0].offsets,
+						);
 					}
 
 					if let Some(layer_bind_group_data) = &effect_data.layer_bind_group_data {
-						let layer_bind_groups = self.create_layer_bind_groups(
-							layer_bind_group_data,
-							source_binding.as_ref(),
-						);
-						for layer_bg in layer_bind_groups {
-							pass.set_bind_group(1, &layer_bg, &[]);
+						for layer_bg in &layer_bind_group_data.current {
+							pass.set_bind_group(1, layer_bg, &[]);
 							pass.draw(0..3, 0..1);
 						}
 					} else {
@@ -675,14 +1340,15 @@ impl Painter {
 					// Case 4: Both bindings vary (both > 1)
 					// Iterate through all instances, setting both bind groups per draw
 					if let Some(layer_bind_group_data) = &effect_data.layer_bind_group_data {
-						let layer_bind_groups = self.create_layer_bind_groups(
-							layer_bind_group_data,
-							source_binding.as_ref(),
-						);
+						let layer_bind_groups = &layer_bind_group_data.current;
 
 						for (value_bg, layer_bg) in bind_groups.iter().zip(layer_bind_groups.iter())
 						{
-							pass.set_bind_group(0, &self.bind_groups[value_bg.0].bind_group, &[]);
+							pass.set_bind_group(
+								0,
+								&self.bind_groups[value_bg.0].bind_group,
+								&self.bind_groups[value_bg.0].offsets,
+							);
 							pass.set_bind_group(1, layer_bg, &[]);
 							pass.draw(0..3, 0..1);
 						}
@@ -690,20 +1356,28 @@ impl Painter {
 				}
 			}
 		}
-
-		self.queue.submit(Some(encoder.finish()));
 	}
 
 	pub fn paint(&mut self, layer: Layer) {
+		self.layers[layer.0].static_texture_dirty.set(false);
+		self.prepare_shape_layer_bind_groups(layer);
+
+		let mut encoder = self.begin_encoder();
+
 		let l = &self.layers[layer.0];
 		let shapes_len = l.shapes.len();
 		let effects_len = l.effects.len();
 		let has_shapes = shapes_len > 0;
+		let is_static = l.static_texture;
+		let source_texture = *l.current_source_texture();
 
 		if has_shapes {
+			let timer = self.profiler.begin_pass();
+
 			let color_attachments: Vec<Option<RenderPassColorAttachment<'_>>> =
 				if !l.is_multi_target {
-					let target_view = l.current_target_texture().target_view(self);
+					let (target_view, depth_slice) =
+						l.target_attachment(self, l.current_target_texture());
 					let multisampled_texture = l.multisampled_textures.get(0);
 
 					let view = multisampled_texture.map_or(target_view, |t| t.target_view(self));
@@ -718,14 +1392,14 @@ impl Painter {
 								.map_or(wgpu::LoadOp::Load, |color| wgpu::LoadOp::Clear(color)),
 							store: wgpu::StoreOp::Store,
 						},
-						depth_slice: None,
+						depth_slice,
 					})]
 				} else {
 					l.target_textures
 						.iter()
 						.enumerate()
 						.map(|(i, t)| {
-							let target_view = t.target_view(self);
+							let (target_view, depth_slice) = l.target_attachment(self, t);
 							let multisampled_texture = l.multisampled_textures.get(i);
 
 							let view =
@@ -741,31 +1415,35 @@ impl Painter {
 									}),
 									store: wgpu::StoreOp::Store,
 								},
-								depth_slice: None,
+								depth_slice,
 							})
 						})
 						.collect::<Vec<_>>()
 				};
 
-			let mut encoder = self
-				.device
-				.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-
 			{
 				let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-					label: None,
+					label: l.label.as_deref(),
 					color_attachments: &color_attachments,
 					depth_stencil_attachment: l.depth_texture.as_ref().map(|t| {
+						let has_stencil = self.textures[t.0].texture.format().has_stencil_aspect();
 						wgpu::RenderPassDepthStencilAttachment {
 							view: t.view(self, &TexViewKey::Default),
 							depth_ops: Some(wgpu::Operations {
-								load: wgpu::LoadOp::Clear(1.0),
+								load: l
+									.depth_clear
+									.map_or(wgpu::LoadOp::Load, wgpu::LoadOp::Clear),
+								store: wgpu::StoreOp::Store,
+							}),
+							stencil_ops: has_stencil.then(|| wgpu::Operations {
+								load: l
+									.stencil_clear
+									.map_or(wgpu::LoadOp::Load, wgpu::LoadOp::Clear),
 								store: wgpu::StoreOp::Store,
 							}),
-							stencil_ops: None,
 						}
 					}),
-					timestamp_writes: None,
+					timestamp_writes: self.profiler.timestamp_writes(&timer),
 					occlusion_query_set: None,
 				});
 
@@ -774,11 +1452,13 @@ impl Painter {
 				}
 			}
 
-			self.queue.submit(Some(encoder.finish()));
+			self.profiler
+				.end_pass(timer, layer, l.label.as_deref(), None);
 		}
 
 		if effects_len == 0 {
-			l.current_target_texture().update_mips(self);
+			l.current_target_texture().encode_mips(self, &mut encoder);
+			self.end_encoder(encoder);
 			return;
 		}
 
@@ -790,11 +1470,26 @@ impl Painter {
 		for i in 0..effects_len {
 			let effect = self.layers[layer.0].effects[i].effect;
 			let e = &self.effects[effect.0];
+			let has_dst_mip_level = e.dst_mip_level.is_some();
 
-			let skip_source_tex = i == 0 && !(has_shapes || e.src_mip_level.is_some());
-			self.render_effect(i, layer, skip_source_tex);
+			let skip_source_tex = i == 0 && !(has_shapes || is_static || e.src_mip_level.is_some());
+			self.prepare_effect_layer_bind_groups(i, layer, skip_source_tex);
 
-			if e.dst_mip_level.is_none() {
+			let timer = self.profiler.begin_pass();
+			self.render_effect(
+				&mut encoder,
+				i,
+				layer,
+				self.profiler.timestamp_writes(&timer),
+			);
+			self.profiler.end_pass(
+				timer,
+				layer,
+				self.layers[layer.0].label.as_deref(),
+				Some((effect, self.effects[effect.0].label.as_deref())),
+			);
+
+			if !has_dst_mip_level {
 				self.layers[layer.0].swap_targets();
 			} else {
 				// If the effect has a mip target, we don't swap the targets.
@@ -806,10 +1501,76 @@ impl Painter {
 		if update_mips {
 			self.layers[layer.0]
 				.current_source_texture()
-				.update_mips(self);
+				.encode_mips(self, &mut encoder);
+		}
+
+		self.end_encoder(encoder);
+
+		// Storage texture bindings of the layer have to follow the swapped targets
+		if *self.layers[layer.0].current_source_texture() != source_texture {
+			BindGroup::refresh_storage_texture_bind_groups(self, layer);
 		}
 	}
 
+	/// Runs a compute pass with the workgroup count of the given [`Compute`].
+	///
+	/// The work is ordered with any `paint` calls before and after it.
+	pub fn dispatch(&mut self, compute: Compute) {
+		self.try_dispatch(compute)
+			.unwrap_or_else(|err| panic!("{}", err));
+	}
+
+	/// Like `dispatch`, but returns an error if the compute shader code is missing.
+	/// Nothing is dispatched in that case.
+	pub fn try_dispatch(&mut self, compute: Compute) -> Result<(), PainterError> {
+		let shade = self.computes[compute.0].shade;
+		shade.ensure_pipeline(self)?;
+
+		let mut data = self.computes[compute.0].layer_bind_group_data.take();
+		if let Some(data) = &mut data {
+			data.prepare_gpu_bind_groups(self, None);
+		}
+		self.computes[compute.0].layer_bind_group_data = data;
+
+		let mut encoder = self.begin_encoder();
+
+		let c = &self.computes[compute.0];
+		let s = &self.compute_shades[shade.0];
+
+		let layer_bind_group = c
+			.layer_bind_group_data
+			.as_ref()
+			.map(|data| &data.current[0]);
+
+		{
+			let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+				label: None,
+				timestamp_writes: None,
+			});
+
+			pass.set_pipeline(s.pipeline.as_ref().unwrap());
+
+			let mut group_index = 0;
+			if let Some(bind_group) = c.bind_group {
+				pass.set_bind_group(
+					group_index,
+					&self.bind_groups[bind_group.0].bind_group,
+					&self.bind_groups[bind_group.0].offsets,
+				);
+				group_index += 1;
+			}
+			if let Some(bind_group) = layer_bind_group {
+				pass.set_bind_group(group_index, bind_group, &[]);
+			}
+
+			let (x, y, z) = c.workgroups;
+			pass.dispatch_workgroups(x, y, z);
+		}
+
+		self.end_encoder(encoder);
+		Ok(())
+	}
+
 	pub fn compose<I>(&mut self, layers: I)
 	where
 		I: IntoIterator<Item = Layer>,
@@ -819,34 +1580,137 @@ impl Painter {
 		}
 	}
 
-	pub fn show(&mut self, layer: Layer) {
-		let result = self.surface.get_current_texture();
+	/// Paints the layers and all layers they depend on, each after its dependencies,
+	/// see `paint_order`.
+	///
+	/// Static texture layers are only painted after their data was updated.
+	///
+	/// # Example
+	/// ```ignore
+	/// // scene and blur_x are painted before blur_y, which reads them.
+	/// painter.compose_graph([blur_y]);
+	/// ```
+	pub fn compose_graph<I>(&mut self, layers: I)
+	where
+		I: IntoIterator<Item = Layer>,
+	{
+		self.try_compose_graph(layers)
+			.unwrap_or_else(|err| panic!("{}", err));
+	}
 
-		if result.is_err() {
-			self.surface_error = Some(result.err().unwrap());
-			return;
+	/// Like `compose_graph`, but returns an error instead of panicking
+	/// if the layers depend on each other in a cycle. Nothing is painted in that case.
+	pub fn try_compose_graph<I>(&mut self, layers: I) -> Result<(), PainterError>
+	where
+		I: IntoIterator<Item = Layer>,
+	{
+		for layer in self.try_paint_order(layers)? {
+			let l = &self.layers[layer.0];
+			if l.static_texture && !l.static_texture_dirty.get() {
+				continue;
+			}
+			self.paint(layer);
 		}
+		Ok(())
+	}
 
-		let frame = result.unwrap();
+	/// The given layers and all layers they depend on, see `Layer::dependencies`,
+	/// ordered so that every layer comes after the layers it reads.
+	///
+	/// Panics if the layers depend on each other in a cycle.
+	/// Layers that read each others textures from the previous frame
+	/// have to be painted in an explicit order with `compose` instead.
+	pub fn paint_order<I>(&self, layers: I) -> Vec<Layer>
+	where
+		I: IntoIterator<Item = Layer>,
+	{
+		self.try_paint_order(layers)
+			.unwrap_or_else(|err| panic!("{}", err))
+	}
 
-		let view = frame
-			.texture
-			.create_view(&wgpu::TextureViewDescriptor::default());
+	/// Like `paint_order`, but returns an error for cyclic dependencies.
+	pub fn try_paint_order<I>(&self, layers: I) -> Result<Vec<Layer>, PainterError>
+	where
+		I: IntoIterator<Item = Layer>,
+	{
+		dependency_order(layers, |layer| layer.dependencies(self)).map_err(|cycle| {
+			let cycle = cycle
+				.iter()
+				.map(|l| match &self.layers[l.0].label {
+					Some(label) => label.clone(),
+					None => format!("layer {}", l.0.index),
+				})
+				.collect::<Vec<_>>();
+			PainterError::CyclicDependency(cycle)
+		})
+	}
 
-		let mut encoder = self
-			.device
-			.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+	/// Renders the layer to the window surface.
+	/// For headless painters, the layer is rendered into the offscreen target texture instead.
+	///
+	/// Within `Painter::frame`, the surface texture is presented after the frame was submitted.
+	pub fn show(&mut self, layer: Layer) {
+		self.shown_layer = Some(layer);
+
+		// Reuse the surface texture if the layer was already shown in this frame
+		let shown = self.frame.as_mut().and_then(|f| f.surface_texture.take());
+
+		let frame = match (shown, &self.surface) {
+			(Some(frame), _) => Some(frame),
+			(None, Some(surface)) => match surface.get_current_texture() {
+				Ok(frame) => Some(frame),
+				Err(err) => {
+					self.surface_error = Some(err);
+					return;
+				}
+			},
+			(None, None) => None,
+		};
+
+		let mut encoder = self.begin_encoder();
+
+		let frame_view = frame.as_ref().map(|frame| {
+			frame
+				.texture
+				.create_view(&wgpu::TextureViewDescriptor::default())
+		});
+
+		let view = match &frame_view {
+			Some(view) => view,
+			None => self.headless_target.unwrap().target_view(self),
+		};
 
-		let pipeline = &self.pipelines[FULL_SCREEN_TEXTURE_PIPELINE];
+		let filterable = {
+			let l = &self.layers[layer.0];
+			is_float_filterable(l.current_source_texture().format(self))
+		};
+		let (pipeline, sampler) = if filterable {
+			(
+				&self.pipelines[FULL_SCREEN_TEXTURE_PIPELINE],
+				BindGroup::NEAREST_SAMPLER,
+			)
+		} else {
+			(
+				&self.pipelines[FULL_SCREEN_UNFILTERED_TEXTURE_PIPELINE],
+				BindGroup::UNFILTERED_SAMPLER,
+			)
+		};
+
+		// Transparent windows show the content behind them where the layer is transparent
+		let clear_color = if is_transparent_alpha_mode(self.config.alpha_mode) {
+			wgpu::Color::TRANSPARENT
+		} else {
+			wgpu::Color::BLACK
+		};
 
 		{
 			let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
 				label: None,
 				color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-					view: &view,
+					view,
 					resolve_target: None,
 					ops: wgpu::Operations {
-						load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+						load: wgpu::LoadOp::Clear(clear_color),
 						store: wgpu::StoreOp::Store,
 					},
 					depth_slice: None,
@@ -856,7 +1720,11 @@ impl Painter {
 				occlusion_query_set: None,
 			});
 			pass.set_pipeline(&pipeline.pipeline);
-			pass.set_bind_group(0, &self.bind_groups[0].bind_group, &[]);
+			pass.set_bind_group(
+				0,
+				&self.bind_groups[sampler.0].bind_group,
+				&self.bind_groups[sampler.0].offsets,
+			);
 			pass.set_bind_group(
 				1,
 				&BindGroup::layer_gpu_bind_group(self, layer.binding()),
@@ -865,8 +1733,106 @@ impl Painter {
 			pass.draw(0..3, 0..1);
 		}
 
+		// Outside of `Painter::frame`, showing a layer ends the profiled frame
+		let ends_profiling = self.frame.is_none() && self.profiler.has_passes();
+		if ends_profiling {
+			self.profiler.end_frame(&self.device, &mut encoder);
+		}
+
+		self.end_encoder(encoder);
+
+		if ends_profiling {
+			self.profiler.after_submit();
+		}
+
+		if let Some(frame) = frame {
+			if let Some(state) = &mut self.frame {
+				state.surface_texture = Some(frame);
+			} else {
+				frame.present();
+			}
+		}
+	}
+
+	/// Records all painting of the closure into a single command encoder,
+	/// which is submitted to the GPU at once after the closure returns.
+	///
+	/// Use it to render a whole frame, e.g. `compose` of many layers and `show`,
+	/// with only one queue submission instead of one per layer, effect and `show` call.
+	///
+	/// Note that buffer and texture data updates, like `BindingBuffer::update`,
+	/// are written to the GPU before any command of the frame is executed.
+	/// Update a buffer only once per frame if several layers depend on it.
+	/// Likewise, read pixels only after the frame was submitted.
+	///
+	/// # Example
+	/// ```ignore
+	/// painter.frame(|f| {
+	///     f.compose([scene, blur_x, blur_y]);
+	///     f.show(blur_y);
+	/// });
+	/// ```
+	pub fn frame<R>(&mut self, f: impl FnOnce(&mut Painter) -> R) -> R {
+		// Nested frames are recorded into the outer frame
+		if self.frame.is_some() {
+			return f(self);
+		}
+
+		self.frame = Some(FrameState::default());
+		let result = f(self);
+		let mut state = self.frame.take().unwrap();
+
+		if self.profiler.has_passes() {
+			let encoder = state.encoder.get_or_insert_with(|| {
+				self.device
+					.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None })
+			});
+			self.profiler.end_frame(&self.device, encoder);
+		}
+
+		if let Some(encoder) = state.encoder {
+			self.submit(encoder);
+		}
+		self.profiler.after_submit();
+		if let Some(surface_texture) = state.surface_texture {
+			surface_texture.present();
+		}
+
+		result
+	}
+
+	/// Returns the command encoder of the current frame,
+	/// or a new one if painting outside of `Painter::frame`.
+	/// Has to be handed back with `end_encoder`.
+	pub(crate) fn begin_encoder(&mut self) -> wgpu::CommandEncoder {
+		self.frame
+			.as_mut()
+			.and_then(|f| f.encoder.take())
+			.unwrap_or_else(|| {
+				self.device
+					.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None })
+			})
+	}
+
+	/// Keeps recording into the encoder if inside of `Painter::frame`, submits it otherwise.
+	pub(crate) fn end_encoder(&mut self, encoder: wgpu::CommandEncoder) {
+		if let Some(state) = &mut self.frame {
+			state.encoder = Some(encoder);
+		} else {
+			self.submit(encoder);
+		}
+	}
+
+	/// Submits the encoder, together with the staging buffers written into it.
+	fn submit(&mut self, encoder: wgpu::CommandEncoder) {
+		if let Some(belt) = &mut self.staging_belt {
+			belt.finish();
+		}
 		self.queue.submit(Some(encoder.finish()));
-		frame.present();
+		// Staging buffers are reused once they are mapped again after the GPU finished the copies
+		if let Some(belt) = &mut self.staging_belt {
+			belt.recall();
+		}
 	}
 
 	pub fn paint_and_show(&mut self, layer: Layer) {
@@ -884,43 +1850,99 @@ impl Painter {
 		self.paint(layer);
 	}
 
+	/// Creates the GPU pipelines of all layers and compute shades up front,
+	/// instead of on their first paint or dispatch, to avoid hitches in the first frames.
+	///
+	/// Called automatically after CanvasApp::init. Useful after creating layers at runtime.
+	/// Compute shades without shader code yet are skipped.
+	pub fn warm_up_pipelines(&mut self) {
+		self.try_warm_up_pipelines()
+			.unwrap_or_else(|err| panic!("{}", err));
+	}
+
+	/// Like `warm_up_pipelines`, but returns an error if a shader is missing
+	/// or does not match its shade. Pipelines created before the error are kept.
+	pub fn try_warm_up_pipelines(&mut self) -> Result<(), PainterError> {
+		for h in self.layers.handles() {
+			Layer(h).try_init_gpu_pipelines(self)?;
+		}
+
+		for h in self.compute_shades.handles() {
+			if self.compute_shades[h].compute_code.is_some() {
+				ComputeShade(h).ensure_pipeline(self)?;
+			}
+		}
+		Ok(())
+	}
+
 	#[cfg(all(not(target_arch = "wasm32"), debug_assertions))]
 	pub(crate) fn reload_shader(&mut self, path: String) {
 		println!("Reloading shader: {}", path);
-		let shade_indices = self
+		let shade_handles = self
 			.shades
 			.iter()
-			.enumerate()
-			.filter_map(|(idx, s)| {
+			.filter_map(|(h, s)| {
 				if s.vertex_path.as_ref().map_or(false, |p| p.contains(&path)) {
-					return Some(idx);
+					return Some(h);
 				}
 				if s.fragment_path
 					.as_ref()
 					.map_or(false, |p| p.contains(&path))
 				{
-					return Some(idx);
+					return Some(h);
 				}
 				None
 			})
 			.collect::<Vec<_>>();
 
+		// Internal pipelines (fullscreen, mipmaps) do not start with a shade handle
 		let pipeline_keys = self
 			.pipelines
-			.keys()
-			.cloned()
-			.map(|key| (u16::from_le_bytes([key[0], key[1]]), key))
+			.iter()
+			.filter(|(_, p)| p.layer.is_some())
+			.map(|(key, _)| key.clone())
 			.collect::<Vec<_>>();
 
-		for idx in shade_indices {
-			Shade(idx).load_fragment_from_path(self);
-			Shade(idx).load_vertex_from_path(self);
+		let compute_shade_handles = self
+			.compute_shades
+			.iter()
+			.filter_map(|(h, s)| {
+				s.compute_path
+					.as_ref()
+					.is_some_and(|p| p.contains(&path))
+					.then_some(h)
+			})
+			.collect::<Vec<_>>();
 
-			for (shade_idx, pipeline_key) in &pipeline_keys {
-				if *shade_idx == idx as u16 {
+		for h in compute_shade_handles {
+			// Keep the previous code, e.g. while the file is being replaced
+			if let Err(err) = ComputeShade(h).load_compute_from_path(self) {
+				log::error!("{}", err);
+			}
+		}
+
+		for h in shade_handles {
+			let loaded = Shade(h)
+				.load_fragment_from_path(self)
+				.and_then(|_| Shade(h).load_vertex_from_path(self));
+
+			// Keep the previous pipelines running until the shader can be read
+			// and matches its shade again
+			if let Err(err) =
+				loaded.and_then(|_| Shade(h).validate(self).map_err(PainterError::from))
+			{
+				log::error!("{}", err);
+				continue;
+			}
+
+			for pipeline_key in &pipeline_keys {
+				if pipeline_key.starts_with(&h.key_bytes()) {
 					let pipeline = self.pipelines.remove(pipeline_key);
 					if let Some(pipeline) = pipeline {
-						let pipeline = pipeline.recreate(self);
+						let (pipeline, err) = pipeline.recreate(self);
+						if let Some(err) = err {
+							log::error!("{}", err);
+						}
 						self.pipelines.insert(pipeline_key.clone(), pipeline);
 					}
 				}
@@ -929,6 +1951,49 @@ impl Painter {
 	}
 }
 
+/// Orders the nodes and all their dependencies, so that every node comes after its dependencies.
+/// Returns the nodes of the first cycle found, starting and ending with the same node.
+fn dependency_order<T, I, F>(nodes: I, dependencies: F) -> Result<Vec<T>, Vec<T>>
+where
+	T: Copy + PartialEq,
+	I: IntoIterator<Item = T>,
+	F: Fn(T) -> Vec<T>,
+{
+	// Depth first traversal, appending each node after its dependencies
+	fn visit<T: Copy + PartialEq>(
+		node: T,
+		dependencies: &impl Fn(T) -> Vec<T>,
+		visiting: &mut Vec<T>,
+		order: &mut Vec<T>,
+	) -> Result<(), Vec<T>> {
+		if order.contains(&node) {
+			return Ok(());
+		}
+
+		if let Some(start) = visiting.iter().position(|n| *n == node) {
+			let mut cycle = visiting[start..].to_vec();
+			cycle.push(node);
+			return Err(cycle);
+		}
+
+		visiting.push(node);
+		for dependency in dependencies(node) {
+			visit(dependency, dependencies, visiting, order)?;
+		}
+		visiting.pop();
+
+		order.push(node);
+		Ok(())
+	}
+
+	let mut order = Vec::new();
+	let mut visiting = Vec::new();
+	for node in nodes {
+		visit(node, &dependencies, &mut visiting, &mut order)?;
+	}
+	Ok(order)
+}
+
 pub(crate) fn get_padded_size(unpadded_size: u64) -> u64 {
 	// Valid vulkan usage is
 	// 1. buffer size must be a multiple of COPY_BUFFER_ALIGNMENT.
@@ -937,3 +2002,63 @@ pub(crate) fn get_padded_size(unpadded_size: u64) -> u64 {
 	let align_mask = wgpu::COPY_BUFFER_ALIGNMENT - 1;
 	((unpadded_size + align_mask) & !align_mask).max(wgpu::COPY_BUFFER_ALIGNMENT)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn linear_surface_format_is_never_encoded_twice() {
+		use wgpu::TextureFormat::*;
+
+		let format = SurfaceFormat::Linear.choose(&[Bgra8UnormSrgb, Bgra8Unorm]);
+		assert_eq!(format, Bgra8Unorm);
+		assert_eq!(SurfaceFormat::Linear.transfer(format), ShowTransfer::None);
+
+		let format = SurfaceFormat::Linear.choose(&[Bgra8UnormSrgb]);
+		assert_eq!(format, Bgra8UnormSrgb);
+		assert_eq!(
+			SurfaceFormat::Linear.transfer(format),
+			ShowTransfer::SrgbDecode
+		);
+
+		let format = SurfaceFormat::Linear.choose(&[Rgba16Float]);
+		assert_eq!(
+			SurfaceFormat::Linear.transfer(format),
+			ShowTransfer::SrgbDecode
+		);
+	}
+
+	fn order(graph: &[(u32, &[u32])], roots: &[u32]) -> Result<Vec<u32>, Vec<u32>> {
+		dependency_order(roots.iter().copied(), |node| {
+			graph
+				.iter()
+				.find(|(n, _)| *n == node)
+				.map_or(vec![], |(_, deps)| deps.to_vec())
+		})
+	}
+
+	#[test]
+	fn orders_diamond_dependencies_once() {
+		// 3 reads 1 and 2, which both read 0
+		let graph: &[(u32, &[u32])] = &[(3, &[1, 2]), (1, &[0]), (2, &[0])];
+
+		assert_eq!(order(graph, &[3]), Ok(vec![0, 1, 2, 3]));
+		// Roots that are dependencies of other roots are not repeated
+		assert_eq!(order(graph, &[1, 3]), Ok(vec![0, 1, 2, 3]));
+	}
+
+	#[test]
+	fn keeps_independent_roots_in_order() {
+		assert_eq!(order(&[], &[2, 0, 1]), Ok(vec![2, 0, 1]));
+	}
+
+	#[test]
+	fn returns_cycles() {
+		let graph: &[(u32, &[u32])] = &[(0, &[1]), (1, &[2]), (2, &[1])];
+		assert_eq!(order(graph, &[0]), Err(vec![1, 2, 1]));
+
+		let graph: &[(u32, &[u32])] = &[(0, &[0])];
+		assert_eq!(order(graph, &[0]), Err(vec![0, 0]));
+	}
+}
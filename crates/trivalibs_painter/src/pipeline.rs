@@ -1,7 +1,13 @@
 use trivalibs_core::utils::default;
-use wgpu::{ColorTargetState, util::make_spirv};
+use wgpu::ColorTargetState;
 
-use crate::{Painter, effect::Effect, layer::Layer, shape::Shape};
+use crate::{Painter, effect::Effect, error::PainterError, layer::Layer, shape::Shape};
+
+/// `REPLACE` is equivalent to disabled blending, which also keeps
+/// non-blendable targets like `Rgba32Float` valid.
+fn blend_for(state: wgpu::BlendState) -> Option<wgpu::BlendState> {
+	(state != wgpu::BlendState::REPLACE).then_some(state)
+}
 
 pub(crate) struct PipelineStorage {
 	pub pipeline: wgpu::RenderPipeline,
@@ -14,11 +20,16 @@ pub(crate) struct PipelineStorage {
 }
 
 impl PipelineStorage {
-	pub(crate) fn create_shape_pipeline(painter: &Painter, shape: Shape, layer: Layer) -> Self {
+	pub(crate) fn create_shape_pipeline(
+		painter: &Painter,
+		shape: Shape,
+		layer: Layer,
+	) -> Result<Self, PainterError> {
 		let l = &painter.layers[layer.0];
 		let sp = &painter.shapes[shape.0];
 		let sd = &painter.shades[sp.shade.0];
 		let f = &painter.forms[sp.form.0];
+		let pc_group = sd.push_constants.and_then(|p| p.fallback_group());
 
 		let targets: Vec<Option<ColorTargetState>> = l
 			.formats
@@ -26,25 +37,49 @@ impl PipelineStorage {
 			.map(|f| {
 				Some(wgpu::ColorTargetState {
 					format: *f,
-					blend: Some(sp.blend_state),
+					blend: blend_for(sp.blend_state),
 					write_mask: wgpu::ColorWrites::ALL,
 				})
 			})
 			.collect::<Vec<_>>();
 
+		let vertex_code = sd.vertex_code.as_ref().ok_or(PainterError::MissingShader {
+			stage: wgpu::ShaderStages::VERTEX,
+		})?;
+		let fragment_code = sd
+			.fragment_code
+			.as_ref()
+			.ok_or(PainterError::MissingShader {
+				stage: wgpu::ShaderStages::FRAGMENT,
+			})?;
+
 		let vertex_shader = painter
 			.device
 			.create_shader_module(wgpu::ShaderModuleDescriptor {
 				label: None,
-				source: make_spirv(&sd.vertex_bytes.as_ref().unwrap()),
+				source: vertex_code.source_with_push_constants_at(pc_group),
 			});
 
 		let fragment_shader = painter
 			.device
 			.create_shader_module(wgpu::ShaderModuleDescriptor {
 				label: None,
-				source: make_spirv(&sd.fragment_bytes.as_ref().unwrap()),
+				source: fragment_code.source_with_push_constants_at(pc_group),
+			});
+
+		let mut buffers = vec![wgpu::VertexBufferLayout {
+			array_stride: sd.attribs.stride,
+			step_mode: wgpu::VertexStepMode::Vertex,
+			attributes: &sd.attribs.attributes,
+		}];
+
+		if let Some(instance_attribs) = &sd.instance_attribs {
+			buffers.push(wgpu::VertexBufferLayout {
+				array_stride: instance_attribs.stride,
+				step_mode: wgpu::VertexStepMode::Instance,
+				attributes: &instance_attribs.attributes,
 			});
+		}
 
 		let pipeline = painter
 			.device
@@ -54,11 +89,7 @@ impl PipelineStorage {
 				vertex: wgpu::VertexState {
 					module: &vertex_shader,
 					entry_point: None,
-					buffers: &[wgpu::VertexBufferLayout {
-						array_stride: sd.attribs.stride,
-						step_mode: wgpu::VertexStepMode::Vertex,
-						attributes: &sd.attribs.attributes,
-					}],
+					buffers: &buffers,
 					compilation_options: default(),
 				},
 				fragment: Some(wgpu::FragmentState {
@@ -68,57 +99,59 @@ impl PipelineStorage {
 					compilation_options: default(),
 				}),
 				primitive: wgpu::PrimitiveState {
-					topology: f.props.topology,
+					topology: sp.topology,
 					strip_index_format: None,
 					front_face: f.props.front_face,
 					cull_mode: sp.cull_mode,
-					// Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-					polygon_mode: wgpu::PolygonMode::Fill,
+					polygon_mode: sp.polygon_mode,
 					unclipped_depth: false,
 					conservative: false,
 				},
-				depth_stencil: if l.depth_texture.is_some() {
-					Some(wgpu::DepthStencilState {
-						format: wgpu::TextureFormat::Depth24Plus,
-						depth_write_enabled: true,
-						depth_compare: wgpu::CompareFunction::Less,
-						stencil: default(),
-						bias: default(),
-					})
-				} else {
-					None
-				},
+				depth_stencil: l.depth_texture.map(|depth| wgpu::DepthStencilState {
+					format: painter.textures[depth.0].texture.format(),
+					depth_write_enabled: sp.depth_write,
+					depth_compare: sp.depth_compare,
+					stencil: sp.stencil.clone(),
+					bias: sp.depth_bias,
+				}),
 				multisample: wgpu::MultisampleState {
-					count: if l.multisampled_textures.is_empty() {
-						1
-					} else {
-						4
-					},
+					count: l.sample_count,
 					mask: !0,
-					alpha_to_coverage_enabled: false,
+					alpha_to_coverage_enabled: sp.alpha_to_coverage && l.sample_count > 1,
 				},
 				multiview: None,
-				cache: None,
+				cache: painter.pipeline_cache(),
 			});
 
-		PipelineStorage {
+		Ok(PipelineStorage {
 			pipeline,
 			effect: None,
 			layer: Some(layer),
 			shape: Some(shape),
-		}
+		})
 	}
 
-	pub(crate) fn create_effect_pipeline(painter: &Painter, effect: Effect, layer: Layer) -> Self {
+	pub(crate) fn create_effect_pipeline(
+		painter: &Painter,
+		effect: Effect,
+		layer: Layer,
+	) -> Result<Self, PainterError> {
 		let e = &painter.effects[effect.0];
 		let s = &painter.shades[e.shade.0];
 		let l = &painter.layers[layer.0];
 
+		let fragment_code = s
+			.fragment_code
+			.as_ref()
+			.ok_or(PainterError::MissingShader {
+				stage: wgpu::ShaderStages::FRAGMENT,
+			})?;
+
 		let fragment_shader = painter
 			.device
 			.create_shader_module(wgpu::ShaderModuleDescriptor {
 				label: None,
-				source: make_spirv(&s.fragment_bytes.as_ref().unwrap()),
+				source: fragment_code.source(),
 			});
 
 		let targets: Vec<Option<ColorTargetState>> = l
@@ -127,7 +160,7 @@ impl PipelineStorage {
 			.map(|f| {
 				Some(wgpu::ColorTargetState {
 					format: *f,
-					blend: Some(e.blend_state),
+					blend: blend_for(e.blend_state),
 					write_mask: wgpu::ColorWrites::ALL,
 				})
 			})
@@ -165,27 +198,29 @@ impl PipelineStorage {
 					alpha_to_coverage_enabled: false,
 				},
 				multiview: None,
-				cache: None,
+				cache: painter.pipeline_cache(),
 			});
 
-		PipelineStorage {
+		Ok(PipelineStorage {
 			pipeline,
 			layer: Some(layer),
 			effect: Some(effect),
 			shape: None,
-		}
+		})
 	}
 
+	/// Recreates the pipeline with the current shader code, `self` is kept on error.
 	#[cfg(all(not(target_arch = "wasm32"), debug_assertions))]
-	pub(crate) fn recreate(self, painter: &Painter) -> Self {
-		if let Some(layer) = self.layer {
-			if let Some(effect) = self.effect {
-				return Self::create_effect_pipeline(painter, effect, layer);
-			} else if let Some(shape) = self.shape {
-				return Self::create_shape_pipeline(painter, shape, layer);
-			}
-		}
+	pub(crate) fn recreate(self, painter: &Painter) -> (Self, Option<PainterError>) {
+		let recreated = match (self.layer, self.effect, self.shape) {
+			(Some(layer), Some(effect), _) => Self::create_effect_pipeline(painter, effect, layer),
+			(Some(layer), None, Some(shape)) => Self::create_shape_pipeline(painter, shape, layer),
+			_ => return (self, None),
+		};
 
-		self
+		match recreated {
+			Ok(pipeline) => (pipeline, None),
+			Err(err) => (self, Some(err)),
+		}
 	}
 }
@@ -0,0 +1,145 @@
+use std::{fs, path::PathBuf};
+
+/// A `wgpu::PipelineCache` persisted between runs, see `PainterConfig::pipeline_cache_key`.
+///
+/// Only supported by Vulkan adapters. The file is stored next to the dev state,
+/// named by the cache key and the adapter, and starts with a hash of the shader code
+/// the pipelines were compiled from.
+pub(crate) struct PersistentPipelineCache {
+	pub cache: wgpu::PipelineCache,
+	path: PathBuf,
+	/// Shader hash of the loaded data, cleared once the current shaders were compared with it.
+	loaded_shader_hash: Option<u64>,
+}
+
+impl PersistentPipelineCache {
+	pub(crate) fn path(key: &str, adapter_info: &wgpu::AdapterInfo) -> Option<PathBuf> {
+		let adapter_key = wgpu::util::pipeline_cache_key(adapter_info)?;
+		let mut path = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
+		path.push("rust-graphics");
+		path.push("pipeline-cache");
+		path.push(format!("{}-{}.bin", key, adapter_key));
+		Some(path)
+	}
+
+	/// Creates the cache with the data saved by a previous run, if any.
+	/// `None` if the device does not support pipeline caches.
+	pub(crate) fn load(
+		key: &str,
+		adapter_info: &wgpu::AdapterInfo,
+		device: &wgpu::Device,
+	) -> Option<Self> {
+		if !device.features().contains(wgpu::Features::PIPELINE_CACHE) {
+			return None;
+		}
+		let path = Self::path(key, adapter_info)?;
+
+		let file = fs::read(&path).ok().filter(|data| data.len() > 8);
+		let (loaded_shader_hash, data) = match &file {
+			Some(file) => {
+				let (hash, data) = file.split_at(8);
+				let hash = u64::from_le_bytes(hash.try_into().unwrap());
+				log::info!("Loaded pipeline cache from: {:?}", path);
+				(Some(hash), Some(data))
+			}
+			None => (None, None),
+		};
+
+		Some(Self {
+			cache: create_cache(device, data),
+			path,
+			loaded_shader_hash,
+		})
+	}
+
+	pub(crate) fn needs_shader_check(&self) -> bool {
+		self.loaded_shader_hash.is_some()
+	}
+
+	/// Drops the loaded pipelines if the shaders changed since they were saved,
+	/// so that the cache does not grow with pipelines that are never used again.
+	///
+	/// The cache data is opaque and can not be split per pipeline, so a change to any shader
+	/// discards all of them. Pipelines of unchanged shaders are compiled again once.
+	///
+	/// Called before the first shape or effect pipeline is created, when all shaders are loaded.
+	pub(crate) fn check_shaders(&mut self, device: &wgpu::Device, shader_hash: u64) {
+		if let Some(loaded) = self.loaded_shader_hash.take()
+			&& loaded != shader_hash
+		{
+			log::info!("Shaders changed, discarding pipeline cache {:?}", self.path);
+			self.cache = create_cache(device, None);
+		}
+	}
+
+	pub(crate) fn save(&self, shader_hash: u64) -> std::io::Result<()> {
+		let Some(data) = self.cache.get_data() else {
+			return Ok(());
+		};
+
+		fs::create_dir_all(self.path.parent().unwrap())?;
+		// Write to a temporary file first, so a crash never leaves a truncated cache behind
+		let temp_path = self.path.with_extension("temp");
+		fs::write(
+			&temp_path,
+			[shader_hash.to_le_bytes().as_slice(), &data].concat(),
+		)?;
+		fs::rename(&temp_path, &self.path)?;
+		log::info!("Saved pipeline cache to: {:?}", self.path);
+		Ok(())
+	}
+}
+
+fn create_cache(device: &wgpu::Device, data: Option<&[u8]>) -> wgpu::PipelineCache {
+	// SAFETY: The data was written by `PipelineCache::get_data` of a previous run.
+	// With `fallback`, data that does not match the adapter or driver is ignored.
+	unsafe {
+		device.create_pipeline_cache(&wgpu::PipelineCacheDescriptor {
+			label: None,
+			data,
+			fallback: true,
+		})
+	}
+}
+
+/// Hashes shader code for `PersistentPipelineCache::check_shaders`.
+///
+/// Uses FNV-1a, as the hash is persisted and has to stay the same across Rust versions,
+/// which the `std` hashers do not guarantee.
+pub(crate) fn hash_shader_code<'a>(code: impl IntoIterator<Item = &'a [u8]>) -> u64 {
+	code.into_iter().fold(FNV_OFFSET_BASIS, |hash, bytes| {
+		// The length separates the code of consecutive shaders
+		let hash = fnv1a(hash, &(bytes.len() as u64).to_le_bytes());
+		fnv1a(hash, bytes)
+	})
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+	bytes.iter().fold(hash, |hash, byte| {
+		(hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn fnv1a_matches_reference_values() {
+		assert_eq!(fnv1a(FNV_OFFSET_BASIS, b""), 0xcbf2_9ce4_8422_2325);
+		assert_eq!(fnv1a(FNV_OFFSET_BASIS, b"a"), 0xaf63_dc4c_8601_ec8c);
+		assert_eq!(fnv1a(FNV_OFFSET_BASIS, b"foobar"), 0x8594_4171_f739_67e8);
+	}
+
+	#[test]
+	fn shader_hash_separates_shaders() {
+		let hash = hash_shader_code([b"ab".as_slice(), b"c"]);
+
+		assert_eq!(hash, hash_shader_code([b"ab".as_slice(), b"c"]));
+		assert_ne!(hash, hash_shader_code([b"a".as_slice(), b"bc"]));
+		assert_ne!(hash, hash_shader_code([b"abc".as_slice()]));
+	}
+}
@@ -0,0 +1,284 @@
+use crate::{effect::Effect, layer::Layer};
+use std::{
+	sync::{
+		Arc,
+		atomic::{AtomicBool, Ordering},
+	},
+	time::Duration,
+};
+use web_time::Instant;
+
+/// Maximum number of passes per frame with GPU timestamps, further passes are timed on the CPU only.
+const MAX_TIMED_PASSES: u32 = 256;
+/// Number of frames waiting for their GPU timings, before further frames are skipped.
+const MAX_PENDING_FRAMES: usize = 3;
+
+/// Timing of a single render pass, see `Painter::frame_timings`.
+#[derive(Clone, Debug)]
+pub struct PassTiming {
+	pub layer: Layer,
+	pub layer_label: Option<String>,
+	/// The effect rendered by the pass, `None` for the shape pass of the layer.
+	pub effect: Option<Effect>,
+	pub effect_label: Option<String>,
+	/// Time spent on the CPU to record the pass.
+	pub cpu: Duration,
+	/// Time spent on the GPU to execute the pass.
+	/// `None` if the device does not support `Features::TIMESTAMP_QUERY`.
+	pub gpu: Option<Duration>,
+}
+
+impl PassTiming {
+	/// A readable name of the pass, e.g. `blur/blur_x`, or `layer 2/shapes` for unlabeled layers.
+	pub fn label(&self) -> String {
+		let layer = match &self.layer_label {
+			Some(label) => label.clone(),
+			None => format!("layer {}", self.layer.0.index),
+		};
+		let pass = match (&self.effect, &self.effect_label) {
+			(_, Some(label)) => label.clone(),
+			(Some(effect), None) => format!("effect {}", effect.0.index),
+			(None, None) => "shapes".to_string(),
+		};
+		format!("{}/{}", layer, pass)
+	}
+}
+
+/// Started timing of a pass, see `Profiler::begin_pass`.
+pub(crate) struct PassTimer {
+	start: Instant,
+	query: Option<u32>,
+}
+
+struct GpuQueries {
+	query_set: wgpu::QuerySet,
+	resolve_buffer: wgpu::Buffer,
+	/// Readback buffers that are not used by a pending frame.
+	free_buffers: Vec<wgpu::Buffer>,
+	/// Nanoseconds per timestamp tick.
+	period: f64,
+}
+
+/// A frame waiting for its timestamps to be copied to the CPU.
+struct PendingFrame {
+	passes: Vec<PassTiming>,
+	queries: Vec<Option<u32>>,
+	buffer: wgpu::Buffer,
+	mapped: Option<Arc<AtomicBool>>,
+}
+
+/// Collects CPU and GPU timings of layer and effect passes, see `Painter::set_profiling`.
+#[derive(Default)]
+pub(crate) struct Profiler {
+	gpu: Option<GpuQueries>,
+	enabled: bool,
+	passes: Vec<PassTiming>,
+	/// First of the two timestamp queries of each pass.
+	queries: Vec<Option<u32>>,
+	query_count: u32,
+	pending: Vec<PendingFrame>,
+	timings: Vec<PassTiming>,
+}
+
+impl Profiler {
+	pub(crate) fn set_enabled(
+		&mut self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		enabled: bool,
+	) {
+		*self = Profiler::default();
+		self.enabled = enabled;
+
+		if enabled && device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+			let size = (MAX_TIMED_PASSES * 2) as u64 * wgpu::QUERY_SIZE as u64;
+			self.gpu = Some(GpuQueries {
+				query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+					label: None,
+					ty: wgpu::QueryType::Timestamp,
+					count: MAX_TIMED_PASSES * 2,
+				}),
+				resolve_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+					label: None,
+					size,
+					usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+					mapped_at_creation: false,
+				}),
+				free_buffers: Vec::with_capacity(MAX_PENDING_FRAMES),
+				period: queue.get_timestamp_period() as f64,
+			});
+		}
+	}
+
+	pub(crate) fn is_enabled(&self) -> bool {
+		self.enabled
+	}
+
+	pub(crate) fn has_passes(&self) -> bool {
+		!self.passes.is_empty()
+	}
+
+	/// Starts timing a pass, `None` if profiling is disabled.
+	pub(crate) fn begin_pass(&mut self) -> Option<PassTimer> {
+		if !self.enabled {
+			return None;
+		}
+
+		let query = (self.gpu.is_some() && self.query_count < MAX_TIMED_PASSES * 2).then(|| {
+			self.query_count += 2;
+			self.query_count - 2
+		});
+
+		Some(PassTimer {
+			start: Instant::now(),
+			query,
+		})
+	}
+
+	/// The timestamp writes for the render pass of the timer.
+	pub(crate) fn timestamp_writes(
+		&self,
+		timer: &Option<PassTimer>,
+	) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+		let query = timer.as_ref()?.query?;
+		self.gpu
+			.as_ref()
+			.map(|gpu| wgpu::RenderPassTimestampWrites {
+				query_set: &gpu.query_set,
+				beginning_of_pass_write_index: Some(query),
+				end_of_pass_write_index: Some(query + 1),
+			})
+	}
+
+	pub(crate) fn end_pass(
+		&mut self,
+		timer: Option<PassTimer>,
+		layer: Layer,
+		layer_label: Option<&str>,
+		effect: Option<(Effect, Option<&str>)>,
+	) {
+		let Some(timer) = timer else {
+			return;
+		};
+
+		self.passes.push(PassTiming {
+			layer,
+			layer_label: layer_label.map(str::to_string),
+			effect: effect.map(|(e, _)| e),
+			effect_label: effect.and_then(|(_, label)| label.map(str::to_string)),
+			cpu: timer.start.elapsed(),
+			gpu: None,
+		});
+		self.queries.push(timer.query);
+	}
+
+	/// Finishes the timings of the current frame and records copying its timestamps for readback.
+	///
+	/// The readback buffer is mapped by `after_submit`, once the encoder was submitted.
+	pub(crate) fn end_frame(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+		self.collect(device);
+
+		let passes = std::mem::take(&mut self.passes);
+		let queries = std::mem::take(&mut self.queries);
+		let query_count = std::mem::replace(&mut self.query_count, 0);
+
+		if passes.is_empty() {
+			return;
+		}
+
+		let Some(gpu) = &mut self.gpu else {
+			self.timings = passes;
+			return;
+		};
+
+		// Skip the frame if the GPU is too far behind
+		if query_count == 0 || self.pending.len() >= MAX_PENDING_FRAMES {
+			return;
+		}
+
+		let size = (MAX_TIMED_PASSES * 2) as u64 * wgpu::QUERY_SIZE as u64;
+		let buffer = gpu.free_buffers.pop().unwrap_or_else(|| {
+			device.create_buffer(&wgpu::BufferDescriptor {
+				label: None,
+				size,
+				usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+				mapped_at_creation: false,
+			})
+		});
+
+		encoder.resolve_query_set(&gpu.query_set, 0..query_count, &gpu.resolve_buffer, 0);
+		encoder.copy_buffer_to_buffer(
+			&gpu.resolve_buffer,
+			0,
+			&buffer,
+			0,
+			query_count as u64 * wgpu::QUERY_SIZE as u64,
+		);
+
+		self.pending.push(PendingFrame {
+			passes,
+			queries,
+			buffer,
+			mapped: None,
+		});
+	}
+
+	/// Requests the readback of the frames recorded by `end_frame`.
+	pub(crate) fn after_submit(&mut self) {
+		for frame in self.pending.iter_mut().filter(|f| f.mapped.is_none()) {
+			let mapped = Arc::new(AtomicBool::new(false));
+			let done = mapped.clone();
+			frame
+				.buffer
+				.slice(..)
+				.map_async(wgpu::MapMode::Read, move |result| {
+					if result.is_ok() {
+						done.store(true, Ordering::Release);
+					}
+				});
+			frame.mapped = Some(mapped);
+		}
+	}
+
+	/// Reads the timestamps of all frames that finished rendering, without blocking.
+	pub(crate) fn collect(&mut self, device: &wgpu::Device) {
+		if self.pending.is_empty() {
+			return;
+		}
+
+		let _ = device.poll(wgpu::PollType::Poll);
+
+		while let Some(frame) = self.pending.first()
+			&& frame
+				.mapped
+				.as_ref()
+				.is_some_and(|m| m.load(Ordering::Acquire))
+		{
+			let mut frame = self.pending.remove(0);
+			let Some(gpu) = &mut self.gpu else {
+				return;
+			};
+
+			{
+				let data = frame.buffer.slice(..).get_mapped_range();
+				let timestamps: &[u64] = bytemuck::cast_slice(&data);
+
+				for (pass, query) in frame.passes.iter_mut().zip(&frame.queries) {
+					pass.gpu = query.map(|q| {
+						let ticks =
+							timestamps[q as usize + 1].saturating_sub(timestamps[q as usize]);
+						Duration::from_nanos((ticks as f64 * gpu.period) as u64)
+					});
+				}
+			}
+
+			frame.buffer.unmap();
+			gpu.free_buffers.push(frame.buffer);
+			self.timings = frame.passes;
+		}
+	}
+
+	pub(crate) fn timings(&self) -> &[PassTiming] {
+		&self.timings
+	}
+}
@@ -0,0 +1,271 @@
+use crate::{Painter, bind_group::BindGroupLayout, binding::InstanceBinding, error::PainterError};
+
+/// Push constant range of a shade, see `ShadeBuilder::with_push_constants`.
+#[derive(Clone, Copy)]
+pub(crate) struct PushConstantsLayout {
+	pub stages: wgpu::ShaderStages,
+	pub size: u32,
+	/// Bind group index and layout of the dynamic-offset uniform replacing the push constants
+	/// on devices without `Features::PUSH_CONSTANTS`, e.g. WebGPU.
+	pub fallback: Option<(u32, BindGroupLayout)>,
+}
+
+impl PushConstantsLayout {
+	/// The fallback uniform is bound at `group`, the bind group after the value and layer bindings.
+	pub(crate) fn new(
+		painter: &mut Painter,
+		stages: wgpu::ShaderStages,
+		size: u32,
+		group: u32,
+	) -> Self {
+		let supported = painter
+			.device
+			.features()
+			.contains(wgpu::Features::PUSH_CONSTANTS)
+			&& size <= painter.device.limits().max_push_constant_size;
+
+		let fallback = (!supported).then(|| (group, fallback_layout(painter, stages, size)));
+
+		Self {
+			stages,
+			size,
+			fallback,
+		}
+	}
+
+	pub(crate) fn validate_size(size: u32) -> Result<(), PainterError> {
+		if size == 0 || !size.is_multiple_of(wgpu::PUSH_CONSTANT_ALIGNMENT) {
+			return Err(PainterError::InvalidProps(format!(
+				"Push constants size must be a non-zero multiple of {}, got {}",
+				wgpu::PUSH_CONSTANT_ALIGNMENT,
+				size
+			)));
+		}
+		Ok(())
+	}
+
+	/// Checks that the data fits into the push constants, shorter data is padded with zeros.
+	pub(crate) fn validate_data(&self, data: &[u8]) -> Result<(), PainterError> {
+		if data.len() > self.size as usize {
+			return Err(PainterError::InvalidProps(format!(
+				"Push constants data of {} bytes exceeds the shade's push constants size of {} bytes",
+				data.len(),
+				self.size
+			)));
+		}
+		Ok(())
+	}
+
+	pub(crate) fn range(&self) -> Option<wgpu::PushConstantRange> {
+		self.fallback.is_none().then_some(wgpu::PushConstantRange {
+			stages: self.stages,
+			range: 0..self.size,
+		})
+	}
+
+	/// The bind group index of the fallback uniform, if used.
+	pub(crate) fn fallback_group(&self) -> Option<u32> {
+		self.fallback.map(|(group, _)| group)
+	}
+}
+
+/// Uniform bindings are sized in multiples of 16 bytes.
+fn uniform_size(size: u32) -> u64 {
+	(size as u64).next_multiple_of(16)
+}
+
+/// The layout of the fallback uniform, shared by all shades with the same stages and size.
+fn fallback_layout(
+	painter: &mut Painter,
+	stages: wgpu::ShaderStages,
+	size: u32,
+) -> BindGroupLayout {
+	let key = (stages.bits(), size);
+	if let Some(layout) = painter.push_constants_fallback_layouts.get(&key) {
+		return *layout;
+	}
+
+	let layout = painter
+		.device
+		.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: None,
+			entries: &[wgpu::BindGroupLayoutEntry {
+				binding: 0,
+				visibility: stages,
+				ty: wgpu::BindingType::Buffer {
+					ty: wgpu::BufferBindingType::Uniform,
+					has_dynamic_offset: true,
+					min_binding_size: wgpu::BufferSize::new(uniform_size(size)),
+				},
+				count: None,
+			}],
+		});
+
+	painter.bind_group_layouts.push(layout);
+	let layout = BindGroupLayout(painter.bind_group_layouts.len() - 1);
+	painter.push_constants_fallback_layouts.insert(key, layout);
+	layout
+}
+
+#[derive(Clone)]
+struct FallbackBuffer {
+	buffer: wgpu::Buffer,
+	bind_group: wgpu::BindGroup,
+	stride: u64,
+}
+
+/// Push constant data of a shape, one entry per draw.
+#[derive(Clone)]
+pub(crate) struct PushConstantsData {
+	layout: PushConstantsLayout,
+	base: Vec<u8>,
+	/// A single entry, unless instances override the shape's data.
+	pub data: Vec<Vec<u8>>,
+	overrides: Vec<bool>,
+	fallback: Option<FallbackBuffer>,
+}
+
+impl PushConstantsData {
+	pub(crate) fn new(
+		painter: &Painter,
+		layout: PushConstantsLayout,
+		base: Option<&[u8]>,
+		instances: &[InstanceBinding],
+	) -> Result<Self, PainterError> {
+		let base = padded(&layout, base.unwrap_or(&[]))?;
+
+		let (data, overrides) = if instances.iter().any(|i| i.push_constants.is_some()) {
+			instances
+				.iter()
+				.map(|i| match &i.push_constants {
+					Some(d) => Ok((padded(&layout, d)?, true)),
+					None => Ok((base.clone(), false)),
+				})
+				.collect::<Result<Vec<_>, PainterError>>()?
+				.into_iter()
+				.unzip()
+		} else {
+			(vec![base.clone()], vec![false])
+		};
+
+		let mut pc = Self {
+			layout,
+			base,
+			data,
+			overrides,
+			fallback: None,
+		};
+		pc.create_fallback_buffer(painter);
+		Ok(pc)
+	}
+
+	fn create_fallback_buffer(&mut self, painter: &Painter) {
+		let Some((_, layout)) = self.layout.fallback else {
+			return;
+		};
+
+		let stride = uniform_size(self.layout.size)
+			.next_multiple_of(painter.device.limits().min_uniform_buffer_offset_alignment as u64);
+
+		let buffer = painter.device.create_buffer(&wgpu::BufferDescriptor {
+			label: None,
+			size: stride * self.data.len() as u64,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+
+		let bind_group = painter
+			.device
+			.create_bind_group(&wgpu::BindGroupDescriptor {
+				label: None,
+				layout: &painter.bind_group_layouts[layout.0],
+				entries: &[wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+						buffer: &buffer,
+						offset: 0,
+						size: wgpu::BufferSize::new(uniform_size(self.layout.size)),
+					}),
+				}],
+			});
+
+		self.fallback = Some(FallbackBuffer {
+			buffer,
+			bind_group,
+			stride,
+		});
+
+		for i in 0..self.data.len() {
+			self.write(painter, i);
+		}
+	}
+
+	fn write(&self, painter: &Painter, index: usize) {
+		if let Some(f) = &self.fallback {
+			painter
+				.queue
+				.write_buffer(&f.buffer, f.stride * index as u64, &self.data[index]);
+		}
+	}
+
+	/// Sets the data of all draws not overridden by an instance.
+	pub(crate) fn set(&mut self, painter: &Painter, data: &[u8]) -> Result<(), PainterError> {
+		self.base = padded(&self.layout, data)?;
+		for i in 0..self.data.len() {
+			if !self.overrides[i] {
+				self.data[i] = self.base.clone();
+				self.write(painter, i);
+			}
+		}
+		Ok(())
+	}
+
+	/// Overrides the data of a single instance of a shape with `instance_count` instances.
+	pub(crate) fn set_instance(
+		&mut self,
+		painter: &Painter,
+		instance_count: usize,
+		index: usize,
+		data: &[u8],
+	) -> Result<(), PainterError> {
+		if index >= instance_count {
+			return Err(PainterError::InvalidProps(format!(
+				"Instance index {} out of range, the shape has {} instances",
+				index, instance_count
+			)));
+		}
+		let data = padded(&self.layout, data)?;
+
+		if self.data.len() != instance_count {
+			self.data = vec![self.base.clone(); instance_count];
+			self.overrides = vec![false; instance_count];
+			self.create_fallback_buffer(painter);
+		}
+
+		self.data[index] = data;
+		self.overrides[index] = true;
+		self.write(painter, index);
+		Ok(())
+	}
+
+	/// Sets the push constants or binds the fallback uniform for the given draw.
+	pub(crate) fn apply(&self, pass: &mut wgpu::RenderPass<'_>, index: usize) {
+		let index = index.min(self.data.len() - 1);
+
+		match (&self.fallback, self.layout.fallback_group()) {
+			(Some(f), Some(group)) => {
+				pass.set_bind_group(group, &f.bind_group, &[(f.stride * index as u64) as u32]);
+			}
+			_ => pass.set_push_constants(self.layout.stages, 0, &self.data[index]),
+		}
+	}
+}
+
+/// Pads the data with zeros to the push constants size.
+fn padded(layout: &PushConstantsLayout, data: &[u8]) -> Result<Vec<u8>, PainterError> {
+	layout.validate_data(data)?;
+
+	let mut bytes = data.to_vec();
+	bytes.resize(layout.size as usize, 0);
+	Ok(bytes)
+}
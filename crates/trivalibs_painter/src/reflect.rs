@@ -0,0 +1,458 @@
+//! # Shader Interface Validation
+//!
+//! Shader modules are reflected with naga and compared to the layouts of their shade,
+//! before a render pipeline is created from them. Mismatching bindings or vertex inputs
+//! are reported with the bind group, binding or location, instead of failing deep
+//! inside pipeline creation.
+//!
+//! SPIR-V modules are validated on all targets, WGSL modules only on native targets.
+//! Modules naga can not parse or validate are skipped, wgpu reports their errors itself.
+
+use crate::shade::ShadeStorage;
+use std::{fmt, path::Path};
+use wgpu::naga;
+
+/// A shader module that does not match the layouts of its shade, see `Shade::validate`.
+#[derive(Clone, Debug)]
+pub struct ShaderInterfaceError {
+	/// The stage of the mismatching shader module.
+	pub stage: wgpu::ShaderStages,
+	/// Path of the shader file, if the shader was loaded from a path.
+	pub path: Option<String>,
+	/// Description of the mismatch, naming the bind group and binding or the vertex location.
+	pub message: String,
+}
+
+impl fmt::Display for ShaderInterfaceError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let stage = if self.stage == wgpu::ShaderStages::VERTEX {
+			"Vertex"
+		} else {
+			"Fragment"
+		};
+		write!(f, "{} shader", stage)?;
+
+		if let Some(path) = &self.path {
+			write!(f, " {}", path)?;
+
+			// Compiled SPIR-V files are placed next to the Cargo.toml of their shader crate
+			if let Some(dir) = Path::new(path).parent()
+				&& dir.join("Cargo.toml").exists()
+			{
+				write!(f, " (shader crate {})", dir.display())?;
+			}
+		}
+
+		write!(f, " does not match its shade: {}", self.message)
+	}
+}
+
+impl std::error::Error for ShaderInterfaceError {}
+
+/// A bind group of the pipeline layout of a shade.
+struct GroupLayout {
+	name: &'static str,
+	entries: Vec<(wgpu::BindingType, wgpu::ShaderStages)>,
+}
+
+/// See `Shade::validate`.
+pub(crate) fn validate_shade(s: &ShadeStorage) -> Result<(), ShaderInterfaceError> {
+	let pc_group = s.push_constants.and_then(|p| p.fallback_group());
+
+	let mut groups = Vec::with_capacity(3);
+	if !s.binding_layouts.is_empty() {
+		groups.push(GroupLayout {
+			name: "value bindings",
+			entries: s
+				.binding_layouts
+				.iter()
+				.map(|b| (b.binding_type, b.visibility))
+				.collect(),
+		});
+	}
+	if !s.layer_layouts.is_empty() {
+		groups.push(GroupLayout {
+			name: "layer bindings",
+			entries: s
+				.layer_layouts
+				.iter()
+				.map(|l| {
+					let ty = wgpu::BindingType::Texture {
+						multisampled: false,
+						view_dimension: l.view_dimension,
+						sample_type: l.sample_type,
+					};
+					(ty, l.visibility)
+				})
+				.collect(),
+		});
+	}
+	if let Some(pc) = s.push_constants.filter(|_| pc_group.is_some()) {
+		let ty = wgpu::BindingType::Buffer {
+			ty: wgpu::BufferBindingType::Uniform,
+			has_dynamic_offset: true,
+			min_binding_size: None,
+		};
+		groups.push(GroupLayout {
+			name: "push constants",
+			entries: vec![(ty, pc.stages)],
+		});
+	}
+
+	let stages = [
+		(wgpu::ShaderStages::VERTEX, &s.vertex_code, &s.vertex_path),
+		(
+			wgpu::ShaderStages::FRAGMENT,
+			&s.fragment_code,
+			&s.fragment_path,
+		),
+	];
+
+	for (stage, code, path) in stages {
+		let Some(code) = code else {
+			continue;
+		};
+		let Some(module) = code.module(pc_group) else {
+			continue;
+		};
+
+		let error = |message: String| ShaderInterfaceError {
+			stage,
+			path: path.clone(),
+			message,
+		};
+
+		let Ok(info) = naga::valid::Validator::new(
+			naga::valid::ValidationFlags::all(),
+			naga::valid::Capabilities::all(),
+		)
+		.validate(&module) else {
+			continue;
+		};
+
+		let naga_stage = if stage == wgpu::ShaderStages::VERTEX {
+			naga::ShaderStage::Vertex
+		} else {
+			naga::ShaderStage::Fragment
+		};
+		let Some((ep_index, ep)) = module
+			.entry_points
+			.iter()
+			.enumerate()
+			.find(|(_, ep)| ep.stage == naga_stage)
+		else {
+			continue;
+		};
+		let ep_info = info.get_entry_point(ep_index);
+
+		for (handle, var) in module.global_variables.iter() {
+			if ep_info[handle].is_empty() {
+				continue;
+			}
+
+			if var.space == naga::AddressSpace::PushConstant {
+				if s.push_constants.is_none() {
+					let message = "it uses push constants, but the shade declares none";
+					return Err(error(message.to_string()));
+				}
+				if let Some(group) = pc_group {
+					return Err(error(format!(
+						"it uses push constants, which the device does not support, declare them as uniform at group {} binding 0 instead",
+						group
+					)));
+				}
+			}
+
+			let Some(binding) = &var.binding else {
+				continue;
+			};
+			check_binding(&module, var, binding, stage, &groups).map_err(error)?;
+		}
+
+		if stage == wgpu::ShaderStages::VERTEX {
+			let attributes = s
+				.attribs
+				.attributes
+				.iter()
+				.chain(s.instance_attribs.iter().flat_map(|a| a.attributes.iter()));
+			check_vertex_inputs(&module, ep, attributes).map_err(error)?;
+		}
+	}
+
+	Ok(())
+}
+
+fn check_binding(
+	module: &naga::Module,
+	var: &naga::GlobalVariable,
+	binding: &naga::ResourceBinding,
+	stage: wgpu::ShaderStages,
+	groups: &[GroupLayout],
+) -> Result<(), String> {
+	let (group, index) = (binding.group, binding.binding);
+	let shader_type = describe_shader_resource(module, var);
+
+	let Some(layout) = groups.get(group as usize) else {
+		return Err(format!(
+			"{} at group {} binding {} is not declared, the shade has {}",
+			shader_type,
+			group,
+			index,
+			describe_groups(groups)
+		));
+	};
+
+	let Some((ty, visibility)) = layout.entries.get(index as usize) else {
+		return Err(format!(
+			"{} at group {} binding {} is not declared, group {} holds {} {}",
+			shader_type,
+			group,
+			index,
+			group,
+			layout.entries.len(),
+			layout.name
+		));
+	};
+
+	if !visibility.contains(stage) {
+		return Err(format!(
+			"group {} binding {} of the {} is not visible in this stage, it is declared for {:?}",
+			group, index, layout.name, visibility
+		));
+	}
+
+	if !matches_binding_type(module, var, ty) {
+		return Err(format!(
+			"group {} binding {} is a {} in the shader, but the shade declares a {}",
+			group,
+			index,
+			shader_type,
+			describe_binding_type(ty)
+		));
+	}
+
+	Ok(())
+}
+
+/// Mirrors the binding checks of wgpu's pipeline creation.
+fn matches_binding_type(
+	module: &naga::Module,
+	var: &naga::GlobalVariable,
+	ty: &wgpu::BindingType,
+) -> bool {
+	match (var.space, ty) {
+		(naga::AddressSpace::Uniform, wgpu::BindingType::Buffer { ty, .. }) => {
+			*ty == wgpu::BufferBindingType::Uniform
+		}
+		(naga::AddressSpace::Storage { access }, wgpu::BindingType::Buffer { ty, .. }) => {
+			match ty {
+				wgpu::BufferBindingType::Storage { read_only } => {
+					access.contains(naga::StorageAccess::STORE) != *read_only
+				}
+				wgpu::BufferBindingType::Uniform => false,
+			}
+		}
+		(naga::AddressSpace::Handle, _) => match (&module.types[var.ty].inner, ty) {
+			(naga::TypeInner::Sampler { comparison }, wgpu::BindingType::Sampler(sampler)) => {
+				*comparison == (*sampler == wgpu::SamplerBindingType::Comparison)
+			}
+			(
+				naga::TypeInner::Image {
+					dim,
+					arrayed,
+					class,
+				},
+				wgpu::BindingType::Texture {
+					view_dimension,
+					sample_type,
+					multisampled,
+				},
+			) => {
+				let class_matches = match (class, sample_type) {
+					(
+						naga::ImageClass::Sampled { kind, multi },
+						wgpu::TextureSampleType::Float { .. },
+					) => *kind == naga::ScalarKind::Float && multi == multisampled,
+					(naga::ImageClass::Sampled { kind, multi }, wgpu::TextureSampleType::Sint) => {
+						*kind == naga::ScalarKind::Sint && multi == multisampled
+					}
+					(naga::ImageClass::Sampled { kind, multi }, wgpu::TextureSampleType::Uint) => {
+						*kind == naga::ScalarKind::Uint && multi == multisampled
+					}
+					(naga::ImageClass::Depth { multi }, wgpu::TextureSampleType::Depth) => {
+						multi == multisampled
+					}
+					_ => false,
+				};
+				class_matches && view_dimension_of(*dim, *arrayed) == Some(*view_dimension)
+			}
+			(
+				naga::TypeInner::Image {
+					dim,
+					arrayed,
+					class: naga::ImageClass::Storage { .. },
+				},
+				wgpu::BindingType::StorageTexture { view_dimension, .. },
+			) => view_dimension_of(*dim, *arrayed) == Some(*view_dimension),
+			_ => false,
+		},
+		_ => false,
+	}
+}
+
+fn view_dimension_of(
+	dim: naga::ImageDimension,
+	arrayed: bool,
+) -> Option<wgpu::TextureViewDimension> {
+	match (dim, arrayed) {
+		(naga::ImageDimension::D1, false) => Some(wgpu::TextureViewDimension::D1),
+		(naga::ImageDimension::D2, false) => Some(wgpu::TextureViewDimension::D2),
+		(naga::ImageDimension::D2, true) => Some(wgpu::TextureViewDimension::D2Array),
+		(naga::ImageDimension::D3, false) => Some(wgpu::TextureViewDimension::D3),
+		(naga::ImageDimension::Cube, false) => Some(wgpu::TextureViewDimension::Cube),
+		(naga::ImageDimension::Cube, true) => Some(wgpu::TextureViewDimension::CubeArray),
+		_ => None,
+	}
+}
+
+fn describe_shader_resource(module: &naga::Module, var: &naga::GlobalVariable) -> String {
+	match var.space {
+		naga::AddressSpace::Uniform => "uniform buffer".to_string(),
+		naga::AddressSpace::Storage { access } if access.contains(naga::StorageAccess::STORE) => {
+			"read-write storage buffer".to_string()
+		}
+		naga::AddressSpace::Storage { .. } => "read-only storage buffer".to_string(),
+		_ => match &module.types[var.ty].inner {
+			naga::TypeInner::Sampler { comparison: true } => "comparison sampler".to_string(),
+			naga::TypeInner::Sampler { comparison: false } => "sampler".to_string(),
+			naga::TypeInner::Image {
+				dim,
+				arrayed,
+				class,
+			} => {
+				let dimension = view_dimension_of(*dim, *arrayed)
+					.map_or("unknown".to_string(), |d| format!("{:?}", d));
+				match class {
+					naga::ImageClass::Sampled { kind, multi: false } => {
+						format!("{} {:?} texture", dimension, kind)
+					}
+					naga::ImageClass::Sampled { kind, multi: true } => {
+						format!("multisampled {} {:?} texture", dimension, kind)
+					}
+					naga::ImageClass::Depth { .. } => format!("{} depth texture", dimension),
+					naga::ImageClass::Storage { format, .. } => {
+						format!("{} {:?} storage texture", dimension, format)
+					}
+				}
+			}
+			_ => "resource".to_string(),
+		},
+	}
+}
+
+fn describe_binding_type(ty: &wgpu::BindingType) -> String {
+	match ty {
+		wgpu::BindingType::Buffer {
+			ty: wgpu::BufferBindingType::Uniform,
+			..
+		} => "uniform buffer".to_string(),
+		wgpu::BindingType::Buffer {
+			ty: wgpu::BufferBindingType::Storage { read_only: true },
+			..
+		} => "read-only storage buffer".to_string(),
+		wgpu::BindingType::Buffer { .. } => "read-write storage buffer".to_string(),
+		wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison) => {
+			"comparison sampler".to_string()
+		}
+		wgpu::BindingType::Sampler(_) => "sampler".to_string(),
+		wgpu::BindingType::Texture {
+			view_dimension,
+			sample_type,
+			..
+		} => format!(
+			"{:?} texture with {:?} samples",
+			view_dimension, sample_type
+		),
+		wgpu::BindingType::StorageTexture {
+			view_dimension,
+			format,
+			..
+		} => format!("{:?} {:?} storage texture", view_dimension, format),
+		_ => format!("{:?}", ty),
+	}
+}
+
+fn describe_groups(groups: &[GroupLayout]) -> String {
+	if groups.is_empty() {
+		return "no bind groups".to_string();
+	}
+
+	groups
+		.iter()
+		.enumerate()
+		.map(|(i, g)| format!("{} {} at group {}", g.entries.len(), g.name, i))
+		.collect::<Vec<_>>()
+		.join(" and ")
+}
+
+fn check_vertex_inputs<'a>(
+	module: &naga::Module,
+	ep: &naga::EntryPoint,
+	attributes: impl Iterator<Item = &'a wgpu::VertexAttribute>,
+) -> Result<(), String> {
+	let attributes: Vec<_> = attributes.collect();
+
+	// Vertex inputs are either arguments or members of struct arguments
+	let mut inputs = Vec::new();
+	for arg in &ep.function.arguments {
+		match (&arg.binding, &module.types[arg.ty].inner) {
+			(Some(binding), _) => inputs.push((binding, arg.ty)),
+			(None, naga::TypeInner::Struct { members, .. }) => {
+				for m in members {
+					if let Some(binding) = &m.binding {
+						inputs.push((binding, m.ty));
+					}
+				}
+			}
+			_ => {}
+		}
+	}
+
+	for (binding, ty) in inputs {
+		let naga::Binding::Location { location, .. } = binding else {
+			continue;
+		};
+
+		let Some(attribute) = attributes.iter().find(|a| a.shader_location == *location) else {
+			return Err(format!(
+				"vertex input at location {} is not provided, the shade has {} vertex attributes",
+				location,
+				attributes.len()
+			));
+		};
+
+		let kind = match &module.types[ty].inner {
+			naga::TypeInner::Scalar(scalar) | naga::TypeInner::Vector { scalar, .. } => scalar.kind,
+			_ => continue,
+		};
+		if kind != vertex_format_kind(attribute.format) {
+			return Err(format!(
+				"vertex input at location {} is of {:?} type, but the shade attribute has format {:?}",
+				location, kind, attribute.format
+			));
+		}
+	}
+
+	Ok(())
+}
+
+fn vertex_format_kind(format: wgpu::VertexFormat) -> naga::ScalarKind {
+	use wgpu::VertexFormat::*;
+	match format {
+		Uint8 | Uint8x2 | Uint8x4 | Uint16 | Uint16x2 | Uint16x4 | Uint32 | Uint32x2 | Uint32x3
+		| Uint32x4 => naga::ScalarKind::Uint,
+		Sint8 | Sint8x2 | Sint8x4 | Sint16 | Sint16x2 | Sint16x4 | Sint32 | Sint32x2 | Sint32x3
+		| Sint32x4 => naga::ScalarKind::Sint,
+		_ => naga::ScalarKind::Float,
+	}
+}
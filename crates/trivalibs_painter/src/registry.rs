@@ -0,0 +1,205 @@
+//! # Resource Registry
+//!
+//! Painter resources (forms, shades, shapes, layers, ...) are stored in registries
+//! and referenced by lightweight copyable handles.
+//!
+//! Destroyed resources free their slot, which is reused by the next created resource.
+//! Each slot carries a generation counter that is increased on every reuse,
+//! so a handle to a destroyed resource never silently refers to a newer one.
+//! Accessing a resource through such a stale handle panics.
+
+use std::ops::{Index, IndexMut};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) struct Handle {
+	pub index: usize,
+	pub generation: u32,
+}
+
+impl Handle {
+	/// The index and generation as bytes, used to identify resources in pipeline keys.
+	pub fn key_bytes(&self) -> [u8; 12] {
+		let mut bytes = [0; 12];
+		bytes[..8].copy_from_slice(&(self.index as u64).to_le_bytes());
+		bytes[8..].copy_from_slice(&self.generation.to_le_bytes());
+		bytes
+	}
+}
+
+struct Slot<T> {
+	generation: u32,
+	value: Option<T>,
+}
+
+pub(crate) struct Registry<T> {
+	slots: Vec<Slot<T>>,
+	free: Vec<usize>,
+}
+
+impl<T> Registry<T> {
+	pub fn with_capacity(capacity: usize) -> Self {
+		Registry {
+			slots: Vec::with_capacity(capacity),
+			free: Vec::new(),
+		}
+	}
+
+	pub fn insert(&mut self, value: T) -> Handle {
+		if let Some(index) = self.free.pop() {
+			let slot = &mut self.slots[index];
+			slot.value = Some(value);
+			return Handle {
+				index,
+				generation: slot.generation,
+			};
+		}
+
+		self.slots.push(Slot {
+			generation: 0,
+			value: Some(value),
+		});
+
+		Handle {
+			index: self.slots.len() - 1,
+			generation: 0,
+		}
+	}
+
+	/// Removes the resource and frees its slot for reuse.
+	/// Returns `None` if the handle is stale.
+	pub fn remove(&mut self, handle: Handle) -> Option<T> {
+		if !self.contains(handle) {
+			return None;
+		}
+
+		let slot = &mut self.slots[handle.index];
+		slot.generation = slot.generation.wrapping_add(1);
+		self.free.push(handle.index);
+		slot.value.take()
+	}
+
+	pub fn contains(&self, handle: Handle) -> bool {
+		self.get(handle).is_some()
+	}
+
+	pub fn get(&self, handle: Handle) -> Option<&T> {
+		self.slots
+			.get(handle.index)
+			.filter(|slot| slot.generation == handle.generation)
+			.and_then(|slot| slot.value.as_ref())
+	}
+
+	pub fn get_mut(&mut self, handle: Handle) -> Option<&mut T> {
+		self.slots
+			.get_mut(handle.index)
+			.filter(|slot| slot.generation == handle.generation)
+			.and_then(|slot| slot.value.as_mut())
+	}
+
+	/// Iterates all live resources with their handles.
+	pub fn iter(&self) -> impl Iterator<Item = (Handle, &T)> {
+		self.slots.iter().enumerate().filter_map(|(index, slot)| {
+			slot.value.as_ref().map(|value| {
+				(
+					Handle {
+						index,
+						generation: slot.generation,
+					},
+					value,
+				)
+			})
+		})
+	}
+
+	pub fn handles(&self) -> Vec<Handle> {
+		self.iter().map(|(handle, _)| handle).collect()
+	}
+}
+
+impl<T> Index<Handle> for Registry<T> {
+	type Output = T;
+
+	fn index(&self, handle: Handle) -> &T {
+		self.get(handle)
+			.expect("Painter resource was destroyed or the handle is stale")
+	}
+}
+
+impl<T> IndexMut<Handle> for Registry<T> {
+	fn index_mut(&mut self, handle: Handle) -> &mut T {
+		self.get_mut(handle)
+			.expect("Painter resource was destroyed or the handle is stale")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn reuses_freed_slots_with_new_generation() {
+		let mut registry = Registry::with_capacity(2);
+		let a = registry.insert("a");
+		let b = registry.insert("b");
+
+		assert_eq!(registry.remove(a), Some("a"));
+		assert_eq!(registry.remove(a), None);
+
+		let c = registry.insert("c");
+		assert_eq!(c.index, a.index);
+		assert_eq!(c.generation, a.generation + 1);
+
+		assert!(!registry.contains(a));
+		assert_eq!(registry[c], "c");
+		assert_eq!(registry[b], "b");
+		assert_eq!(registry.handles(), vec![c, b]);
+	}
+
+	#[test]
+	#[should_panic(expected = "stale")]
+	fn panics_on_stale_handle() {
+		let mut registry = Registry::with_capacity(1);
+		let a = registry.insert(1);
+		registry.remove(a);
+		registry.insert(2);
+
+		let _ = registry[a];
+	}
+
+	#[test]
+	fn wraps_generation_around() {
+		let mut registry = Registry::with_capacity(1);
+		let a = registry.insert(1);
+		registry.slots[a.index].generation = u32::MAX;
+		let a = Handle {
+			index: a.index,
+			generation: u32::MAX,
+		};
+
+		assert_eq!(registry.remove(a), Some(1));
+
+		let b = registry.insert(2);
+		assert_eq!(b.generation, 0);
+		assert!(!registry.contains(a));
+		assert_eq!(registry[b], 2);
+	}
+
+	#[test]
+	fn key_bytes_differ_by_index_and_generation() {
+		let a = Handle {
+			index: 1,
+			generation: 0,
+		};
+		let b = Handle {
+			index: 1 + (1 << 16),
+			generation: 0,
+		};
+		let c = Handle {
+			index: 1,
+			generation: 1,
+		};
+
+		assert_ne!(a.key_bytes(), b.key_bytes());
+		assert_ne!(a.key_bytes(), c.key_bytes());
+	}
+}
@@ -1,13 +1,20 @@
-use crate::{binding::ValueBinding, Painter};
+use crate::{Painter, binding::ValueBinding, error::PainterError};
 
 #[derive(Clone, Copy)]
 pub struct SamplerProps {
 	pub address_mode_u: wgpu::AddressMode,
 	pub address_mode_v: wgpu::AddressMode,
+	pub address_mode_w: wgpu::AddressMode,
 	pub mag_filter: wgpu::FilterMode,
 	pub min_filter: wgpu::FilterMode,
 	pub mipmap_filter: wgpu::FilterMode,
 	pub sample_depth: bool,
+	/// Maximum anisotropy, 1 disables anisotropic filtering. Values above 1 require linear filters.
+	pub anisotropy_clamp: u16,
+	pub lod_min_clamp: f32,
+	pub lod_max_clamp: f32,
+	/// Color outside the texture for `AddressMode::ClampToBorder`.
+	pub border_color: Option<wgpu::SamplerBorderColor>,
 }
 
 impl Default for SamplerProps {
@@ -20,41 +27,129 @@ impl SamplerProps {
 	pub const NEAREST: SamplerProps = SamplerProps {
 		address_mode_u: wgpu::AddressMode::ClampToEdge,
 		address_mode_v: wgpu::AddressMode::ClampToEdge,
+		address_mode_w: wgpu::AddressMode::ClampToEdge,
 		mag_filter: wgpu::FilterMode::Nearest,
 		min_filter: wgpu::FilterMode::Nearest,
 		mipmap_filter: wgpu::FilterMode::Nearest,
 		sample_depth: false,
+		anisotropy_clamp: 1,
+		lod_min_clamp: 0.0,
+		lod_max_clamp: 32.0,
+		border_color: None,
 	};
 
 	pub const LINEAR: SamplerProps = SamplerProps {
 		address_mode_u: wgpu::AddressMode::ClampToEdge,
 		address_mode_v: wgpu::AddressMode::ClampToEdge,
+		address_mode_w: wgpu::AddressMode::ClampToEdge,
 		mag_filter: wgpu::FilterMode::Linear,
 		min_filter: wgpu::FilterMode::Linear,
 		mipmap_filter: wgpu::FilterMode::Nearest,
 		sample_depth: false,
+		anisotropy_clamp: 1,
+		lod_min_clamp: 0.0,
+		lod_max_clamp: 32.0,
+		border_color: None,
 	};
+
+	/// The same checks as `Device::create_sampler`, which would panic instead.
+	fn validate(&self, features: wgpu::Features) -> Result<(), PainterError> {
+		let invalid = |message: String| Err(PainterError::InvalidProps(message));
+
+		let address_modes = [
+			self.address_mode_u,
+			self.address_mode_v,
+			self.address_mode_w,
+		];
+		if address_modes.contains(&wgpu::AddressMode::ClampToBorder)
+			&& !features.contains(wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER)
+		{
+			return invalid(
+				"Sampler address mode ClampToBorder requires Features::ADDRESS_MODE_CLAMP_TO_BORDER"
+					.to_string(),
+			);
+		}
+		if self.border_color == Some(wgpu::SamplerBorderColor::Zero)
+			&& !features.contains(wgpu::Features::ADDRESS_MODE_CLAMP_TO_ZERO)
+		{
+			return invalid(
+				"Sampler border color Zero requires Features::ADDRESS_MODE_CLAMP_TO_ZERO"
+					.to_string(),
+			);
+		}
+
+		if self.lod_min_clamp < 0.0 || self.lod_max_clamp < self.lod_min_clamp {
+			return invalid(format!(
+				"Invalid sampler LOD clamp {}..{}",
+				self.lod_min_clamp, self.lod_max_clamp
+			));
+		}
+
+		if self.anisotropy_clamp < 1 {
+			return invalid(format!(
+				"Sampler anisotropy clamp must be at least 1, got {}",
+				self.anisotropy_clamp
+			));
+		}
+		let filters = [self.mag_filter, self.min_filter, self.mipmap_filter];
+		if self.anisotropy_clamp > 1 && filters.iter().any(|f| *f != wgpu::FilterMode::Linear) {
+			return invalid(format!(
+				"Sampler anisotropy clamp {} requires linear mag, min and mipmap filters",
+				self.anisotropy_clamp
+			));
+		}
+
+		Ok(())
+	}
+
+	/// The binding type of samplers with these props, see `BindingLayout::for_format`.
+	///
+	/// Samplers with only nearest filters can be bound as filtering or non-filtering samplers.
+	pub fn binding_type(&self) -> wgpu::SamplerBindingType {
+		if self.sample_depth {
+			wgpu::SamplerBindingType::Comparison
+		} else if self.mag_filter == wgpu::FilterMode::Linear
+			|| self.min_filter == wgpu::FilterMode::Linear
+			|| self.mipmap_filter == wgpu::FilterMode::Linear
+		{
+			wgpu::SamplerBindingType::Filtering
+		} else {
+			wgpu::SamplerBindingType::NonFiltering
+		}
+	}
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub struct Sampler(pub(crate) usize);
 
 impl Sampler {
 	pub fn create(painter: &mut Painter, props: SamplerProps) -> Self {
+		Self::try_create(painter, props).unwrap_or_else(|err| panic!("{}", err))
+	}
+
+	/// Like `create`, but returns an error for props rejected by the device,
+	/// e.g. anisotropy with nearest filters or border colors without the required features.
+	pub fn try_create(painter: &mut Painter, props: SamplerProps) -> Result<Self, PainterError> {
+		props.validate(painter.device.features())?;
+
 		let sampler = painter.device.create_sampler(&wgpu::SamplerDescriptor {
 			address_mode_u: props.address_mode_u,
 			address_mode_v: props.address_mode_v,
-			address_mode_w: wgpu::AddressMode::ClampToEdge,
+			address_mode_w: props.address_mode_w,
 			mag_filter: props.mag_filter,
 			min_filter: props.min_filter,
 			mipmap_filter: props.mipmap_filter,
 			compare: props.sample_depth.then(|| wgpu::CompareFunction::LessEqual),
+			anisotropy_clamp: props.anisotropy_clamp,
+			lod_min_clamp: props.lod_min_clamp,
+			lod_max_clamp: props.lod_max_clamp,
+			border_color: props.border_color,
 			..Default::default()
 		});
 
 		painter.samplers.push(sampler);
 
-		Self(painter.samplers.len() - 1)
+		Ok(Self(painter.samplers.len() - 1))
 	}
 
 	pub fn binding(&self) -> ValueBinding {
@@ -79,6 +174,10 @@ impl<'a> SamplerBuilder<'a> {
 		Sampler::create(self.painter, self.props)
 	}
 
+	pub fn try_create(self) -> Result<Sampler, PainterError> {
+		Sampler::try_create(self.painter, self.props)
+	}
+
 	pub fn with_address_mode_u(mut self, mode: wgpu::AddressMode) -> Self {
 		self.props.address_mode_u = mode;
 		self
@@ -89,12 +188,33 @@ impl<'a> SamplerBuilder<'a> {
 		self
 	}
 
+	pub fn with_address_mode_w(mut self, mode: wgpu::AddressMode) -> Self {
+		self.props.address_mode_w = mode;
+		self
+	}
+
 	pub fn with_address_modes(mut self, mode: wgpu::AddressMode) -> Self {
 		self.props.address_mode_u = mode;
 		self.props.address_mode_v = mode;
+		self.props.address_mode_w = mode;
 		self
 	}
 
+	pub fn with_repeat(self) -> Self {
+		self.with_address_modes(wgpu::AddressMode::Repeat)
+	}
+
+	pub fn with_mirror_repeat(self) -> Self {
+		self.with_address_modes(wgpu::AddressMode::MirrorRepeat)
+	}
+
+	/// Clamps to the given border color on all axes.
+	/// Requires `Features::ADDRESS_MODE_CLAMP_TO_BORDER`, or `ADDRESS_MODE_CLAMP_TO_ZERO` for `Zero`.
+	pub fn with_border_color(mut self, color: wgpu::SamplerBorderColor) -> Self {
+		self.props.border_color = Some(color);
+		self.with_address_modes(wgpu::AddressMode::ClampToBorder)
+	}
+
 	pub fn with_mag_filter(mut self, filter: wgpu::FilterMode) -> Self {
 		self.props.mag_filter = filter;
 		self
@@ -120,4 +240,20 @@ impl<'a> SamplerBuilder<'a> {
 		self.props.sample_depth = true;
 		self
 	}
+
+	/// Enables anisotropic filtering. Sets all filters to linear, as required by wgpu.
+	pub fn with_anisotropy(mut self, max_anisotropy: u16) -> Self {
+		self.props.anisotropy_clamp = max_anisotropy;
+		self.props.mag_filter = wgpu::FilterMode::Linear;
+		self.props.min_filter = wgpu::FilterMode::Linear;
+		self.props.mipmap_filter = wgpu::FilterMode::Linear;
+		self
+	}
+
+	/// Restricts the mip levels used for sampling.
+	pub fn with_lod_clamp(mut self, min: f32, max: f32) -> Self {
+		self.props.lod_min_clamp = min;
+		self.props.lod_max_clamp = max;
+		self
+	}
 }
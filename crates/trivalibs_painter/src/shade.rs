@@ -2,42 +2,147 @@ use crate::{
 	Painter,
 	bind_group::BindGroupLayout,
 	binding::{BindingLayout, LayerLayout},
+	error::PainterError,
 	prelude::BINDING_LAYER_FRAG,
+	push_constants::PushConstantsLayout,
+	reflect::{ShaderInterfaceError, validate_shade},
+	registry::Handle,
 };
-use std::fs;
+use std::{borrow::Cow, cell::OnceCell, fs};
+use wgpu::{naga, util::make_spirv};
+
+/// Code of a single shader stage, either compiled SPIR-V or WGSL source.
+pub(crate) enum ShaderCode {
+	SpirV(Vec<u8>),
+	Wgsl(String),
+}
+
+impl ShaderCode {
+	/// Reads a shader file. Files with `.wgsl` extension are read as WGSL source, all others as SPIR-V.
+	pub(crate) fn read(path: &str) -> std::io::Result<Self> {
+		if path.ends_with(".wgsl") {
+			fs::read_to_string(path).map(ShaderCode::Wgsl)
+		} else {
+			fs::read(path).map(ShaderCode::SpirV)
+		}
+	}
+
+	/// The raw code, used to detect shader changes for the persisted pipeline cache.
+	pub(crate) fn as_bytes(&self) -> &[u8] {
+		match self {
+			ShaderCode::SpirV(bytes) => bytes,
+			ShaderCode::Wgsl(code) => code.as_bytes(),
+		}
+	}
+
+	pub(crate) fn source(&self) -> wgpu::ShaderSource<'_> {
+		match self {
+			ShaderCode::SpirV(bytes) => make_spirv(bytes),
+			ShaderCode::Wgsl(code) => wgpu::ShaderSource::Wgsl(Cow::Borrowed(code)),
+		}
+	}
+
+	/// Parses the code into a naga module, `None` if it is invalid.
+	/// Push constants are turned into a uniform at binding 0 of the given bind group.
+	pub(crate) fn module(&self, push_constants_group: Option<u32>) -> Option<naga::Module> {
+		let mut module = match self {
+			ShaderCode::SpirV(bytes) => {
+				naga::front::spv::parse_u8_slice(bytes, &naga::front::spv::Options::default())
+					.ok()?
+			}
+			ShaderCode::Wgsl(code) => naga::front::wgsl::parse_str(code).ok()?,
+		};
+
+		if let Some(group) = push_constants_group {
+			for (_, var) in module.global_variables.iter_mut() {
+				if var.space == naga::AddressSpace::PushConstant {
+					var.space = naga::AddressSpace::Uniform;
+					var.binding = Some(naga::ResourceBinding { group, binding: 0 });
+				}
+			}
+		}
+
+		Some(module)
+	}
+
+	/// Like `source`, but turns the push constants into a uniform at the given bind group,
+	/// for devices without push constant support. See `module`.
+	pub(crate) fn source_with_push_constants_at(
+		&self,
+		group: Option<u32>,
+	) -> wgpu::ShaderSource<'_> {
+		if group.is_some()
+			&& let Some(module) = self.module(group)
+		{
+			return wgpu::ShaderSource::Naga(Cow::Owned(module));
+		}
+		// Invalid code is passed on as is, so the device reports the errors
+		self.source()
+	}
+}
 
 pub(crate) struct ShadeStorage {
 	pub vertex_path: Option<String>,
-	pub vertex_bytes: Option<Vec<u8>>,
+	pub vertex_code: Option<ShaderCode>,
 	pub fragment_path: Option<String>,
-	pub fragment_bytes: Option<Vec<u8>>,
+	pub fragment_code: Option<ShaderCode>,
 	pub attribs: AttribsFormat,
+	pub instance_attribs: Option<AttribsFormat>,
 	pub pipeline_layout: wgpu::PipelineLayout,
 	pub binding_layout: Option<BindGroupLayout>,
 	pub layers_layout: Option<BindGroupLayout>,
 	pub value_bindings_length: usize,
 	pub layer_bindings_length: usize,
+	pub push_constants: Option<PushConstantsLayout>,
+	/// Layouts the shader modules are validated against, see `Shade::validate`.
+	pub binding_layouts: Vec<BindingLayout>,
+	pub layer_layouts: Vec<LayerLayout>,
+	/// Result of `Shade::validate` for the current shader code, reset when the code changes.
+	pub validation: OnceCell<Result<(), ShaderInterfaceError>>,
+}
+
+impl ShadeStorage {
+	pub(crate) fn set_vertex_code(&mut self, code: ShaderCode) {
+		self.vertex_code = Some(code);
+		self.validation.take();
+	}
+
+	pub(crate) fn set_fragment_code(&mut self, code: ShaderCode) {
+		self.fragment_code = Some(code);
+		self.validation.take();
+	}
 }
 
 pub struct ShadeProps<Format: Into<AttribsFormat>> {
 	pub attributes: Format,
+	/// Per-instance vertex attributes, read from the form's instance buffer.
+	/// Their shader locations follow after the vertex attributes.
+	pub instance_attributes: Option<AttribsFormat>,
 	pub bindings: Vec<BindingLayout>,
 	pub layers: Vec<LayerLayout>,
+	/// Shader stages and size in bytes of the push constants, see `ShadeBuilder::with_push_constants`.
+	pub push_constants: Option<(wgpu::ShaderStages, u32)>,
 }
 
-fn layouts_from_props(
+pub(crate) fn layouts_from_props(
 	painter: &mut Painter,
 	bindings: &[BindingLayout],
 	layers: &[LayerLayout],
+	push_constants: Option<(wgpu::ShaderStages, u32)>,
 ) -> (
 	wgpu::PipelineLayout,
 	Option<BindGroupLayout>,
 	Option<BindGroupLayout>,
+	Option<PushConstantsLayout>,
 ) {
 	let bindings_layout = BindGroupLayout::values(painter, bindings);
 
 	let layer_layout = BindGroupLayout::layers(painter, layers);
 
+	let group_count = bindings_layout.iter().chain(layer_layout.iter()).count() as u32;
+	let push_constants = push_constants
+		.map(|(stages, size)| PushConstantsLayout::new(painter, stages, size, group_count));
+
 	let mut layouts = vec![];
 
 	if let Some(l) = &bindings_layout {
@@ -48,15 +153,29 @@ fn layouts_from_props(
 		layouts.push(&painter.bind_group_layouts[l.0]);
 	}
 
+	if let Some((_, l)) = push_constants.and_then(|p| p.fallback) {
+		layouts.push(&painter.bind_group_layouts[l.0]);
+	}
+
+	let push_constant_ranges = push_constants
+		.and_then(|p| p.range())
+		.into_iter()
+		.collect::<Vec<_>>();
+
 	let pipeline_layout = painter
 		.device
 		.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
 			label: None,
 			bind_group_layouts: layouts.as_slice(),
-			push_constant_ranges: &[],
+			push_constant_ranges: &push_constant_ranges,
 		});
 
-	(pipeline_layout, bindings_layout, layer_layout)
+	(
+		pipeline_layout,
+		bindings_layout,
+		layer_layout,
+		push_constants,
+	)
 }
 
 impl Default for ShadeProps<AttribsFormat> {
@@ -66,8 +185,10 @@ impl Default for ShadeProps<AttribsFormat> {
 				attributes: vec![],
 				stride: 0,
 			},
+			instance_attributes: None,
 			bindings: vec![],
 			layers: vec![],
+			push_constants: None,
 		}
 	}
 }
@@ -143,65 +264,119 @@ impl From<wgpu::VertexFormat> for AttribsFormat {
 }
 
 #[derive(Clone, Copy)]
-pub struct Shade(pub(crate) usize);
+pub struct Shade(pub(crate) Handle);
 
 impl Shade {
 	pub fn new<Format: Into<AttribsFormat>>(
 		painter: &mut Painter,
 		props: ShadeProps<Format>,
 	) -> Self {
-		let format = props.attributes.into();
+		Self::try_new(painter, props).unwrap_or_else(|err| panic!("{}", err))
+	}
+
+	/// Like `new`, but returns an error if the push constants size is invalid.
+	pub fn try_new<Format: Into<AttribsFormat>>(
+		painter: &mut Painter,
+		props: ShadeProps<Format>,
+	) -> Result<Self, PainterError> {
+		if let Some((_, size)) = props.push_constants {
+			PushConstantsLayout::validate_size(size)?;
+		}
+
+		let format: AttribsFormat = props.attributes.into();
+		let instance_format = props.instance_attributes.map(|mut f| {
+			let location_offset = format.attributes.len() as u32;
+			for a in f.attributes.iter_mut() {
+				a.shader_location += location_offset;
+			}
+			f
+		});
 		let bindings_len = props.bindings.len();
 		let layers_len = props.layers.len();
 
-		let (pipeline_layout, binding_layout, layers_layout) =
-			layouts_from_props(painter, &props.bindings, &props.layers);
+		let (pipeline_layout, binding_layout, layers_layout, push_constants) = layouts_from_props(
+			painter,
+			&props.bindings,
+			&props.layers,
+			props.push_constants,
+		);
 
 		let s = ShadeStorage {
 			vertex_path: None,
-			vertex_bytes: None,
+			vertex_code: None,
 			fragment_path: None,
-			fragment_bytes: None,
+			fragment_code: None,
 			attribs: format,
+			instance_attribs: instance_format,
 			pipeline_layout,
 			binding_layout,
 			layers_layout,
 			value_bindings_length: bindings_len,
 			layer_bindings_length: layers_len,
+			push_constants,
+			binding_layouts: props.bindings,
+			layer_layouts: props.layers,
+			validation: OnceCell::new(),
 		};
 
-		let i = painter.shades.len();
-		painter.shades.push(s);
-
-		Shade(i)
+		Ok(Shade(painter.shades.insert(s)))
 	}
 
 	pub fn new_effect(painter: &mut Painter, props: ShadeEffectProps) -> Self {
 		let bindings_len = props.bindings.len();
 		let layers_len = props.layers.len();
 
-		let (pipeline_layout, binding_layout, layers_layout) =
-			layouts_from_props(painter, &props.bindings, &props.layers);
+		let (pipeline_layout, binding_layout, layers_layout, _) =
+			layouts_from_props(painter, &props.bindings, &props.layers, None);
 
 		let format = vec![].into();
 
 		let s = ShadeStorage {
 			vertex_path: None,
-			vertex_bytes: None,
+			vertex_code: None,
 			fragment_path: None,
-			fragment_bytes: None,
+			fragment_code: None,
 			attribs: format,
+			instance_attribs: None,
 			pipeline_layout,
 			binding_layout,
 			layers_layout,
 			value_bindings_length: bindings_len,
 			layer_bindings_length: layers_len,
+			push_constants: None,
+			binding_layouts: props.bindings,
+			layer_layouts: props.layers,
+			validation: OnceCell::new(),
 		};
 
-		let i = painter.shades.len();
-		painter.shades.push(s);
+		Shade(painter.shades.insert(s))
+	}
+
+	/// Frees the shade and all cached render pipelines created from it.
+	///
+	/// Shapes and effects using this shade have to be destroyed as well,
+	/// they panic when rendered afterwards.
+	pub fn destroy(self, painter: &mut Painter) {
+		if painter.shades.remove(self.0).is_none() {
+			return;
+		}
 
-		Shade(i)
+		// Pipeline keys start with the shade handle, drop them as they are never used again
+		let prefix = self.0.key_bytes();
+		painter
+			.pipelines
+			.retain(|key, p| p.layer.is_none() || !key.starts_with(&prefix));
+	}
+
+	/// Checks that the bindings and vertex inputs used by the shader code
+	/// match the binding layouts and vertex attributes of the shade.
+	///
+	/// This is done automatically before render pipelines are created, which panics on a mismatch.
+	/// Call it after loading the shaders to handle mismatches instead.
+	/// The result is cached until the shader code changes.
+	pub fn validate(&self, painter: &Painter) -> Result<(), ShaderInterfaceError> {
+		let s = &painter.shades[self.0];
+		s.validation.get_or_init(|| validate_shade(s)).clone()
 	}
 
 	pub fn form_stride(&self, painter: &Painter) -> u64 {
@@ -209,35 +384,75 @@ impl Shade {
 	}
 
 	pub fn set_vertex_bytes(&self, painter: &mut Painter, bytes: Vec<u8>) {
-		painter.shades[self.0].vertex_bytes = Some(bytes);
+		painter.shades[self.0].set_vertex_code(ShaderCode::SpirV(bytes));
+	}
+
+	/// Sets WGSL source code for the vertex stage.
+	/// The code may contain the fragment entry point as well, as long as there is only one per stage.
+	pub fn set_vertex_wgsl(&self, painter: &mut Painter, code: &str) {
+		painter.shades[self.0].set_vertex_code(ShaderCode::Wgsl(code.to_string()));
 	}
 
-	pub(crate) fn load_vertex_from_path(&self, painter: &mut Painter) {
+	pub(crate) fn load_vertex_from_path(&self, painter: &mut Painter) -> Result<(), PainterError> {
 		if let Some(shader_path) = &painter.shades[self.0].vertex_path {
-			let bytes = fs::read(shader_path).expect("Failed to read vertex shader file");
-			self.set_vertex_bytes(painter, bytes);
+			let code = ShaderCode::read(shader_path)
+				.map_err(|err| PainterError::read_file(shader_path, err))?;
+			painter.shades[self.0].set_vertex_code(code);
 		}
+		Ok(())
 	}
 
 	pub fn set_vertex_path(&self, painter: &mut Painter, path: &str) {
+		self.try_set_vertex_path(painter, path)
+			.unwrap_or_else(|err| panic!("{}", err));
+	}
+
+	/// Like `set_vertex_path`, but returns an error if the file can not be read.
+	/// The path is kept for hot reloading either way.
+	pub fn try_set_vertex_path(
+		&self,
+		painter: &mut Painter,
+		path: &str,
+	) -> Result<(), PainterError> {
 		painter.shades[self.0].vertex_path = Some(path.to_string());
-		self.load_vertex_from_path(painter);
+		self.load_vertex_from_path(painter)
 	}
 
 	pub fn set_fragment_bytes(&self, painter: &mut Painter, bytes: Vec<u8>) {
-		painter.shades[self.0].fragment_bytes = Some(bytes);
+		painter.shades[self.0].set_fragment_code(ShaderCode::SpirV(bytes));
+	}
+
+	/// Sets WGSL source code for the fragment stage.
+	pub fn set_fragment_wgsl(&self, painter: &mut Painter, code: &str) {
+		painter.shades[self.0].set_fragment_code(ShaderCode::Wgsl(code.to_string()));
 	}
 
-	pub(crate) fn load_fragment_from_path(&self, painter: &mut Painter) {
+	pub(crate) fn load_fragment_from_path(
+		&self,
+		painter: &mut Painter,
+	) -> Result<(), PainterError> {
 		if let Some(shader_path) = &painter.shades[self.0].fragment_path {
-			let bytes = fs::read(shader_path).expect("Failed to read fragment shader file");
-			self.set_fragment_bytes(painter, bytes);
+			let code = ShaderCode::read(shader_path)
+				.map_err(|err| PainterError::read_file(shader_path, err))?;
+			painter.shades[self.0].set_fragment_code(code);
 		}
+		Ok(())
 	}
 
 	pub fn set_fragment_path(&self, painter: &mut Painter, path: &str) {
+		self.try_set_fragment_path(painter, path)
+			.unwrap_or_else(|err| panic!("{}", err));
+	}
+
+	/// Like `set_fragment_path`, but returns an error if the file can not be read.
+	/// The path is kept for hot reloading either way.
+	pub fn try_set_fragment_path(
+		&self,
+		painter: &mut Painter,
+		path: &str,
+	) -> Result<(), PainterError> {
 		painter.shades[self.0].fragment_path = Some(path.to_string());
-		self.load_fragment_from_path(painter);
+		self.load_fragment_from_path(painter)
 	}
 }
 
@@ -257,8 +472,10 @@ where
 		ShadeBuilder {
 			props: ShadeProps {
 				attributes,
+				instance_attributes: None,
 				bindings: vec![],
 				layers: vec![],
+				push_constants: None,
 			},
 			painter,
 		}
@@ -268,6 +485,10 @@ where
 		Shade::new(self.painter, self.props)
 	}
 
+	pub fn try_create(self) -> Result<Shade, PainterError> {
+		Shade::try_new(self.painter, self.props)
+	}
+
 	pub fn with_bindings<I>(mut self, bindings: I) -> Self
 	where
 		I: IntoIterator<Item = BindingLayout>,
@@ -283,6 +504,25 @@ where
 		self.props.layers = layers.into_iter().collect();
 		self
 	}
+
+	/// Adds a per-instance vertex buffer layout for hardware instancing.
+	/// The instance data is provided by the form, see `Form::update_instance_data`.
+	pub fn with_instance_attributes(mut self, attributes: impl Into<AttribsFormat>) -> Self {
+		self.props.instance_attributes = Some(attributes.into());
+		self
+	}
+
+	/// Declares push constants of `size` bytes, a multiple of 4, visible in the given stages.
+	/// The data is set per shape and instance, see `ShapeBuilder::with_push_constants`.
+	///
+	/// Without `Features::PUSH_CONSTANTS`, or if the size exceeds the device limit, the data
+	/// is provided as dynamic-offset uniform in the bind group after the value and layer bindings.
+	/// Push constant variables of WGSL and SPIR-V code are turned into that uniform automatically,
+	/// so the push constant struct has to follow the uniform layout rules.
+	pub fn with_push_constants(mut self, stages: wgpu::ShaderStages, size: u32) -> Self {
+		self.props.push_constants = Some((stages, size));
+		self
+	}
 }
 
 pub struct ShadeEffectBuilder<'b> {
@@ -342,10 +582,15 @@ macro_rules! load_fragment_shader {
 			$shade.set_fragment_path($painter, full_path);
 		}
 
-		#[cfg(not(debug_assertions))]
-		$shade.set_fragment_bytes($painter, include_bytes!($path).to_vec());
-		#[cfg(target_arch = "wasm32")]
-		$shade.set_fragment_bytes($painter, include_bytes!($path).to_vec());
+		#[cfg(any(not(debug_assertions), target_arch = "wasm32"))]
+		{
+			if $path.ends_with(".wgsl") {
+				let code = std::str::from_utf8(include_bytes!($path)).unwrap();
+				$shade.set_fragment_wgsl($painter, code);
+			} else {
+				$shade.set_fragment_bytes($painter, include_bytes!($path).to_vec());
+			}
+		}
 	};
 }
 
@@ -363,9 +608,14 @@ macro_rules! load_vertex_shader {
 			$shade.set_vertex_path($painter, full_path);
 		}
 
-		#[cfg(not(debug_assertions))]
-		$shade.set_vertex_bytes($painter, include_bytes!($path).to_vec());
-		#[cfg(target_arch = "wasm32")]
-		$shade.set_vertex_bytes($painter, include_bytes!($path).to_vec());
+		#[cfg(any(not(debug_assertions), target_arch = "wasm32"))]
+		{
+			if $path.ends_with(".wgsl") {
+				let code = std::str::from_utf8(include_bytes!($path)).unwrap();
+				$shade.set_vertex_wgsl($painter, code);
+			} else {
+				$shade.set_vertex_bytes($painter, include_bytes!($path).to_vec());
+			}
+		}
 	};
 }
@@ -23,4 +23,30 @@ fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
 fn fs_main(in: VertexOutput) -> @location(0) vec4f {
 	return textureSample(ourTexture, ourSampler, in.coord);
 }
+
+// sRGB transfer function, mirrored for negative values to encode extended sRGB
+fn linear_to_srgb(c: vec3f) -> vec3f {
+	let a = abs(c);
+	let encoded = select(1.055 * pow(a, vec3f(1.0 / 2.4)) - 0.055, a * 12.92, a <= vec3f(0.0031308));
+	return sign(c) * encoded;
+}
+
+@fragment
+fn fs_main_srgb(in: VertexOutput) -> @location(0) vec4f {
+	let color = textureSample(ourTexture, ourSampler, in.coord);
+	return vec4f(linear_to_srgb(color.rgb), color.a);
+}
+
+// Inverse of linear_to_srgb
+fn srgb_to_linear(c: vec3f) -> vec3f {
+	let a = abs(c);
+	let decoded = select(pow((a + 0.055) / 1.055, vec3f(2.4)), a / 12.92, a <= vec3f(0.04045));
+	return sign(c) * decoded;
+}
+
+@fragment
+fn fs_main_srgb_decode(in: VertexOutput) -> @location(0) vec4f {
+	let color = textureSample(ourTexture, ourSampler, in.coord);
+	return vec4f(srgb_to_linear(color.rgb), color.a);
+}
 "#;
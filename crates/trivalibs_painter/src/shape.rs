@@ -1,7 +1,11 @@
 use crate::{
 	Painter,
 	binding::{InstanceBinding, LayerBinding, ValueBinding},
+	error::PainterError,
 	form::Form,
+	layer::Layer,
+	push_constants::PushConstantsData,
+	registry::Handle,
 	shade::Shade,
 };
 
@@ -15,6 +19,18 @@ pub(crate) struct ShapeStorage {
 	pub pipeline_key: Vec<u8>,
 	pub cull_mode: Option<wgpu::Face>,
 	pub blend_state: wgpu::BlendState,
+	pub depth_write: bool,
+	pub depth_compare: wgpu::CompareFunction,
+	pub depth_bias: wgpu::DepthBiasState,
+	pub stencil: wgpu::StencilState,
+	pub stencil_reference: u32,
+	pub alpha_to_coverage: bool,
+	/// Effective rasterization state, see `Shape::new` for the fallbacks.
+	pub topology: wgpu::PrimitiveTopology,
+	pub polygon_mode: wgpu::PolygonMode,
+	/// Draw the line list index buffers of the form instead of its triangles.
+	pub line_list: bool,
+	pub push_constants: Option<PushConstantsData>,
 }
 
 #[derive(Clone)]
@@ -24,6 +40,17 @@ pub struct ShapeProps {
 	pub instances: Vec<InstanceBinding>,
 	pub cull_mode: Option<wgpu::Face>,
 	pub blend_state: wgpu::BlendState,
+	/// Depth and stencil state, only used in layers with depth test.
+	pub depth_write: bool,
+	pub depth_compare: wgpu::CompareFunction,
+	pub depth_bias: wgpu::DepthBiasState,
+	pub stencil: wgpu::StencilState,
+	pub stencil_reference: u32,
+	/// Derives the multisample coverage from the fragment alpha, only used in multisampled layers.
+	pub alpha_to_coverage: bool,
+	pub polygon_mode: wgpu::PolygonMode,
+	/// Push constant data, the shade has to declare push constants.
+	pub push_constants: Option<Vec<u8>>,
 }
 
 impl Default for ShapeProps {
@@ -34,21 +61,114 @@ impl Default for ShapeProps {
 			instances: Vec::with_capacity(0),
 			cull_mode: Some(wgpu::Face::Back),
 			blend_state: wgpu::BlendState::REPLACE,
+			depth_write: true,
+			depth_compare: wgpu::CompareFunction::Less,
+			depth_bias: wgpu::DepthBiasState::default(),
+			stencil: wgpu::StencilState::default(),
+			stencil_reference: 0,
+			alpha_to_coverage: false,
+			polygon_mode: wgpu::PolygonMode::Fill,
+			push_constants: None,
 		}
 	}
 }
 
+fn stencil_key(stencil: &wgpu::StencilState) -> Vec<u8> {
+	let face_key = |face: &wgpu::StencilFaceState| {
+		[
+			face.compare as u8,
+			face.fail_op as u8,
+			face.depth_fail_op as u8,
+			face.pass_op as u8,
+		]
+	};
+
+	[
+		face_key(&stencil.front).as_slice(),
+		face_key(&stencil.back).as_slice(),
+		&stencil.read_mask.to_le_bytes(),
+		&stencil.write_mask.to_le_bytes(),
+	]
+	.concat()
+}
+
 #[derive(Clone, Copy, Debug)]
-pub struct Shape(pub(crate) usize);
+pub struct Shape(pub(crate) Handle);
 
 impl Shape {
 	pub fn new(painter: &mut Painter, form: Form, shade: Shade, props: ShapeProps) -> Self {
+		Self::try_new(painter, form, shade, props).unwrap_or_else(|err| panic!("{}", err))
+	}
+
+	/// Like `new`, but returns an error if the push constants do not match the shade.
+	pub fn try_new(
+		painter: &mut Painter,
+		form: Form,
+		shade: Shade,
+		props: ShapeProps,
+	) -> Result<Self, PainterError> {
+		let has_push_constant_data = props.push_constants.is_some()
+			|| props.instances.iter().any(|i| i.push_constants.is_some());
+		if has_push_constant_data && painter.shades[shade.0].push_constants.is_none() {
+			return Err(PainterError::InvalidProps(
+				"Push constants set on a shape whose shade declares none".to_string(),
+			));
+		}
+
+		// Validates the push constant data against the shade
+		let push_constants = painter.shades[shade.0]
+			.push_constants
+			.map(|layout| {
+				PushConstantsData::new(
+					painter,
+					layout,
+					props.push_constants.as_deref(),
+					&props.instances,
+				)
+			})
+			.transpose()?;
+
+		let f = &painter.forms[form.0];
+		let features = painter.device.features();
+
+		// Without the polygon mode features, e.g. on WebGPU, lines are drawn from a line list
+		// derived from the triangles of the form, and points from the vertices.
+		let is_triangles = matches!(
+			f.props.topology,
+			wgpu::PrimitiveTopology::TriangleList | wgpu::PrimitiveTopology::TriangleStrip
+		);
+		let (topology, polygon_mode, line_list) = match props.polygon_mode {
+			wgpu::PolygonMode::Line
+				if is_triangles && !features.contains(wgpu::Features::POLYGON_MODE_LINE) =>
+			{
+				(
+					wgpu::PrimitiveTopology::LineList,
+					wgpu::PolygonMode::Fill,
+					true,
+				)
+			}
+			wgpu::PolygonMode::Point
+				if is_triangles && !features.contains(wgpu::Features::POLYGON_MODE_POINT) =>
+			{
+				(
+					wgpu::PrimitiveTopology::PointList,
+					wgpu::PolygonMode::Fill,
+					false,
+				)
+			}
+			mode => (f.props.topology, mode, false),
+		};
+
+		if line_list {
+			form.enable_line_list(painter);
+		}
 		let f = &painter.forms[form.0];
 
 		let pipeline_key = vec![
-			(shade.0 as u16).to_le_bytes().to_vec(),
+			shade.0.key_bytes().to_vec(),
 			vec![
-				f.props.topology as u8,
+				topology as u8,
+				polygon_mode as u8,
 				f.props.front_face as u8,
 				props.blend_state.alpha.dst_factor as u8,
 				props.blend_state.alpha.src_factor as u8,
@@ -61,7 +181,14 @@ impl Shape {
 				} else {
 					0xff
 				},
+				props.depth_write as u8,
+				props.depth_compare as u8,
+				props.alpha_to_coverage as u8,
 			],
+			props.depth_bias.constant.to_le_bytes().to_vec(),
+			props.depth_bias.slope_scale.to_le_bytes().to_vec(),
+			props.depth_bias.clamp.to_le_bytes().to_vec(),
+			stencil_key(&props.stencil),
 		]
 		.into_iter()
 		.flatten()
@@ -76,11 +203,87 @@ impl Shape {
 			instances: props.instances,
 			cull_mode: props.cull_mode,
 			blend_state: props.blend_state,
+			depth_write: props.depth_write,
+			depth_compare: props.depth_compare,
+			depth_bias: props.depth_bias,
+			stencil: props.stencil,
+			stencil_reference: props.stencil_reference,
+			alpha_to_coverage: props.alpha_to_coverage,
+			topology,
+			polygon_mode,
+			line_list,
+			push_constants,
 		};
 
-		painter.shapes.push(shape);
+		Ok(Shape(painter.shapes.insert(shape)))
+	}
+
+	/// Updates the push constant data of all instances not overriding it.
+	pub fn set_push_constants<T: bytemuck::Pod>(&self, painter: &mut Painter, data: T) {
+		self.try_set_push_constants(painter, data)
+			.unwrap_or_else(|err| panic!("{}", err));
+	}
+
+	/// Like `set_push_constants`, but returns an error if the shade declares no push constants
+	/// or the data exceeds their size.
+	pub fn try_set_push_constants<T: bytemuck::Pod>(
+		&self,
+		painter: &mut Painter,
+		data: T,
+	) -> Result<(), PainterError> {
+		let mut pc = self.take_push_constants(painter)?;
+		let result = pc.set(painter, bytemuck::bytes_of(&data));
+		painter.shapes[self.0].push_constants = Some(pc);
+		result
+	}
+
+	/// Updates the push constant data of a single instance.
+	pub fn set_instance_push_constants<T: bytemuck::Pod>(
+		&self,
+		painter: &mut Painter,
+		instance: usize,
+		data: T,
+	) {
+		self.try_set_instance_push_constants(painter, instance, data)
+			.unwrap_or_else(|err| panic!("{}", err));
+	}
+
+	/// Like `set_instance_push_constants`, but returns an error if the shade declares
+	/// no push constants, the data exceeds their size or the instance does not exist.
+	pub fn try_set_instance_push_constants<T: bytemuck::Pod>(
+		&self,
+		painter: &mut Painter,
+		instance: usize,
+		data: T,
+	) -> Result<(), PainterError> {
+		let instance_count = painter.shapes[self.0].instances.len();
+		let mut pc = self.take_push_constants(painter)?;
+		let result = pc.set_instance(painter, instance_count, instance, bytemuck::bytes_of(&data));
+		painter.shapes[self.0].push_constants = Some(pc);
+		result
+	}
+
+	fn take_push_constants(
+		&self,
+		painter: &mut Painter,
+	) -> Result<PushConstantsData, PainterError> {
+		painter.shapes[self.0].push_constants.take().ok_or_else(|| {
+			PainterError::InvalidProps(
+				"The shade of the shape declares no push constants".to_string(),
+			)
+		})
+	}
+
+	/// Removes the shape from all layers and frees its bind groups.
+	///
+	/// The form and shade of the shape are not destroyed, as they can be shared by other shapes.
+	pub fn destroy(self, painter: &mut Painter) {
+		for h in painter.layers.handles() {
+			Layer(h).remove_shape(painter, self);
+		}
 
-		Shape(painter.shapes.len() - 1)
+		painter.shapes.remove(self.0);
+		painter.cleanup_pipelines();
 	}
 }
 
@@ -89,6 +292,13 @@ impl Shape {
 /// # Default values for [`ShapeProps`]:
 /// - `cull_mode`: `Some(wgpu::Face::Back)`
 /// - `blend_state`: `wgpu::BlendState::REPLACE`
+/// - `depth_write`: `true`
+/// - `depth_compare`: `wgpu::CompareFunction::Less`
+/// - `depth_bias`: none
+/// - `stencil`: disabled
+/// - `alpha_to_coverage`: `false`
+/// - `polygon_mode`: `wgpu::PolygonMode::Fill`
+/// - `push_constants`: zeroed
 ///
 /// # Example
 /// ```
@@ -118,6 +328,10 @@ impl<'a> ShapeBuilder<'a> {
 		Shape::new(self.painter, self.form, self.shade, self.props)
 	}
 
+	pub fn try_create(self) -> Result<Shape, PainterError> {
+		Shape::try_new(self.painter, self.form, self.shade, self.props)
+	}
+
 	pub fn with_bindings<I>(mut self, bindings: I) -> Self
 	where
 		I: IntoIterator<Item = (u32, ValueBinding)>,
@@ -148,4 +362,62 @@ impl<'a> ShapeBuilder<'a> {
 		self.props.blend_state = blend_state;
 		self
 	}
+
+	/// Tests against the depth buffer without writing to it, e.g. for transparent shapes.
+	pub fn with_depth_write(mut self, depth_write: bool) -> Self {
+		self.props.depth_write = depth_write;
+		self
+	}
+
+	pub fn with_depth_compare(mut self, depth_compare: wgpu::CompareFunction) -> Self {
+		self.props.depth_compare = depth_compare;
+		self
+	}
+
+	/// Offsets the depth of the shape, e.g. to draw decals or outlines on top of coplanar geometry.
+	pub fn with_depth_bias(mut self, constant: i32, slope_scale: f32) -> Self {
+		self.props.depth_bias = wgpu::DepthBiasState {
+			constant,
+			slope_scale,
+			clamp: 0.0,
+		};
+		self
+	}
+
+	/// Sets the stencil test and operations. The layer needs a depth format with stencil,
+	/// e.g. `Depth24PlusStencil8`.
+	pub fn with_stencil(mut self, stencil: wgpu::StencilState) -> Self {
+		self.props.stencil = stencil;
+		self
+	}
+
+	/// The reference value the stencil test compares against and `Replace` writes.
+	pub fn with_stencil_reference(mut self, reference: u32) -> Self {
+		self.props.stencil_reference = reference;
+		self
+	}
+
+	/// Uses the fragment alpha as multisample coverage mask, for antialiased cutouts
+	/// like foliage without sorting. Only takes effect in multisampled layers.
+	pub fn with_alpha_to_coverage(mut self) -> Self {
+		self.props.alpha_to_coverage = true;
+		self
+	}
+
+	/// Renders the triangles of the form as wireframe or points, e.g. to debug geometry.
+	///
+	/// Uses `Features::POLYGON_MODE_LINE` and `Features::POLYGON_MODE_POINT` where the adapter
+	/// supports them. Elsewhere, lines are drawn from a line list derived from the form's
+	/// triangles and points from its vertices, so line widths and point sizes are always 1px.
+	pub fn with_polygon_mode(mut self, polygon_mode: wgpu::PolygonMode) -> Self {
+		self.props.polygon_mode = polygon_mode;
+		self
+	}
+
+	/// Sets the push constant data, declared by `ShadeBuilder::with_push_constants`.
+	/// Instances can override it with `InstanceBinding::with_push_constants`.
+	pub fn with_push_constants<T: bytemuck::Pod>(mut self, data: T) -> Self {
+		self.props.push_constants = Some(bytemuck::bytes_of(&data).to_vec());
+		self
+	}
 }
@@ -1,5 +1,12 @@
+#[cfg(not(target_arch = "wasm32"))]
+use crate::texture_utils::pixels_to_png_data;
 use crate::{
-	texture_utils::{generate_mipmap_2d, num_mip_levels},
+	binding::LayerBinding,
+	error::PainterError,
+	registry::Handle,
+	texture_utils::{
+		decode_png, encode_mipmap_2d, ensure_mipmap_pipeline, generate_mipmap_2d, num_mip_levels,
+	},
 	Painter,
 };
 use std::collections::BTreeMap;
@@ -12,22 +19,80 @@ pub enum MipMapCount {
 	Max(u32),
 }
 
-/// # Default Texture2DProps
+/// The dimension and number of slices of a texture.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextureKind {
+	#[default]
+	D2,
+	/// 2D texture array with the given number of layers.
+	D2Array(u32),
+	/// Cubemap with six square faces in the order +X, -X, +Y, -Y, +Z, -Z.
+	Cube,
+	/// 3D texture with the given depth.
+	D3(u32),
+}
+
+impl TextureKind {
+	/// The number of array layers, cube faces or depth slices.
+	pub fn slice_count(&self) -> u32 {
+		match self {
+			TextureKind::D2 => 1,
+			TextureKind::D2Array(layers) => *layers,
+			TextureKind::Cube => 6,
+			TextureKind::D3(depth) => *depth,
+		}
+	}
+
+	pub fn view_dimension(&self) -> wgpu::TextureViewDimension {
+		match self {
+			TextureKind::D2 => wgpu::TextureViewDimension::D2,
+			TextureKind::D2Array(_) => wgpu::TextureViewDimension::D2Array,
+			TextureKind::Cube => wgpu::TextureViewDimension::Cube,
+			TextureKind::D3(_) => wgpu::TextureViewDimension::D3,
+		}
+	}
+
+	fn dimension(&self) -> wgpu::TextureDimension {
+		match self {
+			TextureKind::D3(_) => wgpu::TextureDimension::D3,
+			_ => wgpu::TextureDimension::D2,
+		}
+	}
+
+	fn extent(&self, width: u32, height: u32) -> wgpu::Extent3d {
+		wgpu::Extent3d {
+			width,
+			height,
+			depth_or_array_layers: self.slice_count(),
+		}
+	}
+
+	/// Whether single slices are rendered through their own 2D view.
+	/// Slices of 3D textures are selected by the depth slice of the render pass instead.
+	pub(crate) fn has_slice_views(&self) -> bool {
+		matches!(self, TextureKind::D2Array(_) | TextureKind::Cube)
+	}
+}
+
+/// # Default TextureProps
 /// - Format: `Rgba8UnormSrgb` (8-bit RGBA color in sRGB color space)
 /// - Usage: `TEXTURE_BINDING | COPY_DST` (can be used as texture and receive data)
+/// - Kind: `TextureKind::D2`
 #[derive(Clone, Copy)]
-pub(crate) struct Texture2DProps {
+pub(crate) struct TextureProps {
 	pub format: wgpu::TextureFormat,
 	pub usage: wgpu::TextureUsages,
 	pub mips: Option<MipMapCount>,
+	pub kind: TextureKind,
 }
 
-impl Default for Texture2DProps {
+impl Default for TextureProps {
 	fn default() -> Self {
-		Texture2DProps {
+		TextureProps {
 			format: wgpu::TextureFormat::Rgba8UnormSrgb,
 			usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
 			mips: None,
+			kind: TextureKind::D2,
 		}
 	}
 }
@@ -37,55 +102,186 @@ pub(crate) enum TexViewKey {
 	Default,
 	WithAllMips,
 	AtMipLevel(u32),
+	/// 2D view of a single array layer or cube face at mip level 0, used as render target.
+	Slice(u32),
+	/// View of the depth aspect, used to sample depth-stencil textures.
+	DepthOnly,
 }
 
 impl TexViewKey {
-	pub fn make_view(&self, t: &wgpu::Texture) -> wgpu::TextureView {
+	pub fn make_view(
+		&self,
+		t: &wgpu::Texture,
+		dimension: wgpu::TextureViewDimension,
+	) -> wgpu::TextureView {
 		match self {
-			TexViewKey::Default => t.create_view(&default()),
+			TexViewKey::Default => t.create_view(&TextureViewDescriptor {
+				dimension: Some(dimension),
+				..default()
+			}),
 			TexViewKey::WithAllMips => t.create_view(&TextureViewDescriptor {
+				dimension: Some(dimension),
 				mip_level_count: Some(t.mip_level_count()),
 				..default()
 			}),
 			TexViewKey::AtMipLevel(mip_level) => t.create_view(&wgpu::TextureViewDescriptor {
+				dimension: Some(dimension),
 				base_mip_level: *mip_level,
 				mip_level_count: Some(1),
 				..default()
 			}),
+			TexViewKey::Slice(slice) => t.create_view(&wgpu::TextureViewDescriptor {
+				dimension: Some(wgpu::TextureViewDimension::D2),
+				base_mip_level: 0,
+				mip_level_count: Some(1),
+				base_array_layer: *slice,
+				array_layer_count: Some(1),
+				..default()
+			}),
+			TexViewKey::DepthOnly => t.create_view(&wgpu::TextureViewDescriptor {
+				dimension: Some(dimension),
+				aspect: wgpu::TextureAspect::DepthOnly,
+				..default()
+			}),
 		}
 	}
 }
 
 pub(crate) struct TextureStorage {
 	pub texture: wgpu::Texture,
+	pub kind: TextureKind,
 	pub views: BTreeMap<TexViewKey, wgpu::TextureView>,
 }
 
 impl TextureStorage {
+	fn new(texture: wgpu::Texture, kind: TextureKind) -> Self {
+		let mut storage = TextureStorage {
+			texture,
+			kind,
+			views: BTreeMap::new(),
+		};
+
+		storage.prepare_view(TexViewKey::AtMipLevel(0));
+		storage.prepare_view(TexViewKey::WithAllMips);
+		if kind.has_slice_views() {
+			for slice in 0..kind.slice_count() {
+				storage.prepare_view(TexViewKey::Slice(slice));
+			}
+		}
+
+		storage
+	}
+
 	pub(crate) fn prepare_view(&mut self, key: TexViewKey) {
-		let view = key.make_view(&self.texture);
+		let view = key.make_view(&self.texture, self.kind.view_dimension());
 		self.views.insert(key, view);
 	}
 }
 
-#[derive(Clone, Copy)]
-pub struct Texture(pub(crate) usize);
+/// A rectangle of a single slice and mip level of a texture, see `Texture::write_region`.
+///
+/// The default region covers the first slice at mip level 0.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TextureRegion {
+	pub x: u32,
+	pub y: u32,
+	/// Width and height of the region. If `None`, the region extends from `x` and `y`
+	/// to the end of the mip level.
+	pub size: Option<(u32, u32)>,
+	pub mip_level: u32,
+	/// The array layer, cube face or depth slice.
+	pub slice: u32,
+}
 
-fn create_2d(
+impl TextureRegion {
+	pub fn rect(x: u32, y: u32, width: u32, height: u32) -> Self {
+		TextureRegion {
+			x,
+			y,
+			size: Some((width, height)),
+			..default()
+		}
+	}
+
+	/// The whole first slice at the given mip level.
+	pub fn mip_level(mip_level: u32) -> Self {
+		TextureRegion {
+			mip_level,
+			..default()
+		}
+	}
+
+	pub fn with_mip_level(mut self, mip_level: u32) -> Self {
+		self.mip_level = mip_level;
+		self
+	}
+
+	pub fn with_slice(mut self, slice: u32) -> Self {
+		self.slice = slice;
+		self
+	}
+}
+
+/// Component types of pixel data that can be written to textures, see `Texture::write_region`.
+pub trait TexelComponent: bytemuck::Pod {
+	/// Whether pixels of the format consist of components of this type.
+	fn matches_format(format: wgpu::TextureFormat) -> bool;
+}
+
+/// Raw bytes in the layout of the texture format, accepted for all formats.
+impl TexelComponent for u8 {
+	fn matches_format(_format: wgpu::TextureFormat) -> bool {
+		true
+	}
+}
+
+impl TexelComponent for f32 {
+	fn matches_format(format: wgpu::TextureFormat) -> bool {
+		use wgpu::TextureFormat::*;
+		matches!(format, R32Float | Rg32Float | Rgba32Float)
+	}
+}
+
+impl TexelComponent for half::f16 {
+	fn matches_format(format: wgpu::TextureFormat) -> bool {
+		use wgpu::TextureFormat::*;
+		matches!(format, R16Float | Rg16Float | Rgba16Float)
+	}
+}
+
+/// Destination and data layout of a validated `TextureRegion` write.
+struct RegionCopy {
+	mip_level: u32,
+	origin: wgpu::Origin3d,
+	extent: wgpu::Extent3d,
+	bytes_per_row: u32,
+}
+
+/// Initial size of the staging buffers used by `Texture::write_region_staged`.
+/// Larger writes allocate buffers of their own size.
+pub(crate) const STAGING_BELT_CHUNK_SIZE: wgpu::BufferAddress = 1 << 20;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Texture(pub(crate) Handle);
+
+fn create_texture(
 	painter: &mut Painter,
 	width: u32,
 	height: u32,
-	props: Texture2DProps,
-	multi_sampled: bool,
+	props: TextureProps,
+	sample_count: u32,
 ) -> wgpu::Texture {
-	let extent = wgpu::Extent3d {
-		width,
-		height,
-		depth_or_array_layers: 1,
-	};
+	let extent = props.kind.extent(width, height);
+
+	if props.mips.is_some() && matches!(props.kind, TextureKind::D3(_)) {
+		panic!("Mipmaps can not be generated for 3D textures")
+	}
 
 	let mip_level_count = if let Some(mips) = props.mips {
-		let max_mip_levels = num_mip_levels(extent);
+		let max_mip_levels = num_mip_levels(wgpu::Extent3d {
+			depth_or_array_layers: 1,
+			..extent
+		});
 		match mips {
 			MipMapCount::Full => max_mip_levels,
 			MipMapCount::Max(max) => max.min(max_mip_levels),
@@ -94,12 +290,16 @@ fn create_2d(
 		1
 	};
 
+	if mip_level_count > 1 {
+		ensure_mipmap_pipeline(painter, props.format);
+	}
+
 	painter.device.create_texture(&wgpu::TextureDescriptor {
 		label: None,
 		size: extent,
 		mip_level_count,
-		sample_count: if multi_sampled { 4 } else { 1 },
-		dimension: wgpu::TextureDimension::D2,
+		sample_count,
+		dimension: props.kind.dimension(),
 		format: props.format,
 		usage: if mip_level_count > 1 {
 			props.usage | wgpu::TextureUsages::RENDER_ATTACHMENT
@@ -114,7 +314,8 @@ fn create_depth(
 	painter: &mut Painter,
 	width: u32,
 	height: u32,
-	multi_sampled: bool,
+	format: wgpu::TextureFormat,
+	sample_count: u32,
 ) -> wgpu::Texture {
 	painter.device.create_texture(&wgpu::TextureDescriptor {
 		label: None,
@@ -124,104 +325,91 @@ fn create_depth(
 			depth_or_array_layers: 1,
 		},
 		mip_level_count: 1,
-		sample_count: if multi_sampled { 4 } else { 1 },
+		sample_count,
 		dimension: wgpu::TextureDimension::D2,
-		format: wgpu::TextureFormat::Depth24Plus,
+		format,
 		usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
 		view_formats: &[],
 	})
 }
 
 impl Texture {
-	pub(crate) fn create_2d(
+	pub(crate) fn create(
 		painter: &mut Painter,
 		width: u32,
 		height: u32,
-		props: Texture2DProps,
-		multi_sampled: bool,
+		props: TextureProps,
+		sample_count: u32,
 	) -> Self {
-		let texture = create_2d(painter, width, height, props, multi_sampled);
-
-		let mut storage = TextureStorage {
-			texture,
-			views: BTreeMap::new(),
-		};
-
-		storage.prepare_view(TexViewKey::AtMipLevel(0));
-		storage.prepare_view(TexViewKey::WithAllMips);
-
-		painter.textures.push(storage);
+		let texture = create_texture(painter, width, height, props, sample_count);
+		let storage = TextureStorage::new(texture, props.kind);
 
-		Self(painter.textures.len() - 1)
+		Self(painter.textures.insert(storage))
 	}
 
-	pub(crate) fn replace_2d(
+	pub(crate) fn replace(
 		&self,
 		painter: &mut Painter,
 		width: u32,
 		height: u32,
-		props: Texture2DProps,
-		multi_sampled: bool,
+		props: TextureProps,
+		sample_count: u32,
 	) {
-		let texture = create_2d(painter, width, height, props, multi_sampled);
+		let texture = create_texture(painter, width, height, props, sample_count);
 
 		let old = &mut painter.textures[self.0];
 
-		let mut storage = TextureStorage {
-			texture,
-			views: BTreeMap::new(),
-		};
-
-		storage.prepare_view(TexViewKey::AtMipLevel(0));
-		storage.prepare_view(TexViewKey::WithAllMips);
+		let storage = TextureStorage::new(texture, props.kind);
 
 		old.texture.destroy();
 
 		painter.textures[self.0] = storage;
+		painter.invalidate_layer_bind_groups(*self);
 	}
 
+	/// Creates a depth texture, e.g. `Depth24Plus`, `Depth32Float` or `Depth24PlusStencil8`.
 	pub fn create_depth(
 		painter: &mut Painter,
 		width: u32,
 		height: u32,
-		multi_sampled: bool,
+		format: wgpu::TextureFormat,
+		sample_count: u32,
 	) -> Self {
-		let texture = create_depth(painter, width, height, multi_sampled);
+		let texture = create_depth(painter, width, height, format, sample_count);
 
 		let mut storage = TextureStorage {
 			texture,
+			kind: TextureKind::D2,
 			views: BTreeMap::new(),
 		};
 
 		storage.prepare_view(TexViewKey::Default);
+		storage.prepare_view(TexViewKey::DepthOnly);
 
-		painter.textures.push(storage);
-
-		Self(painter.textures.len() - 1)
+		Self(painter.textures.insert(storage))
 	}
 
-	pub fn replace_depth(
-		&self,
-		painter: &mut Painter,
-		width: u32,
-		height: u32,
-		multi_sampled: bool,
-	) {
-		let texture = create_depth(painter, width, height, multi_sampled);
+	pub fn replace_depth(&self, painter: &mut Painter, width: u32, height: u32, sample_count: u32) {
+		let format = painter.textures[self.0].texture.format();
+		let texture = create_depth(painter, width, height, format, sample_count);
 		let old = &mut painter.textures[self.0];
 
 		let mut storage = TextureStorage {
 			texture,
+			kind: TextureKind::D2,
 			views: BTreeMap::new(),
 		};
 
 		storage.prepare_view(TexViewKey::Default);
+		storage.prepare_view(TexViewKey::DepthOnly);
 
 		old.texture.destroy();
 
 		painter.textures[self.0] = storage;
+		painter.invalidate_layer_bind_groups(*self);
 	}
 
+	/// Writes the pixel data of all slices, i.e. all layers, cube faces or depth slices one after another.
 	pub fn fill_2d(&self, painter: &Painter, data: &[u8]) {
 		let texture = &painter.textures[self.0].texture;
 		let bytes_per_pixel = texture.format().block_copy_size(None).unwrap();
@@ -249,16 +437,241 @@ impl Texture {
 		self.update_mips(painter);
 	}
 
+	/// Writes the pixel data of a single array layer, cube face or depth slice.
+	pub fn fill_slice(&self, painter: &Painter, slice: u32, data: &[u8]) {
+		let texture = &painter.textures[self.0].texture;
+		let bytes_per_pixel = texture.format().block_copy_size(None).unwrap();
+
+		let size = texture.size();
+		if slice >= size.depth_or_array_layers {
+			panic!(
+				"Slice {} is out of range for a texture with {} slices",
+				slice, size.depth_or_array_layers
+			);
+		}
+
+		painter.queue.write_texture(
+			wgpu::TexelCopyTextureInfo {
+				texture,
+				mip_level: 0,
+				origin: wgpu::Origin3d {
+					x: 0,
+					y: 0,
+					z: slice,
+				},
+				aspect: wgpu::TextureAspect::All,
+			},
+			data,
+			wgpu::TexelCopyBufferLayout {
+				offset: 0,
+				bytes_per_row: Some(size.width * bytes_per_pixel),
+				rows_per_image: Some(size.height),
+			},
+			wgpu::Extent3d {
+				width: size.width,
+				height: size.height,
+				depth_or_array_layers: 1,
+			},
+		);
+
+		self.update_mips(painter);
+	}
+
+	/// Writes pixel data to a region of a single slice and mip level.
+	///
+	/// The data are tightly packed rows of the region, either as raw bytes,
+	/// or as `f32` or `half::f16` components for float formats of the matching size.
+	/// Panics if the component type does not match the format,
+	/// the region is out of bounds, or the data is too small.
+	///
+	/// Other mip levels are not updated, call `update_mips` after writing to level 0 if needed.
+	/// Each call allocates a temporary upload buffer,
+	/// use `write_region_staged` for data that is updated every frame.
+	pub fn write_region<T: TexelComponent>(
+		&self,
+		painter: &Painter,
+		region: TextureRegion,
+		data: &[T],
+	) {
+		let copy = self.region_copy(painter, region, data);
+		painter.queue.write_texture(
+			wgpu::TexelCopyTextureInfo {
+				texture: &painter.textures[self.0].texture,
+				mip_level: copy.mip_level,
+				origin: copy.origin,
+				aspect: wgpu::TextureAspect::All,
+			},
+			bytemuck::cast_slice(data),
+			wgpu::TexelCopyBufferLayout {
+				offset: 0,
+				bytes_per_row: Some(copy.bytes_per_row),
+				rows_per_image: Some(copy.extent.height),
+			},
+			copy.extent,
+		);
+	}
+
+	/// Like `write_region`, but copies the data through staging buffers that are reused
+	/// once the GPU has finished with them, instead of allocating new ones for every write.
+	///
+	/// The copy is recorded in order with the paint calls around it,
+	/// into the current frame if called within `Painter::frame`.
+	pub fn write_region_staged<T: TexelComponent>(
+		&self,
+		painter: &mut Painter,
+		region: TextureRegion,
+		data: &[T],
+	) {
+		let copy = self.region_copy(painter, region, data);
+		let bytes: &[u8] = bytemuck::cast_slice(data);
+
+		let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+		let padded_bytes_per_row = copy.bytes_per_row.div_ceil(align) * align;
+		let size = padded_bytes_per_row as u64 * copy.extent.height as u64;
+		if size == 0 {
+			return;
+		}
+
+		let mut encoder = painter.begin_encoder();
+
+		let belt = painter
+			.staging_belt
+			.get_or_insert_with(|| wgpu::util::StagingBelt::new(STAGING_BELT_CHUNK_SIZE));
+		let slice = belt.allocate(
+			wgpu::BufferSize::new(size).unwrap(),
+			wgpu::BufferSize::new(align as u64).unwrap(),
+			&painter.device,
+		);
+
+		{
+			let mut view = slice.get_mapped_range_mut();
+			let rows = view.chunks_mut(padded_bytes_per_row as usize);
+			let src_rows = bytes.chunks(copy.bytes_per_row as usize);
+			for (row, src) in rows.zip(src_rows).take(copy.extent.height as usize) {
+				row[..src.len()].copy_from_slice(src);
+			}
+		}
+
+		encoder.copy_buffer_to_texture(
+			wgpu::TexelCopyBufferInfo {
+				buffer: slice.buffer(),
+				layout: wgpu::TexelCopyBufferLayout {
+					offset: slice.offset(),
+					bytes_per_row: Some(padded_bytes_per_row),
+					rows_per_image: Some(copy.extent.height),
+				},
+			},
+			wgpu::TexelCopyTextureInfo {
+				texture: &painter.textures[self.0].texture,
+				mip_level: copy.mip_level,
+				origin: copy.origin,
+				aspect: wgpu::TextureAspect::All,
+			},
+			copy.extent,
+		);
+
+		painter.end_encoder(encoder);
+	}
+
+	/// Validates a region write, panics if it does not fit the texture.
+	fn region_copy<T: TexelComponent>(
+		&self,
+		painter: &Painter,
+		region: TextureRegion,
+		data: &[T],
+	) -> RegionCopy {
+		let texture = &painter.textures[self.0].texture;
+		let format = texture.format();
+
+		if !T::matches_format(format) {
+			panic!(
+				"Pixel data of {} can not be written to a texture of format {:?}",
+				std::any::type_name::<T>(),
+				format
+			);
+		}
+		let Some(bytes_per_pixel) = format.block_copy_size(None) else {
+			panic!(
+				"Texture format {:?} can not be written with pixel data",
+				format
+			);
+		};
+
+		if region.mip_level >= texture.mip_level_count() {
+			panic!(
+				"Mip level {} is out of range for a texture with {} mip levels",
+				region.mip_level,
+				texture.mip_level_count()
+			);
+		}
+		let level_size = texture
+			.size()
+			.mip_level_size(region.mip_level, texture.dimension());
+		if region.slice >= level_size.depth_or_array_layers {
+			panic!(
+				"Slice {} is out of range for a texture with {} slices",
+				region.slice, level_size.depth_or_array_layers
+			);
+		}
+
+		let (width, height) = region.size.unwrap_or((
+			level_size.width.saturating_sub(region.x),
+			level_size.height.saturating_sub(region.y),
+		));
+		if region.x + width > level_size.width || region.y + height > level_size.height {
+			panic!(
+				"Region of {}x{} at {},{} is out of bounds of mip level {} with size {}x{}",
+				width,
+				height,
+				region.x,
+				region.y,
+				region.mip_level,
+				level_size.width,
+				level_size.height
+			);
+		}
+
+		let bytes_per_row = width * bytes_per_pixel;
+		let size = bytes_per_row as usize * height as usize;
+		if std::mem::size_of_val(data) < size {
+			panic!(
+				"Pixel data of {} bytes is too small for a {}x{} region of {:?}, expected {} bytes",
+				std::mem::size_of_val(data),
+				width,
+				height,
+				format,
+				size
+			);
+		}
+
+		RegionCopy {
+			mip_level: region.mip_level,
+			origin: wgpu::Origin3d {
+				x: region.x,
+				y: region.y,
+				z: region.slice,
+			},
+			extent: wgpu::Extent3d {
+				width,
+				height,
+				depth_or_array_layers: 1,
+			},
+			bytes_per_row,
+		}
+	}
+
+	/// Frees the gpu texture. The handle must not be used afterwards.
 	pub fn destroy(self, painter: &mut Painter) {
-		let t = &mut painter.textures[self.0];
-		t.texture.destroy();
+		if let Some(t) = painter.textures.remove(self.0) {
+			t.texture.destroy();
+			painter.invalidate_layer_bind_groups(self);
+		}
 	}
 
 	pub(crate) fn prepare_view(&self, painter: &mut Painter, key: TexViewKey) {
-		let t = &painter.textures[self.0];
+		let t = &mut painter.textures[self.0];
 		if !t.views.contains_key(&key) {
-			let view = key.make_view(&t.texture);
-			painter.textures[self.0].views.insert(key, view);
+			t.prepare_view(key);
 		}
 	}
 
@@ -273,10 +686,6 @@ impl Texture {
 		painter.textures[self.0].views.get(key).unwrap()
 	}
 
-	pub(crate) fn source_view<'a>(&'a self, painter: &'a Painter) -> &'a wgpu::TextureView {
-		self.view(painter, &TexViewKey::WithAllMips)
-	}
-
 	pub(crate) fn target_view<'a>(&self, painter: &'a Painter) -> &'a wgpu::TextureView {
 		self.view(painter, &TexViewKey::AtMipLevel(0))
 	}
@@ -289,7 +698,419 @@ impl Texture {
 		}
 	}
 
+	pub(crate) fn encode_mips(&self, painter: &Painter, encoder: &mut wgpu::CommandEncoder) {
+		let t = &painter.textures[self.0].texture;
+		if t.mip_level_count() > 1 {
+			encode_mipmap_2d(painter, encoder, t);
+		}
+	}
+
+	/// Binds the texture in the `layers` slots of a shade, like a layer.
+	pub fn binding(&self) -> LayerBinding {
+		LayerBinding::Texture(*self)
+	}
+
 	pub fn get_mip_level_count(&self, painter: &Painter) -> u32 {
 		painter.textures[self.0].texture.mip_level_count()
 	}
+
+	pub fn format(&self, painter: &Painter) -> wgpu::TextureFormat {
+		painter.textures[self.0].texture.format()
+	}
+
+	pub fn kind(&self, painter: &Painter) -> TextureKind {
+		painter.textures[self.0].kind
+	}
+
+	/// Returns the width and height of the texture at the given mip level.
+	pub fn size_at_mip_level(&self, painter: &Painter, mip_level: u32) -> (u32, u32) {
+		let t = &painter.textures[self.0].texture;
+		let size = t.size().mip_level_size(mip_level, t.dimension());
+		(size.width, size.height)
+	}
+
+	/// Copies a mip level of the texture back to CPU memory.
+	///
+	/// The returned bytes are tightly packed rows in the texture's format,
+	/// the row padding required by the GPU copy is removed.
+	/// Of depth textures, only the depth aspect is read.
+	///
+	/// Blocks until the GPU has finished all submitted work.
+	/// Panics if the texture can not be read, see `try_read_pixels`.
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn read_pixels(&self, painter: &Painter, mip_level: u32) -> Vec<u8> {
+		self.try_read_pixels(painter, mip_level)
+			.unwrap_or_else(|err| panic!("{}", err))
+	}
+
+	/// Like `read_pixels`, but returns an error if the texture was not created with `COPY_SRC` usage,
+	/// is multisampled, has no such mip level or a format that can not be copied, like `Depth24Plus`.
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn try_read_pixels(
+		&self,
+		painter: &Painter,
+		mip_level: u32,
+	) -> Result<Vec<u8>, PainterError> {
+		let invalid = |message: String| Err(PainterError::InvalidProps(message));
+
+		let texture = &painter.textures[self.0].texture;
+		let format = texture.format();
+		if !texture.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+			return invalid(format!(
+				"Texture can not be read back without COPY_SRC usage, it has {:?}",
+				texture.usage()
+			));
+		}
+		if texture.sample_count() > 1 {
+			return invalid("Multisampled textures can not be read back".to_string());
+		}
+		if mip_level >= texture.mip_level_count() {
+			return invalid(format!(
+				"Mip level {} is out of range, the texture has {} mip levels",
+				mip_level,
+				texture.mip_level_count()
+			));
+		}
+		let aspect = if format.is_depth_stencil_format() {
+			wgpu::TextureAspect::DepthOnly
+		} else {
+			wgpu::TextureAspect::All
+		};
+		let Some(bytes_per_pixel) = format.block_copy_size(Some(aspect)) else {
+			return invalid(format!("Texture format {:?} can not be read back", format));
+		};
+		let (width, height) = self.size_at_mip_level(painter, mip_level);
+
+		let unpadded_bytes_per_row = width * bytes_per_pixel;
+		let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+		let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+
+		let buffer = painter.device.create_buffer(&wgpu::BufferDescriptor {
+			label: None,
+			size: (padded_bytes_per_row * height) as u64,
+			usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+			mapped_at_creation: false,
+		});
+
+		let mut encoder = painter
+			.device
+			.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+				label: Some("readback encoder"),
+			});
+
+		encoder.copy_texture_to_buffer(
+			wgpu::TexelCopyTextureInfo {
+				texture,
+				mip_level,
+				origin: wgpu::Origin3d::ZERO,
+				aspect,
+			},
+			wgpu::TexelCopyBufferInfo {
+				buffer: &buffer,
+				layout: wgpu::TexelCopyBufferLayout {
+					offset: 0,
+					bytes_per_row: Some(padded_bytes_per_row),
+					rows_per_image: Some(height),
+				},
+			},
+			wgpu::Extent3d {
+				width,
+				height,
+				depth_or_array_layers: 1,
+			},
+		);
+
+		painter.queue.submit(Some(encoder.finish()));
+
+		let slice = buffer.slice(..);
+		let (sender, receiver) = std::sync::mpsc::channel();
+		slice.map_async(wgpu::MapMode::Read, move |result| {
+			let _ = sender.send(result);
+		});
+		painter.device.poll(wgpu::PollType::Wait).unwrap();
+		receiver
+			.recv()
+			.unwrap()
+			.expect("Failed to map readback buffer");
+
+		let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+		{
+			let data = slice.get_mapped_range();
+			for row in data.chunks(padded_bytes_per_row as usize) {
+				pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+			}
+		}
+		buffer.unmap();
+
+		Ok(pixels)
+	}
+
+	/// Reads a mip level of the texture back and writes it as PNG file.
+	///
+	/// 8-bit formats are written as 8-bit PNG, 16-bit and float formats as 16-bit PNG.
+	/// Float values are clamped to 0..1, no transfer function is applied.
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn save_png(
+		&self,
+		painter: &Painter,
+		mip_level: u32,
+		path: impl AsRef<std::path::Path>,
+	) -> std::io::Result<()> {
+		let format = self.format(painter);
+		let (width, height) = self.size_at_mip_level(painter, mip_level);
+		let pixels = self.try_read_pixels(painter, mip_level).map_err(|err| {
+			std::io::Error::new(std::io::ErrorKind::InvalidInput, err.to_string())
+		})?;
+
+		let (color, depth, data) = pixels_to_png_data(format, pixels).ok_or_else(|| {
+			std::io::Error::new(
+				std::io::ErrorKind::Unsupported,
+				format!("Texture format {:?} can not be saved as PNG", format),
+			)
+		})?;
+
+		let file = std::fs::File::create(path)?;
+		let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), width, height);
+		encoder.set_color(color);
+		encoder.set_depth(depth);
+		let mut writer = encoder.write_header().map_err(std::io::Error::other)?;
+		writer
+			.write_image_data(&data)
+			.map_err(std::io::Error::other)?;
+		writer.finish().map_err(std::io::Error::other)
+	}
+}
+
+/// Encoded PNG image data, either in memory or as file path.
+#[derive(Clone, Copy)]
+pub enum PngSource<'a> {
+	Bytes(&'a [u8]),
+	Path(&'a std::path::Path),
+}
+
+impl<'a> From<&'a [u8]> for PngSource<'a> {
+	fn from(bytes: &'a [u8]) -> Self {
+		PngSource::Bytes(bytes)
+	}
+}
+
+impl<'a, const N: usize> From<&'a [u8; N]> for PngSource<'a> {
+	fn from(bytes: &'a [u8; N]) -> Self {
+		PngSource::Bytes(bytes)
+	}
+}
+
+impl<'a> From<&'a Vec<u8>> for PngSource<'a> {
+	fn from(bytes: &'a Vec<u8>) -> Self {
+		PngSource::Bytes(bytes)
+	}
+}
+
+impl<'a> From<&'a str> for PngSource<'a> {
+	fn from(path: &'a str) -> Self {
+		PngSource::Path(std::path::Path::new(path))
+	}
+}
+
+impl<'a> From<&'a std::path::Path> for PngSource<'a> {
+	fn from(path: &'a std::path::Path) -> Self {
+		PngSource::Path(path)
+	}
+}
+
+/// Builder for sampleable textures loaded from PNG images.
+///
+/// 8-bit images become `Rgba8UnormSrgb` textures, or `Rgba8Unorm` with `with_linear`.
+/// 16-bit images become `Rgba16Float` textures. Grayscale and images without alpha are expanded to RGBA.
+///
+/// # Example
+/// ```ignore
+/// let texture = painter
+///     .texture_from_png(include_bytes!("image.png"))
+///     .with_mips()
+///     .create();
+///
+/// let shape = painter
+///     .shape(form, shade)
+///     .with_layers(vec![(0, texture.binding())])
+///     .create();
+/// ```
+pub struct TextureBuilder<'a, 'b> {
+	painter: &'a mut Painter,
+	source: PngSource<'b>,
+	srgb: bool,
+	mips: Option<MipMapCount>,
+}
+
+impl<'a, 'b> TextureBuilder<'a, 'b> {
+	pub fn new(painter: &'a mut Painter, source: impl Into<PngSource<'b>>) -> Self {
+		TextureBuilder {
+			painter,
+			source: source.into(),
+			srgb: true,
+			mips: None,
+		}
+	}
+
+	pub fn create(self) -> Texture {
+		self.try_create().unwrap_or_else(|err| panic!("{}", err))
+	}
+
+	/// Like `create`, but returns an error if the file can not be read or decoded.
+	pub fn try_create(self) -> Result<Texture, PainterError> {
+		let data = match self.source {
+			PngSource::Bytes(bytes) => decode_png(bytes, self.srgb)?,
+			PngSource::Path(path) => {
+				let bytes = std::fs::read(path).map_err(|err| PainterError::read_file(path, err))?;
+				decode_png(&bytes, self.srgb)?
+			}
+		};
+
+		let texture = Texture::create(
+			self.painter,
+			data.width,
+			data.height,
+			TextureProps {
+				format: data.format,
+				mips: self.mips,
+				..default()
+			},
+			1,
+		);
+
+		texture.fill_2d(self.painter, &data.data);
+
+		Ok(texture)
+	}
+
+	/// Treats the image data as linear values instead of sRGB colors, e.g. for normal or height maps.
+	pub fn with_linear(mut self) -> Self {
+		self.srgb = false;
+		self
+	}
+
+	pub fn with_mips(mut self) -> Self {
+		self.mips = Some(MipMapCount::Full);
+		self
+	}
+
+	pub fn with_mips_max(mut self, max: u32) -> Self {
+		self.mips = Some(MipMapCount::Max(max));
+		self
+	}
+}
+
+/// Builder for sampleable textures filled with raw pixel data,
+/// e.g. 2D arrays, cubemaps or 3D volumes generated on the CPU.
+///
+/// The data contains all slices one after another in tightly packed rows of the texture format.
+///
+/// # Example
+/// ```ignore
+/// let volume = painter
+///     .texture(32, 32)
+///     .with_kind(TextureKind::D3(32))
+///     .with_format(wgpu::TextureFormat::R8Unorm)
+///     .with_data(&voxels)
+///     .create();
+///
+/// let shade = painter
+///     .shade_effect()
+///     .with_layers([BINDING_TEX3D_FRAG])
+///     .create();
+/// ```
+pub struct TextureDataBuilder<'a, 'b> {
+	painter: &'a mut Painter,
+	width: u32,
+	height: u32,
+	data: Option<&'b [u8]>,
+	props: TextureProps,
+}
+
+impl<'a, 'b> TextureDataBuilder<'a, 'b> {
+	pub fn new(painter: &'a mut Painter, width: u32, height: u32) -> Self {
+		TextureDataBuilder {
+			painter,
+			width,
+			height,
+			data: None,
+			props: TextureProps::default(),
+		}
+	}
+
+	pub fn create(self) -> Texture {
+		self.try_create().unwrap_or_else(|err| panic!("{}", err))
+	}
+
+	/// Like `create`, but returns an error for mipmaps of 3D textures,
+	/// or if the data does not cover all slices of the texture.
+	pub fn try_create(self) -> Result<Texture, PainterError> {
+		if self.props.mips.is_some() && matches!(self.props.kind, TextureKind::D3(_)) {
+			return Err(PainterError::InvalidProps(
+				"Mipmaps can not be generated for 3D textures".to_string(),
+			));
+		}
+
+		if let Some(data) = self.data {
+			let Some(bytes_per_pixel) = self.props.format.block_copy_size(None) else {
+				return Err(PainterError::InvalidProps(format!(
+					"Texture format {:?} can not be filled with pixel data",
+					self.props.format
+				)));
+			};
+			let size = self.width as usize
+				* self.height as usize
+				* self.props.kind.slice_count() as usize
+				* bytes_per_pixel as usize;
+			if data.len() < size {
+				return Err(PainterError::InvalidProps(format!(
+					"Texture data of {} bytes is too small for a {}x{} texture with {} slices of {:?}, expected {} bytes",
+					data.len(),
+					self.width,
+					self.height,
+					self.props.kind.slice_count(),
+					self.props.format,
+					size
+				)));
+			}
+		}
+
+		let texture = Texture::create(self.painter, self.width, self.height, self.props, 1);
+
+		if let Some(data) = self.data {
+			texture.fill_2d(self.painter, data);
+		}
+
+		Ok(texture)
+	}
+
+	pub fn with_kind(mut self, kind: TextureKind) -> Self {
+		self.props.kind = kind;
+		self
+	}
+
+	pub fn with_format(mut self, format: wgpu::TextureFormat) -> Self {
+		self.props.format = format;
+		self
+	}
+
+	/// The pixel data of all slices, can also be written later with `Texture::fill_2d` or `Texture::fill_slice`.
+	pub fn with_data(mut self, data: &'b [u8]) -> Self {
+		self.data = Some(data);
+		self
+	}
+
+	/// Generates mipmaps for each slice. Not supported for 3D textures.
+	///
+	/// The GL backend has no real texture views and can't sample single slices,
+	/// so mipmaps of arrays and cubemaps stay empty there.
+	pub fn with_mips(mut self) -> Self {
+		self.props.mips = Some(MipMapCount::Full);
+		self
+	}
+
+	pub fn with_mips_max(mut self, max: u32) -> Self {
+		self.props.mips = Some(MipMapCount::Max(max));
+		self
+	}
 }
@@ -1,4 +1,10 @@
-use crate::{painter::FULL_SCREEN_TEXTURE_PIPELINE, Painter};
+use crate::{
+	bind_group::BindGroup,
+	binding::texture_sample_type,
+	painter::{FULL_SCREEN_TEXTURE_PIPELINE, ShowTransfer},
+	pipeline::PipelineStorage,
+	Painter,
+};
 use trivalibs_core::utils::default;
 use wgpu::StoreOp;
 
@@ -8,6 +14,42 @@ pub fn num_mip_levels(size: wgpu::Extent3d) -> u32 {
 	1 + (max_size as f32).log2().floor() as u32
 }
 
+/// Whether textures of the format can be sampled with filtering samplers.
+pub(crate) fn is_float_filterable(format: wgpu::TextureFormat) -> bool {
+	texture_sample_type(format) == wgpu::TextureSampleType::Float { filterable: true }
+}
+
+fn mipmap_pipeline_key(format: wgpu::TextureFormat) -> Vec<u8> {
+	vec![0xff, 0xfe, map_format_to_u8(format)]
+}
+
+/// Makes sure a mipmap pipeline exists for textures of the given format.
+/// The surface format uses the fullscreen texture pipeline, unless it applies a transfer function.
+/// Unfilterable formats are downsampled with a nearest sampler.
+pub(crate) fn ensure_mipmap_pipeline(painter: &mut Painter, format: wgpu::TextureFormat) {
+	let key = mipmap_pipeline_key(format);
+	let is_show_pipeline =
+		format == painter.config.format && painter.show_transfer == ShowTransfer::None;
+	if is_show_pipeline || painter.pipelines.contains_key(&key) {
+		return;
+	}
+
+	let pipeline = painter.create_fullscreen_texture_pipeline(
+		format,
+		is_float_filterable(format),
+		ShowTransfer::None,
+	);
+	painter.pipelines.insert(
+		key,
+		PipelineStorage {
+			pipeline,
+			layer: None,
+			shape: None,
+			effect: None,
+		},
+	);
+}
+
 pub fn generate_mipmap_2d(painter: &Painter, texture: &wgpu::Texture) {
 	let mut encoder = painter
 		.device
@@ -15,12 +57,28 @@ pub fn generate_mipmap_2d(painter: &Painter, texture: &wgpu::Texture) {
 			label: Some("mip gen encoder"),
 		});
 
-	for base_mip_level in 1..texture.mip_level_count() {
+	encode_mipmap_2d(painter, &mut encoder, texture);
+
+	painter.queue.submit(Some(encoder.finish()));
+}
+
+/// Records the mipmap generation passes into the given encoder.
+pub(crate) fn encode_mipmap_2d(
+	painter: &Painter,
+	encoder: &mut wgpu::CommandEncoder,
+	texture: &wgpu::Texture,
+) {
+	// Each array layer or cube face gets its own mip chain
+	for (base_array_layer, base_mip_level) in (0..texture.depth_or_array_layers())
+		.flat_map(|layer| (1..texture.mip_level_count()).map(move |mip| (layer, mip)))
+	{
 		let src_view = texture.create_view(&wgpu::TextureViewDescriptor {
 			label: None,
 			dimension: Some(wgpu::TextureViewDimension::D2),
 			base_mip_level: (base_mip_level - 1),
 			mip_level_count: Some(1),
+			base_array_layer,
+			array_layer_count: Some(1),
 			..default()
 		});
 
@@ -29,10 +87,20 @@ pub fn generate_mipmap_2d(painter: &Painter, texture: &wgpu::Texture) {
 			dimension: Some(wgpu::TextureViewDimension::D2),
 			base_mip_level,
 			mip_level_count: Some(1),
+			base_array_layer,
+			array_layer_count: Some(1),
 			..default()
 		});
 
-		let pipeline = &painter.pipelines[FULL_SCREEN_TEXTURE_PIPELINE];
+		let pipeline = painter
+			.pipelines
+			.get(&mipmap_pipeline_key(texture.format()))
+			.unwrap_or(&painter.pipelines[FULL_SCREEN_TEXTURE_PIPELINE]);
+		let sampler = if is_float_filterable(texture.format()) {
+			BindGroup::LINEAR_SAMPLER
+		} else {
+			BindGroup::UNFILTERED_SAMPLER
+		};
 
 		let src_binding = painter
 			.device
@@ -61,14 +129,150 @@ pub fn generate_mipmap_2d(painter: &Painter, texture: &wgpu::Texture) {
 			});
 
 			pass.set_pipeline(&pipeline.pipeline);
-			pass.set_bind_group(0, &painter.bind_groups[1].bind_group, &[]);
+			pass.set_bind_group(0, &painter.bind_groups[sampler.0].bind_group, &[]);
 			pass.set_bind_group(1, &src_binding, &[]);
 
 			pass.draw(0..3, 0..1); // Assuming a fullscreen quad
 		}
 	}
+}
+
+/// Converts tightly packed pixels of the given format into PNG color type, bit depth and data.
+/// Returns `None` if the format has no PNG representation.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn pixels_to_png_data(
+	format: wgpu::TextureFormat,
+	pixels: Vec<u8>,
+) -> Option<(png::ColorType, png::BitDepth, Vec<u8>)> {
+	use png::{BitDepth, ColorType};
+	use wgpu::TextureFormat as F;
+
+	// PNG stores 16-bit samples big endian
+	fn unorm_to_png16(v: f32) -> [u8; 2] {
+		((v.clamp(0.0, 1.0) * 65535.0).round() as u16).to_be_bytes()
+	}
+
+	let f16_to_png16 = |pixels: &[u8]| {
+		pixels
+			.chunks_exact(2)
+			.flat_map(|c| unorm_to_png16(half::f16::from_le_bytes([c[0], c[1]]).to_f32()))
+			.collect::<Vec<_>>()
+	};
+
+	let f32_to_png16 = |pixels: &[u8]| {
+		pixels
+			.chunks_exact(4)
+			.flat_map(|c| unorm_to_png16(f32::from_le_bytes([c[0], c[1], c[2], c[3]])))
+			.collect::<Vec<_>>()
+	};
+
+	let u16_to_png16 = |pixels: &[u8]| {
+		pixels
+			.chunks_exact(2)
+			.flat_map(|c| [c[1], c[0]])
+			.collect::<Vec<_>>()
+	};
+
+	match format {
+		F::Rgba8Unorm | F::Rgba8UnormSrgb => Some((ColorType::Rgba, BitDepth::Eight, pixels)),
+		F::Bgra8Unorm | F::Bgra8UnormSrgb => {
+			let mut pixels = pixels;
+			for p in pixels.chunks_exact_mut(4) {
+				p.swap(0, 2);
+			}
+			Some((ColorType::Rgba, BitDepth::Eight, pixels))
+		}
+		F::R8Unorm => Some((ColorType::Grayscale, BitDepth::Eight, pixels)),
+		F::Rgba16Unorm => Some((ColorType::Rgba, BitDepth::Sixteen, u16_to_png16(&pixels))),
+		F::R16Unorm => Some((ColorType::Grayscale, BitDepth::Sixteen, u16_to_png16(&pixels))),
+		F::Rgba16Float => Some((ColorType::Rgba, BitDepth::Sixteen, f16_to_png16(&pixels))),
+		F::R16Float => Some((ColorType::Grayscale, BitDepth::Sixteen, f16_to_png16(&pixels))),
+		F::Rgba32Float => Some((ColorType::Rgba, BitDepth::Sixteen, f32_to_png16(&pixels))),
+		F::R32Float => Some((ColorType::Grayscale, BitDepth::Sixteen, f32_to_png16(&pixels))),
+		_ => None,
+	}
+}
 
-	painter.queue.submit(Some(encoder.finish()));
+/// PNG pixels converted to RGBA, ready to be uploaded into a texture of `format`.
+pub(crate) struct PngTextureData {
+	pub width: u32,
+	pub height: u32,
+	pub format: wgpu::TextureFormat,
+	pub data: Vec<u8>,
+}
+
+fn expand_to_rgba<T: Copy>(samples: &[T], channels: usize, opaque: T) -> Vec<T> {
+	samples
+		.chunks_exact(channels)
+		.flat_map(|p| match channels {
+			1 => [p[0], p[0], p[0], opaque],
+			2 => [p[0], p[0], p[0], p[1]],
+			3 => [p[0], p[1], p[2], opaque],
+			_ => [p[0], p[1], p[2], p[3]],
+		})
+		.collect()
+}
+
+fn srgb_to_linear(v: f32) -> f32 {
+	if v <= 0.04045 {
+		v / 12.92
+	} else {
+		((v + 0.055) / 1.055).powf(2.4)
+	}
+}
+
+/// Decodes a PNG image of any color type into RGBA pixels.
+///
+/// 8-bit images are stored as `Rgba8UnormSrgb` or `Rgba8Unorm`, depending on `srgb`.
+/// 16-bit images are stored as `Rgba16Float` to keep their precision,
+/// with sRGB colors converted to linear on the CPU.
+pub(crate) fn decode_png(bytes: &[u8], srgb: bool) -> Result<PngTextureData, png::DecodingError> {
+	let mut decoder = png::Decoder::new(std::io::Cursor::new(bytes));
+	// Expand palettes, low bit depths and tRNS chunks to plain 8-bit or 16-bit samples
+	decoder.set_transformations(png::Transformations::EXPAND);
+
+	let mut reader = decoder.read_info()?;
+	let buffer_size = reader
+		.output_buffer_size()
+		.ok_or(png::DecodingError::LimitsExceeded)?;
+	let mut buf = vec![0; buffer_size];
+	let info = reader.next_frame(&mut buf)?;
+	buf.truncate(info.buffer_size());
+
+	let channels = info.color_type.samples();
+
+	let (format, data) = if info.bit_depth == png::BitDepth::Sixteen {
+		let samples = buf
+			.chunks_exact(2)
+			.map(|c| u16::from_be_bytes([c[0], c[1]]) as f32 / 65535.0)
+			.collect::<Vec<_>>();
+
+		let data = expand_to_rgba(&samples, channels, 1.0)
+			.chunks_exact(4)
+			.flat_map(|p| {
+				let rgb = |v: f32| if srgb { srgb_to_linear(v) } else { v };
+				[rgb(p[0]), rgb(p[1]), rgb(p[2]), p[3]]
+			})
+			.flat_map(|v| half::f16::from_f32(v).to_le_bytes())
+			.collect::<Vec<_>>();
+
+		(wgpu::TextureFormat::Rgba16Float, data)
+	} else {
+		let format = if srgb {
+			wgpu::TextureFormat::Rgba8UnormSrgb
+		} else {
+			wgpu::TextureFormat::Rgba8Unorm
+		};
+
+		(format, expand_to_rgba(&buf, channels, 255))
+	};
+
+	Ok(PngTextureData {
+		width: info.width,
+		height: info.height,
+		format,
+		data,
+	})
 }
 
 pub(crate) fn map_format_to_u8(format: wgpu::TextureFormat) -> u8 {
@@ -154,3 +358,48 @@ pub(crate) fn map_format_to_u8(format: wgpu::TextureFormat) -> u8 {
 		wgpu::TextureFormat::R64Uint => 76,
 	}
 }
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn png_data_swizzles_bgra() {
+		let pixels = vec![1, 2, 3, 4, 5, 6, 7, 8];
+		let (color, depth, data) =
+			pixels_to_png_data(wgpu::TextureFormat::Bgra8UnormSrgb, pixels).unwrap();
+
+		assert_eq!(color, png::ColorType::Rgba);
+		assert_eq!(depth, png::BitDepth::Eight);
+		assert_eq!(data, vec![3, 2, 1, 4, 7, 6, 5, 8]);
+	}
+
+	#[test]
+	fn png_data_converts_f16_to_big_endian_u16() {
+		let pixels = [0.0, 1.0, 0.5, 2.0]
+			.into_iter()
+			.flat_map(|v: f32| half::f16::from_f32(v).to_le_bytes())
+			.collect::<Vec<_>>();
+		let (color, depth, data) =
+			pixels_to_png_data(wgpu::TextureFormat::Rgba16Float, pixels).unwrap();
+
+		assert_eq!(color, png::ColorType::Rgba);
+		assert_eq!(depth, png::BitDepth::Sixteen);
+		// Values are clamped to the unorm range
+		assert_eq!(data, vec![0, 0, 255, 255, 128, 0, 255, 255]);
+	}
+
+	#[test]
+	fn png_data_swaps_u16_byte_order() {
+		let pixels = vec![0x34, 0x12, 0xff, 0x00];
+		let (color, _, data) = pixels_to_png_data(wgpu::TextureFormat::R16Unorm, pixels).unwrap();
+
+		assert_eq!(color, png::ColorType::Grayscale);
+		assert_eq!(data, vec![0x12, 0x34, 0x00, 0xff]);
+	}
+
+	#[test]
+	fn png_data_rejects_unsupported_formats() {
+		assert!(pixels_to_png_data(wgpu::TextureFormat::Rgba8Uint, vec![0; 4]).is_none());
+	}
+}
@@ -213,7 +213,7 @@ impl CanvasApp for App {
 	}
 
 	fn frame(&mut self, p: &mut Painter, _tpf: f32) {
-		let frame = p.surface.get_current_texture().unwrap();
+		let frame = p.surface.as_ref().unwrap().get_current_texture().unwrap();
 
 		let view = frame
 			.texture
@@ -69,7 +69,12 @@ impl CanvasApp<UserEvent> for App {
 	}
 
 	fn frame(&mut self, painter: &mut Painter, _tpf: f32) {
-		let frame = painter.surface.get_current_texture().unwrap();
+		let frame = painter
+			.surface
+			.as_ref()
+			.unwrap()
+			.get_current_texture()
+			.unwrap();
 
 		let view = frame
 			.texture